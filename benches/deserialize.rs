@@ -0,0 +1,115 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+use serde_mml::de::Deserializer;
+use serde_mml::md::Reader;
+
+#[derive(Serialize, Deserialize)]
+struct Point {
+    x: i64,
+    y: i64,
+    label: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    width: u32,
+    height: u32,
+    fullscreen: bool,
+    vsync: bool,
+    brightness: u8,
+    contrast: u8,
+    volume: u8,
+    muted: bool,
+    language: String,
+    theme: String,
+    font_size: u16,
+    autosave: bool,
+}
+
+/// Deserialize the same document this many times in each iteration
+const TIMES: usize = 10;
+
+fn bench_reuse(c: &mut Criterion) {
+    let points = (0..10_000)
+        .map(|n| Point {
+            x: n,
+            y: -n,
+            label: format!("point {}", n),
+        })
+        .collect::<Vec<_>>();
+    let doc = serde_mml::ser::to_string(&points).unwrap();
+
+    c.bench_function("10x Vec<Point> of 10K, reparsed", |b| {
+        b.iter(|| {
+            for _ in 0..TIMES {
+                let mut de = Deserializer::new(black_box(&doc));
+                black_box(Vec::<Point>::deserialize(&mut de).unwrap());
+            }
+        })
+    });
+
+    c.bench_function("10x Vec<Point> of 10K, tokenized once", |b| {
+        b.iter(|| {
+            let items = Reader::tokenize(black_box(&doc)).unwrap();
+            for _ in 0..TIMES {
+                let mut de = Deserializer::new_unbuffered(&items);
+                black_box(Vec::<Point>::deserialize(&mut de).unwrap());
+            }
+        })
+    });
+}
+
+fn bench_fields(c: &mut Criterion) {
+    let settings = (0..1_000)
+        .map(|n| Settings {
+            width: 1920,
+            height: 1080,
+            fullscreen: n % 2 == 0,
+            vsync: true,
+            brightness: 50,
+            contrast: 50,
+            volume: 80,
+            muted: false,
+            language: "en".to_owned(),
+            theme: "dark".to_owned(),
+            font_size: 12,
+            autosave: true,
+        })
+        .collect::<Vec<_>>();
+    let doc = serde_mml::ser::to_string(&settings).unwrap();
+    let items = Reader::tokenize(&doc).unwrap();
+
+    c.bench_function("Vec<Settings> of 1K, tokenized", |b| {
+        b.iter(|| {
+            let mut de = Deserializer::new_unbuffered(black_box(&items));
+            black_box(Vec::<Settings>::deserialize(&mut de).unwrap());
+        })
+    });
+}
+
+fn bench_bytes(c: &mut Criterion) {
+    let bytes = (0..100_000).map(|n| n as u8).collect::<Vec<u8>>();
+    let seq = serde_mml::ser::to_string(&bytes).unwrap();
+    let base64 = serde_mml::ser::to_string(serde_bytes::Bytes::new(&bytes)).unwrap();
+
+    c.bench_function("Vec<u8> of 100K, as a seq", |b| {
+        b.iter(|| {
+            let mut de = Deserializer::new(black_box(&seq));
+            black_box(Vec::<u8>::deserialize(&mut de).unwrap());
+        })
+    });
+
+    c.bench_function("Vec<u8> of 100K, with serde_bytes", |b| {
+        b.iter(|| {
+            let mut de = Deserializer::new(black_box(&base64));
+            black_box(serde_bytes::ByteBuf::deserialize(&mut de).unwrap());
+        })
+    });
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_reuse, bench_fields, bench_bytes
+}
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Serialize;
+use serde_mml::ser::Serializer;
+
+fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    value.serialize(&mut Serializer::new(&mut buf)).unwrap();
+    buf
+}
+
+fn bench_primitives(c: &mut Criterion) {
+    let ints = (0..1_000_000u64).collect::<Vec<_>>();
+    c.bench_function("Vec<u64> of 1M", |b| b.iter(|| serialize(black_box(&ints))));
+
+    let floats = (0..1_000_000).map(|n| n as f64 / 7.0).collect::<Vec<_>>();
+    c.bench_function("Vec<f64> of 1M", |b| {
+        b.iter(|| serialize(black_box(&floats)))
+    });
+
+    let strings = (0..100_000)
+        .map(|n| format!("item {}", n))
+        .collect::<Vec<_>>();
+    c.bench_function("Vec<String> of 100K", |b| {
+        b.iter(|| serialize(black_box(&strings)))
+    });
+}
+
+fn bench_maps(c: &mut Criterion) {
+    // Each size is 10 times the last, so the times should be too
+    for &len in &[10_000u32, 100_000] {
+        let flat = (0..len).map(|n| (n, n)).collect::<BTreeMap<_, _>>();
+        c.bench_function(&format!("BTreeMap<u32, u32> of {}K", len / 1000), |b| {
+            b.iter(|| serialize(black_box(&flat)))
+        });
+
+        let nested = (0..len / 100)
+            .map(|n| (n, (0..100).map(|m| (m, n)).collect::<BTreeMap<_, _>>()))
+            .collect::<BTreeMap<_, _>>();
+        c.bench_function(
+            &format!("BTreeMap<u32, BTreeMap<u32, u32>> of {}K", len / 1000),
+            |b| b.iter(|| serialize(black_box(&nested))),
+        );
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_primitives, bench_maps
+}
+criterion_main!(benches);
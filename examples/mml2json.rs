@@ -0,0 +1,10 @@
+//! Transcode an MML document on stdin to JSON on stdout, the reverse of the `serde-mml` binary
+use std::io::{self, Read};
+
+fn main() {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).unwrap();
+
+    let mut serializer = serde_json::Serializer::new(io::stdout());
+    serde_mml::transcode_from_mml(&input, &mut serializer).unwrap();
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Any input must either deserialize or fail with an error, but never panic
+fuzz_target!(|text: &str| {
+    let _ = serde_mml::de::from_str::<serde_value::Value>(text);
+});
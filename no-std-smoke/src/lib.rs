@@ -0,0 +1,44 @@
+//! Makes sure serde-mml builds and works without `std`
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+
+use serde::Serialize;
+use serde_mml::md::FmtOutput;
+use serde_mml::ser::Serializer;
+
+#[derive(Serialize)]
+pub struct Reading {
+    pub sensor: &'static str,
+    pub celsius: i16,
+    pub ok: bool,
+}
+
+/// Serialize a reading straight into a `String`
+pub fn render(reading: &Reading) -> Result<String, serde_mml::Error> {
+    let mut output = String::new();
+    reading.serialize(&mut Serializer::new(FmtOutput(&mut output)))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let reading = Reading {
+            sensor: "probe-1",
+            celsius: -4,
+            ok: true,
+        };
+
+        let doc = render(&reading).unwrap();
+        assert!(doc.starts_with("* [Struct Reading of length 3](serde://struct/Reading/3)\n"));
+        assert!(doc.contains("[probe\\-1](serde://string)\n"));
+        assert!(doc.contains("[\\-4](serde://i16)\n"));
+        assert_eq!(serde_mml::ser::to_string(&reading).unwrap(), doc);
+    }
+}
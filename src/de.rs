@@ -1,25 +1,541 @@
-use std::borrow::Cow;
-use std::iter::Peekable;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::{iter, slice};
 
 use serde::de::{self, IntoDeserializer};
 
 use crate::error::{Error, Result};
 use crate::md::{Item, Reader};
-use crate::ty::Type;
+use crate::ty::{self, Type};
 
-pub struct Deserializer<'de> {
-    reader: Peekable<Reader<'de>>,
+/// How deeply lists may be nested before deserializing fails, to avoid overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+/// How much to pre-allocate for a declared length by default, as it may not be honest
+const MAX_PREALLOCATION: usize = 4096;
+
+/// A callback for errors which were skipped over, shared between clones of a deserializer
+type Recovery = Rc<RefCell<dyn FnMut(Error)>>;
+
+/// Deserializes values from MML, by default parsing it from a string with a `Reader`
+///
+/// Cloning a deserializer snapshots its position, e.g. to try deserializing the rest of the
+/// document as one type and then as another. Clones share the `with_recovery` callback.
+#[derive(Clone)]
+pub struct Deserializer<'de, R = Reader<'de>>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    reader: Lookahead<R>,
+    recovery: Option<Recovery>,
+    scheme: &'static str,
+    minimize_int_widths: bool,
+    lenient_bools: bool,
+    lenient_options: bool,
+    bare_uris: bool,
+    max_preallocation: usize,
+
+    /// The text being parsed, if we know it, for pointing out where errors happened
+    source: Option<&'de str>,
+
+    /// What was read last, or a link whose type URI failed to parse while looking ahead, for
+    /// pointing out where errors happened
+    last_read: Cell<Option<LastRead<'de>>>,
+
+    /// How many more lists deep we may go before giving up
+    remaining_depth: usize,
+}
+
+/// What a deserializer read last, which is usually what an error is about
+#[derive(Clone, Copy)]
+enum LastRead<'de> {
+    /// A link, by its URI as a slice of the source
+    Link(&'de str),
+
+    /// An error from the reader, which never reads past its first one
+    Error,
+}
+
+/// Like `Peekable`, but able to look further than just the next item
+struct Lookahead<R: Iterator> {
+    inner: R,
+    buffer: VecDeque<R::Item>,
+}
+
+impl<R> Clone for Lookahead<R>
+where
+    R: Iterator + Clone,
+    R::Item: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            buffer: self.buffer.clone(),
+        }
+    }
+}
+
+impl<R: Iterator> Lookahead<R> {
+    fn next(&mut self) -> Option<R::Item> {
+        self.buffer.pop_front().or_else(|| self.inner.next())
+    }
+
+    fn peek(&mut self) -> Option<&R::Item> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<&R::Item> {
+        while self.buffer.len() <= n {
+            let item = self.inner.next()?;
+            self.buffer.push_back(item);
+        }
+        self.buffer.get(n)
+    }
+}
+
+/// A stream of items which have already been parsed, and as such can't fail
+#[derive(Clone)]
+pub struct Items<I>(I);
+
+impl<'de, I> Iterator for Items<I>
+where
+    I: Iterator<Item = Item<'de>>,
+{
+    type Item = Result<Item<'de>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Ok)
+    }
 }
 
 impl<'de> Deserializer<'de> {
     pub fn new(text: &'de str) -> Self {
+        let mut deserializer = Self::from_reader(Reader::new(text));
+        deserializer.source = Some(text);
+        deserializer
+    }
+
+    /// The input which hasn't been deserialized yet, e.g. the documents after the one which was
+    /// just deserialized
+    ///
+    /// This is `None` if the deserializer has already looked at what comes next, such as after
+    /// `peek_type` or when skipping malformed items with `with_recovery`.
+    pub fn remaining(&self) -> Option<&'de str> {
+        if self.reader.buffer.is_empty() {
+            Some(self.reader.inner.remaining())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'de, I> Deserializer<'de, Items<I>>
+where
+    I: Iterator<Item = Item<'de>>,
+{
+    /// Deserialize from an already parsed (or never rendered) stream of items
+    pub fn from_items<T>(items: T) -> Self
+    where
+        T: IntoIterator<Item = Item<'de>, IntoIter = I>,
+    {
+        Self::from_reader(Items(items.into_iter()))
+    }
+}
+
+impl<'de, 'a> Deserializer<'de, Items<iter::Cloned<slice::Iter<'a, Item<'de>>>>> {
+    /// Deserialize from items parsed beforehand with `Reader::tokenize`, without consuming them
+    ///
+    /// This saves parsing the document again when deserializing it more than once, e.g. into
+    /// different types.
+    pub fn new_unbuffered(items: &'a [Item<'de>]) -> Self {
+        Self::from_items(items.iter().cloned())
+    }
+}
+
+impl<'de, R> Deserializer<'de, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    fn from_reader(reader: R) -> Self {
         Self {
-            reader: Reader::new(text).peekable(),
+            reader: Lookahead {
+                inner: reader,
+                buffer: VecDeque::new(),
+            },
+            recovery: None,
+            scheme: ty::DEFAULT_SCHEME,
+            minimize_int_widths: false,
+            lenient_bools: false,
+            lenient_options: false,
+            bare_uris: false,
+            max_preallocation: MAX_PREALLOCATION,
+            source: None,
+            last_read: Cell::new(None),
+            remaining_depth: MAX_DEPTH,
+        }
+    }
+
+    /// Visit integers as the smallest type of the same signedness which can hold them
+    ///
+    /// This is meant for canonicalizing untyped values such as `serde_value::Value`, and as
+    /// such it loses the original width of the integers
+    pub fn with_minimize_int_widths(mut self, minimize_int_widths: bool) -> Self {
+        self.minimize_int_widths = minimize_int_widths;
+        self
+    }
+
+    /// Also accept `1`/`0` and `yes`/`no` as booleans, in any case, for documents from lenient
+    /// producers or people
+    pub fn with_lenient_bools(mut self, lenient_bools: bool) -> Self {
+        self.lenient_bools = lenient_bools;
+        self
+    }
+
+    /// Take a left out value, a link with blank text or the text `none` in any case, or a
+    /// `none` URI without a scheme, as `None` when deserializing an `Option`
+    ///
+    /// Any other value is then taken as the `Some`, even without a `serde://some` list around it.
+    pub fn with_lenient_options(mut self, lenient_options: bool) -> Self {
+        self.lenient_options = lenient_options;
+        self
+    }
+
+    /// Expect `scheme` instead of `serde` in the type URIs
+    pub fn with_scheme(mut self, scheme: &'static str) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Also accept type URIs without a scheme, e.g. `[true](bool)`, as people are likely to
+    /// write when using MML as just another Markdown document
+    pub fn with_bare_uris(mut self, bare_uris: bool) -> Self {
+        self.bare_uris = bare_uris;
+        self
+    }
+
+    /// Trust declared lengths of sequences, maps and bytes only up to `max_preallocation` when
+    /// pre-allocating for them, so a huge length can't exhaust memory before any data arrives
+    ///
+    /// Longer values still deserialize fine, growing as their elements are read
+    pub fn with_max_preallocation(mut self, max_preallocation: usize) -> Self {
+        self.max_preallocation = max_preallocation;
+        self
+    }
+
+    /// Clamp a declared length before it's used to pre-allocate anything
+    fn cautious(&self, len: usize) -> usize {
+        len.min(self.max_preallocation)
+    }
+
+    fn parse_type(&self, uri: &'de str) -> Result<Type<'de>> {
+        let ty = parse_type(uri, self.scheme, self.bare_uris);
+        if ty.is_err() {
+            self.last_read.set(Some(LastRead::Link(uri)));
+        }
+        ty
+    }
+
+    /// Skip malformed links inside of sequences and maps instead of failing
+    ///
+    /// Each skipped item's error is passed to `callback`. A list whose header is malformed is
+    /// skipped along with whatever is nested in it, and a malformed map key or value skips its
+    /// entire entry, as does an entry which isn't an ordered list of a key and a value
+    pub fn with_recovery<F: FnMut(Error) + 'static>(mut self, callback: F) -> Self {
+        self.recovery = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Get the next item, if there's any left
+    fn next_opt(&mut self) -> Result<Option<Item<'de>>> {
+        let item = self.reader.next().transpose();
+        match item {
+            Ok(Some(Item::Link { uri, .. })) => self.last_read.set(Some(LastRead::Link(uri))),
+            Err(_) => self.last_read.set(Some(LastRead::Error)),
+            _ => {}
+        }
+        item
+    }
+
+    /// Point out where in the document `error` happened, as best as we can tell
+    ///
+    /// This wraps `error` in an `Error::Located`, whose message has an excerpt of the line with
+    /// the last link read underlined, which is usually the one the error is about: its text if
+    /// the text couldn't be parsed, and its URI otherwise. Errors from reading the document point
+    /// at where reading stopped instead. `from_str` does this by itself; errors are returned as
+    /// they are if the deserializer wasn't made from a string with `new`, or if there's been no
+    /// link yet.
+    pub fn locate(&self, error: Error) -> Error {
+        let (source, last_read) = match (self.source, self.last_read.get()) {
+            (Some(source), Some(last_read)) => (source, last_read),
+            _ => return error,
+        };
+
+        // The reader only ever gives out URIs which are slices of the source
+        let offset = |s: &str| (s.as_ptr() as usize).wrapping_sub(source.as_ptr() as usize);
+        match last_read {
+            LastRead::Link(uri) => {
+                let uri_offset = offset(uri);
+                if uri_offset > source.len() || !source.is_char_boundary(uri_offset) {
+                    return error;
+                }
+                match link_text(source, uri_offset) {
+                    Some(text) if is_text_error(&error) => {
+                        error.located(source, offset(text), text.len())
+                    }
+                    _ => error.located(source, uri_offset, uri.len()),
+                }
+            }
+            LastRead::Error => {
+                // Lookahead may have read past the error, but reading again stops right at it
+                let mut reader = Reader::new(source);
+                while let Some(Ok(_)) = reader.next() {}
+                error.located(source, offset(reader.remaining()), 1)
+            }
+        }
+    }
+
+    /// Get the next item, which must be there
+    fn next_item(&mut self) -> Result<Item<'de>> {
+        self.next_opt()?.ok_or(Error::UnexpectedEOF)
+    }
+
+    /// The part of `uri` after the scheme, without parsing it into a `Type`
+    fn uri_path<'u>(&self, uri: &'u str) -> Option<&'u str> {
+        match uri.strip_prefix(self.scheme) {
+            Some(rest) => rest.strip_prefix("://"),
+            None if self.bare_uris && !uri.contains("://") => Some(uri),
+            None => None,
+        }
+    }
+
+    /// Check whether `uri` is the string type without parsing it into a `Type`
+    fn is_string_uri(&self, uri: &str) -> bool {
+        self.uri_path(uri) == Some("string")
+    }
+
+    /// Look at the next item without consuming it, unless it's an error
+    fn peek_item(&mut self) -> Result<Option<&Item<'de>>> {
+        if let Some(Err(_)) = self.reader.peek() {
+            return Err(self.next_item().unwrap_err());
+        }
+        Ok(self.reader.peek().and_then(|item| item.as_ref().ok()))
+    }
+
+    /// Fail up front if the next value is a list with a declared length other than `len`, as
+    /// tuples can't make do with fewer elements and would silently lose any extra ones
+    fn check_tuple_len(&mut self, len: usize, expected: &dyn de::Expected) -> Result<()> {
+        match self.peek_type()? {
+            Some(Type::Seq(Some(declared)))
+            | Some(Type::Tuple(declared))
+            | Some(Type::TupleStruct(_, declared))
+                if declared != len =>
+            {
+                Err(de::Error::invalid_length(declared, expected))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Start on a seq without deserializing it, to pull its elements out one at a time with
+    /// `next_element` instead, e.g. to process a huge one without collecting it into a `Vec`
+    ///
+    /// Tuples are accepted as well. The end of the seq is consumed once `next_element` returns
+    /// `None`, after which the deserializer carries on with whatever comes next; stopping before
+    /// that leaves the rest of the seq to be read.
+    ///
+    /// ```
+    /// use serde::de::SeqAccess;
+    ///
+    /// # fn main() -> serde_mml::Result<()> {
+    /// let doc = serde_mml::ser::to_string(&(1..=100u32).collect::<Vec<_>>())?;
+    /// let mut de = serde_mml::de::Deserializer::new(&doc);
+    /// let mut seq = de.seq_access()?;
+    /// let mut sum = 0;
+    /// while let Some(n) = seq.next_element::<u32>()? {
+    ///     sum += n;
+    /// }
+    /// assert_eq!(sum, 5050);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn seq_access(&mut self) -> Result<SeqDeserializer<'de, '_, R>> {
+        match self.next_item()? {
+            Item::PushOrderedList => {}
+            _ => return Err(Error::UnexpectedItem("a seq")),
+        }
+
+        let uri = self.header()?;
+        let len = match self.parse_type(uri)? {
+            Type::Seq(len) => len,
+            Type::Tuple(len) | Type::TupleStruct(_, len) => Some(len),
+            _ => {
+                return Err(de::Error::invalid_type(
+                    de::Unexpected::Other(uri),
+                    &"a seq",
+                ))
+            }
+        };
+
+        Ok(SeqDeserializer {
+            deserializer: self,
+            len,
+            done: false,
+        })
+    }
+
+    /// Find out the type of the next value without consuming it
+    ///
+    /// This only looks ahead as far as the value's first link, i.e. its own link for primitives
+    /// and the header for lists, so e.g. a seq's elements can't be inspected before visiting it.
+    /// `None` is returned when the current list has ended, or there's no input left.
+    pub fn peek_type(&mut self) -> Result<Option<Type<'de>>> {
+        let uri = match self.peek_item()? {
+            Some(Item::Link { uri, .. }) => *uri,
+
+            Some(Item::PushOrderedList) | Some(Item::PushUnorderedList) => {
+                match self.reader.peek_nth(1) {
+                    Some(Ok(Item::Link { uri, .. })) => *uri,
+                    Some(Ok(_)) => return Err(Error::UnexpectedItem("a link")),
+
+                    // The document is broken anyway, so there's no harm in dropping the list
+                    Some(Err(_)) => {
+                        self.next_item()?;
+                        return Err(self.next_item().unwrap_err());
+                    }
+
+                    None => return Err(Error::UnexpectedEOF),
+                }
+            }
+
+            Some(Item::Key(_)) => return Ok(Some(Type::String)),
+
+            Some(Item::PopList) | None => return Ok(None),
+        };
+
+        self.parse_type(uri).map(Some)
+    }
+
+    /// If in recovery mode, skip over any malformed values at the front of the reader
+    fn recover(&mut self) -> Result<()> {
+        if self.recovery.is_none() {
+            return Ok(());
+        }
+
+        while let Some(error) = self.malformed_ahead(0) {
+            match self.next_item()? {
+                Item::PushOrderedList | Item::PushUnorderedList => self.skip_list()?,
+                Item::Link { .. } | Item::Key(_) | Item::PopList => {}
+            }
+            (self.recovery.as_ref().unwrap().borrow_mut())(error);
+        }
+        Ok(())
+    }
+
+    /// Deserialize the key of a map entry whose `PushOrderedList` was just read
+    fn entry_key<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<K::Value> {
+        // The keys of definitions are always strings, which don't say so
+        if let Some(Item::Key(_)) = self.peek_item()? {
+            return match self.next_item()? {
+                Item::Key(Cow::Borrowed(key)) => {
+                    seed.deserialize(de::value::BorrowedStrDeserializer::new(key))
+                }
+                Item::Key(Cow::Owned(key)) => seed.deserialize(key.into_deserializer()),
+                _ => unreachable!("peeked a key"),
+            };
+        }
+        seed.deserialize(self)
+    }
+
+    /// Find out why the value starting `n` items ahead is malformed, if it's a link which doesn't
+    /// parse or a list whose header doesn't
+    ///
+    /// Errors from the reader itself are left to be run into when the value is read for real.
+    fn malformed_ahead(&mut self, n: usize) -> Option<Error> {
+        let (text, uri) = match self.reader.peek_nth(n)? {
+            Ok(Item::Link { text, uri }) => (text.clone(), *uri),
+            Ok(Item::PushOrderedList) | Ok(Item::PushUnorderedList) => {
+                let uri = match self.reader.peek_nth(n + 1)? {
+                    Ok(Item::Link { uri, .. }) => *uri,
+                    _ => return None,
+                };
+                return self.parse_type(uri).err();
+            }
+            _ => return None,
+        };
+        self.primitive(text, uri, de::IgnoredAny).err()
+    }
+
+    /// Find out why the map entry whose `PushOrderedList` was just read is malformed, if its key
+    /// is, or its value is while its key is a single item
+    fn malformed_entry(&mut self) -> Option<Error> {
+        if let Some(error) = self.malformed_ahead(0) {
+            return Some(error);
         }
+        match self.reader.peek_nth(0)? {
+            Ok(Item::Link { .. }) | Ok(Item::Key(_)) => self.malformed_ahead(1),
+            _ => None,
+        }
+    }
+
+    /// Skip the rest of the current list, including its closing `PopList`
+    fn skip_list(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+        while let Some(item) = self.next_opt()? {
+            match item {
+                Item::PushOrderedList | Item::PushUnorderedList => depth += 1,
+                Item::PopList if depth == 0 => break,
+                Item::PopList => depth -= 1,
+                Item::Link { .. } | Item::Key(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn bytes<V: de::Visitor<'de>>(
+        &mut self,
+        text: &str,
+        uri: &str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut buf = Vec::new();
+        decode_base64(text, uri, &mut buf)?;
+        visitor.visit_byte_buf(buf)
+    }
+
+    fn hex_bytes<V: de::Visitor<'de>>(
+        &mut self,
+        text: &str,
+        uri: &str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut buf = Vec::with_capacity(text.len() / 2);
+        decode_hex(text, uri, &mut buf)?;
+        visitor.visit_byte_buf(buf)
     }
 
-    fn bytes<V: de::Visitor<'de>>(&mut self, text: &str, visitor: V) -> Result<V::Value> {
-        visitor.visit_byte_buf(base64::decode(text)?)
+    /// Decode each chunk of a `ByteChunks` list straight into the same buffer
+    fn byte_chunks<V: de::Visitor<'de>>(&mut self, len: usize, visitor: V) -> Result<V::Value> {
+        let mut buf = Vec::with_capacity(self.cautious(len));
+        loop {
+            match self.next_item()? {
+                Item::Link { text, uri } => match self.parse_type(uri)? {
+                    Type::HexBytes => decode_hex(&text, uri, &mut buf)?,
+                    Type::RawBytes => buf.extend_from_slice(text.as_bytes()),
+                    _ => decode_base64(&text, uri, &mut buf)?,
+                },
+                Item::PopList => break,
+                Item::PushOrderedList | Item::PushUnorderedList | Item::Key(_) => {
+                    return Err(Error::UnexpectedItem("a link"))
+                }
+            }
+        }
+        visitor.visit_byte_buf(buf)
     }
 
     fn primitive<V: de::Visitor<'de>>(
@@ -28,8 +544,27 @@ impl<'de> Deserializer<'de> {
         uri: &'de str,
         visitor: V,
     ) -> Result<V::Value> {
-        match Type::from_str(uri)? {
-            Type::Bool => visitor.visit_bool(text.parse()?),
+        let ty = self.parse_type(uri)?;
+        let text = if ty.is_scalar() {
+            scalar_text(text, uri)
+        } else {
+            text
+        };
+
+        match ty {
+            Type::I16 if self.minimize_int_widths => visit_signed(text.parse::<i16>()?, visitor),
+            Type::I32 if self.minimize_int_widths => visit_signed(text.parse::<i32>()?, visitor),
+            Type::I64 if self.minimize_int_widths => visit_signed(text.parse::<i64>()?, visitor),
+            Type::I128 if self.minimize_int_widths => visit_signed(text.parse::<i128>()?, visitor),
+            Type::U16 if self.minimize_int_widths => visit_unsigned(text.parse::<u16>()?, visitor),
+            Type::U32 if self.minimize_int_widths => visit_unsigned(text.parse::<u32>()?, visitor),
+            Type::U64 if self.minimize_int_widths => visit_unsigned(text.parse::<u64>()?, visitor),
+            Type::U128 if self.minimize_int_widths => {
+                visit_unsigned(text.parse::<u128>()?, visitor)
+            }
+
+            Type::Bool if self.lenient_bools => visitor.visit_bool(parse_lenient_bool(&text)?),
+            Type::Bool => visitor.visit_bool(parse_bool(&text)?),
             Type::I8 => visitor.visit_i8(text.parse()?),
             Type::I16 => visitor.visit_i16(text.parse()?),
             Type::I32 => visitor.visit_i32(text.parse()?),
@@ -42,22 +577,32 @@ impl<'de> Deserializer<'de> {
             Type::U128 => visitor.visit_u128(text.parse()?),
             Type::F32 => visitor.visit_f32(text.parse()?),
             Type::F64 => visitor.visit_f64(text.parse()?),
-            Type::Char => visitor.visit_char(text.parse()?),
-            Type::String => match text {
+            Type::Char => visitor.visit_char(parse_char(&text)?),
+            // Arbitrary precision numbers are left for the visitor to parse
+            Type::String | Type::BigNum | Type::Decimal => match text {
                 Cow::Borrowed(text) => visitor.visit_borrowed_str(text),
                 Cow::Owned(text) => visitor.visit_string(text),
             },
-            Type::Bytes => self.bytes(text.as_ref(), visitor),
+            Type::Bytes => self.bytes(&text, uri, visitor),
+            Type::HexBytes => self.hex_bytes(&text, uri, visitor),
+            Type::RawBytes => match text {
+                Cow::Borrowed(text) => visitor.visit_borrowed_bytes(text.as_bytes()),
+                Cow::Owned(text) => visitor.visit_byte_buf(text.into_bytes()),
+            },
 
             Type::None => visitor.visit_none(),
 
             Type::Unit => visitor.visit_unit(),
 
+            Type::Summary => Err(Error::Summarized),
+
             // Can we really do nothing with the name?
             Type::UnitStruct(..) => visitor.visit_unit(),
 
             // This is what the example Deserializer does but I'm not sure about it
-            Type::UnitVariant(_name, variant) => visitor.visit_enum(variant.into_deserializer()),
+            Type::UnitVariant(_name, variant) => {
+                visitor.visit_enum(ty::decode_name(variant).into_deserializer())
+            }
 
             // All of the following are non-primitive types
             Type::Some
@@ -69,26 +614,103 @@ impl<'de> Deserializer<'de> {
             | Type::TupleVariant(_, _, _)
             | Type::Map(_)
             | Type::Struct(_, _)
-            | Type::StructVariant(_, _, _) => unreachable!(),
+            | Type::StructVariant(_, _, _)
+            | Type::ByteChunks(_) => Err(de::Error::invalid_type(
+                de::Unexpected::Other(uri),
+                &visitor,
+            )),
         }
     }
 
-    fn ordered_list<V: de::Visitor<'de>>(&mut self, visitor: V) -> Result<V::Value> {
-        let ty = match self.reader.next().ok_or(Error::UnexpectedEOF)? {
-            Item::Link { uri, .. } => Type::from_str(uri)?,
-            Item::PushOrderedList | Item::PushUnorderedList | Item::PopList => unreachable!(),
+    /// If the next item is a link to any kind of number or a string, consume it and return its text
+    ///
+    /// This lets numbers be parsed straight into the type that's wanted, so that e.g. an `f64`
+    /// field which has since become an `f32` or a `u64` one which has become a `u16` still work.
+    /// Strings are included as numbers are often written with `collect_str`
+    fn number_text(&mut self) -> Result<Option<Cow<'de, str>>> {
+        let uri = match self.peek_item()? {
+            Some(Item::Link { uri, .. }) => *uri,
+            _ => return Ok(None),
         };
 
+        // Long runs of numbers, e.g. a `Vec<u8>` without `serde_bytes`, are common enough that
+        // it's worth not building a `Type` for every single one of them
+        if let Some(
+            "i8" | "i16" | "i32" | "i64" | "i128" | "u8" | "u16" | "u32" | "u64" | "u128" | "f32"
+            | "f64" | "bignum" | "decimal" | "string",
+        ) = self.uri_path(uri)
+        {
+            return match self.next_item()? {
+                Item::Link { text, .. } => Ok(Some(text)),
+                _ => Err(Error::UnexpectedItem("a link")),
+            };
+        }
+
+        match self.parse_type(uri)? {
+            Type::I8
+            | Type::I16
+            | Type::I32
+            | Type::I64
+            | Type::I128
+            | Type::U8
+            | Type::U16
+            | Type::U32
+            | Type::U64
+            | Type::U128
+            | Type::F32
+            | Type::F64
+            | Type::BigNum
+            | Type::Decimal
+            | Type::String => match self.next_item()? {
+                Item::Link { text, .. } => Ok(Some(scalar_text(text, uri))),
+                _ => Err(Error::UnexpectedItem("a link")),
+            },
+
+            _ => Ok(None),
+        }
+    }
+
+    /// Get the URI of the link which starts every list
+    fn header(&mut self) -> Result<&'de str> {
+        match self.next_item()? {
+            Item::Link { uri, .. } => Ok(uri),
+            Item::PushOrderedList | Item::PushUnorderedList | Item::PopList | Item::Key(_) => {
+                Err(Error::UnexpectedItem("a link"))
+            }
+        }
+    }
+
+    /// Consume the `PopList` which ends the current list
+    fn end_list(&mut self) -> Result<()> {
+        match self.next_item()? {
+            Item::PopList => Ok(()),
+            _ => Err(Error::UnexpectedItem("the end of the list")),
+        }
+    }
+
+    /// Deserialize the body of a list, whose opening `Push*List` has already been consumed
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.remaining_depth = self
+            .remaining_depth
+            .checked_sub(1)
+            .ok_or(Error::RecursionLimitExceeded)?;
+        let result = f(self);
+        self.remaining_depth += 1;
+        result
+    }
+
+    /// Deserialize the body of an ordered list, whose header has already been parsed into `ty`
+    fn ordered_list<V: de::Visitor<'de>>(&mut self, ty: Type<'de>, visitor: V) -> Result<V::Value> {
         match ty {
             Type::Some => {
                 let value = visitor.visit_some(&mut *self)?;
-                assert_eq!(self.reader.next(), Some(Item::PopList));
+                self.end_list()?;
                 Ok(value)
             }
 
             Type::NewtypeStruct(..) => {
                 let value = visitor.visit_newtype_struct(&mut *self)?;
-                assert_eq!(self.reader.next(), Some(Item::PopList));
+                self.end_list()?;
                 Ok(value)
             }
 
@@ -96,26 +718,24 @@ impl<'de> Deserializer<'de> {
                 let value = visitor.visit_enum(VariantDeserializer {
                     deserializer: &mut *self,
                     variant,
+                    newtype: true,
                 })?;
-                assert_eq!(self.reader.next(), Some(Item::PopList));
+                self.end_list()?;
                 Ok(value)
             }
 
-            Type::Seq(len) => visitor.visit_seq(SeqDeserializer {
-                deserializer: &mut *self,
-                len,
-            }),
+            Type::Seq(len) => self.visit_seq(len, visitor),
 
-            Type::Tuple(len) | Type::TupleStruct(_, len) => visitor.visit_seq(SeqDeserializer {
-                deserializer: &mut *self,
-                len: Some(len),
-            }),
+            Type::Tuple(len) | Type::TupleStruct(_, len) => self.visit_seq(Some(len), visitor),
 
             Type::TupleVariant(_, variant, _) => visitor.visit_enum(VariantDeserializer {
                 deserializer: &mut *self,
                 variant,
+                newtype: false,
             }),
 
+            Type::ByteChunks(len) => self.byte_chunks(len, visitor),
+
             Type::Bool
             | Type::I8
             | Type::I16
@@ -131,29 +751,116 @@ impl<'de> Deserializer<'de> {
             | Type::F64
             | Type::Char
             | Type::String
+            | Type::BigNum
+            | Type::Decimal
             | Type::Bytes
+            | Type::HexBytes
+            | Type::RawBytes
             | Type::None
             | Type::Unit
             | Type::UnitStruct(_)
             | Type::UnitVariant(_, _)
-            | Type::Map(_)
-            | Type::Struct(_, _)
-            | Type::StructVariant(_, _, _) => unreachable!(),
+            | Type::Summary => Err(Error::UnexpectedItem("a link")),
+
+            Type::Map(_) | Type::Struct(_, _) | Type::StructVariant(_, _, _) => {
+                Err(Error::UnexpectedItem("an unordered list"))
+            }
+        }
+    }
+
+    /// Visit the elements of a seq, whose header has already been consumed
+    ///
+    /// Visitors may stop before the end of the list, e.g. for tuples, so whatever's left of it is
+    /// skipped afterwards
+    fn visit_seq<V: de::Visitor<'de>>(
+        &mut self,
+        len: Option<usize>,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut seq = SeqDeserializer {
+            deserializer: &mut *self,
+            len,
+            done: false,
+        };
+        let value = visitor.visit_seq(&mut seq)?;
+        if !seq.done {
+            self.skip_list()?;
         }
+        Ok(value)
     }
 
-    fn unordered_list<V: de::Visitor<'de>>(&mut self, visitor: V) -> Result<V::Value> {
-        let ty = match self.reader.next().ok_or(Error::UnexpectedEOF)? {
-            Item::Link { uri, .. } => Type::from_str(uri)?,
-            Item::PushOrderedList | Item::PushUnorderedList | Item::PopList => unreachable!(),
+    /// Visit the entries of a map, whose header has already been consumed
+    ///
+    /// Like with `visit_seq`, any entries the visitor didn't ask for are skipped afterwards
+    fn visit_map<V: de::Visitor<'de>>(
+        &mut self,
+        len: Option<usize>,
+        visitor: V,
+    ) -> Result<V::Value> {
+        let mut map = MapDeserializer {
+            deserializer: &mut *self,
+            len,
+            done: false,
         };
+        let value = visitor.visit_map(&mut map)?;
+        if !map.done {
+            self.skip_list()?;
+        }
+        Ok(value)
+    }
+
+    /// Deserialize the body of a struct, whose opening `PushUnorderedList` has already been consumed
+    ///
+    /// The names of the fields are only a size hint: a struct may have fewer entries when fields
+    /// are skipped or defaulted, and more when it has unknown or `skip_deserializing` fields,
+    /// neither of which only the names can tell apart, so reporting missing or unknown fields is
+    /// left up to the visitor. Structs are read whatever their name, so that renaming one doesn't
+    /// break existing documents.
+    fn struct_map<V: de::Visitor<'de>>(
+        &mut self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        let uri = self.header()?;
+        match self.parse_type(uri)? {
+            Type::Map(_) | Type::Struct(_, _) => self.visit_map(Some(fields.len()), visitor),
+
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other(uri),
+                &visitor,
+            )),
+        }
+    }
+
+    /// Visit an enum variant's data as a map with a single entry, from the variant's name to it
+    fn variant_map<V: de::Visitor<'de>>(
+        &mut self,
+        variant: &'de str,
+        kind: VariantKind,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_map(VariantMap {
+            deserializer: self,
+            variant: Some(variant),
+            kind: Some(kind),
+        })
+    }
 
+    /// Deserialize the body of an unordered list, whose header has already been parsed into `ty`
+    fn unordered_list<V: de::Visitor<'de>>(
+        &mut self,
+        ty: Type<'de>,
+        visitor: V,
+    ) -> Result<V::Value> {
         match ty {
-            Type::Map(_) | Type::Struct(_, _) => visitor.visit_map(self),
+            Type::Map(len) => self.visit_map(len, visitor),
+
+            Type::Struct(_, len) => self.visit_map(Some(len), visitor),
 
             Type::StructVariant(_, variant, _) => visitor.visit_enum(VariantDeserializer {
                 deserializer: &mut *self,
                 variant,
+                newtype: false,
             }),
 
             Type::Bool
@@ -171,106 +878,807 @@ impl<'de> Deserializer<'de> {
             | Type::F64
             | Type::Char
             | Type::String
+            | Type::BigNum
+            | Type::Decimal
             | Type::Bytes
+            | Type::HexBytes
+            | Type::RawBytes
             | Type::None
-            | Type::Some
             | Type::Unit
             | Type::UnitStruct(_)
             | Type::UnitVariant(_, _)
+            | Type::Summary => Err(Error::UnexpectedItem("a link")),
+
+            Type::Some
             | Type::NewtypeStruct(_)
             | Type::NewtypeVariant(_, _)
             | Type::Seq(_)
             | Type::Tuple(_)
             | Type::TupleStruct(_, _)
-            | Type::TupleVariant(_, _, _) => unreachable!(),
+            | Type::TupleVariant(_, _, _)
+            | Type::ByteChunks(_) => Err(Error::UnexpectedItem("an ordered list")),
         }
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
-    type Error = Error;
+/// Make sure that, if `ty` is an enum variant, it belongs to the enum called `name`
+fn check_enum<'de, V: de::Visitor<'de>>(
+    name: &str,
+    uri: &str,
+    ty: &Type<'_>,
+    visitor: &V,
+) -> Result<()> {
+    match *ty {
+        Type::UnitVariant(enum_name, _)
+        | Type::NewtypeVariant(enum_name, _)
+        | Type::TupleVariant(enum_name, _, _)
+        | Type::StructVariant(enum_name, _, _)
+            if ty::decode_name(enum_name) != name =>
+        {
+            Err(de::Error::invalid_value(
+                de::Unexpected::Other(uri),
+                visitor,
+            ))
+        }
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: de::Visitor<'de>,
-    {
-        match self.reader.next().ok_or(Error::UnexpectedEOF)? {
-            Item::PushOrderedList => self.ordered_list(visitor),
+        _ => Ok(()),
+    }
+}
 
-            Item::PushUnorderedList => self.unordered_list(visitor),
+/// Parse the URI of a link into its type, as the deserializer does
+pub(crate) fn parse_type<'de>(
+    uri: &'de str,
+    scheme: &'static str,
+    bare_uris: bool,
+) -> Result<Type<'de>> {
+    let error = match parse_type_uri(uri, scheme, bare_uris) {
+        Ok(ty) => return Ok(ty),
+        Err(error) => error,
+    };
 
-            Item::PopList => {
-                assert_eq!(self.reader.next(), None);
-                Err(Error::UnexpectedEOF)
-            }
+    // Booleans and numbers shown differently have their value after the URI
+    match uri
+        .rsplit_once('#')
+        .map(|(uri, _)| parse_type_uri(uri, scheme, bare_uris))
+    {
+        Some(Ok(ty)) if ty.is_scalar() => Ok(ty),
+        _ => Err(error),
+    }
+}
 
-            Item::Link { text, uri } => self.primitive(text, uri, visitor),
-        }
+/// Parse a type URI as written with `scheme`, or without one at all if `bare_uris` is set
+fn parse_type_uri<'de>(uri: &'de str, scheme: &'static str, bare_uris: bool) -> Result<Type<'de>> {
+    if bare_uris && !uri.contains("://") {
+        return Ok(Type::from_path(uri)?);
     }
+    Ok(Type::from_str(uri, scheme)?)
+}
 
-    serde::forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
-        bytes byte_buf option unit unit_struct newtype_struct seq tuple
-        tuple_struct map struct enum identifier ignored_any
+/// The text to parse a boolean or number from, which follows the URI if it's shown differently
+fn scalar_text<'de>(text: Cow<'de, str>, uri: &'de str) -> Cow<'de, str> {
+    match uri.rsplit_once('#') {
+        Some((_, value)) => Cow::Borrowed(value),
+        None => text,
     }
 }
 
-impl<'de> de::MapAccess<'de> for Deserializer<'de> {
-    type Error = Error;
+/// Decode a bytes link into `buf`, keeping enough of it around to find it again on failure
+fn decode_base64(text: &str, uri: &str, buf: &mut Vec<u8>) -> Result<()> {
+    base64::decode_config_buf(text, base64::URL_SAFE, buf).map_err(|error| Error::B64DecodeError {
+        uri: uri.to_owned(),
+        snippet: snippet(text),
+        error,
+    })
+}
 
-    fn next_key_seed<K: de::DeserializeSeed<'de>>(
-        &mut self,
-        seed: K,
-    ) -> Result<Option<K::Value>, Self::Error> {
-        match self.reader.next() {
-            Some(Item::PushOrderedList) => seed.deserialize(self).map(Some),
+/// Decode a hex bytes link into `buf`, in either case
+fn decode_hex(text: &str, uri: &str, buf: &mut Vec<u8>) -> Result<()> {
+    let error = || Error::HexDecodeError {
+        uri: uri.to_owned(),
+        snippet: snippet(text),
+    };
 
-            Some(Item::PopList) => Ok(None),
-            Some(Item::PushUnorderedList) | Some(Item::Link { .. }) | None => unreachable!(),
-        }
+    // Checking the digits up front also rules out the sign `from_str_radix` would accept
+    if text.len() % 2 == 1 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(error());
+    }
+    for i in (0..text.len()).step_by(2) {
+        buf.push(u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| error())?);
     }
+    Ok(())
+}
 
-    fn next_value_seed<V: de::DeserializeSeed<'de>>(
-        &mut self,
-        seed: V,
-    ) -> Result<V::Value, Self::Error> {
-        let value = seed.deserialize(&mut *self)?;
-        assert_eq!(self.reader.next(), Some(Item::PopList));
-        Ok(value)
+/// The start of a link's text, to point out which one was malformed
+fn snippet(text: &str) -> String {
+    const SNIPPET_LEN: usize = 16;
+
+    let mut snippet = text.chars().take(SNIPPET_LEN).collect::<String>();
+    if text.chars().nth(SNIPPET_LEN).is_some() {
+        snippet.push('…');
     }
+    snippet
 }
 
-struct SeqDeserializer<'de, 'a> {
-    deserializer: &'a mut Deserializer<'de>,
-    len: Option<usize>,
+fn parse_char(text: &str) -> Result<char> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(Error::InvalidChar(text.to_owned())),
+    }
 }
 
-impl<'de, 'a> de::SeqAccess<'de> for SeqDeserializer<'de, 'a> {
-    type Error = Error;
+fn parse_bool(text: &str) -> Result<bool> {
+    match text {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(Error::InvalidBool(text.to_owned())),
+    }
+}
 
-    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
-    where
-        T: de::DeserializeSeed<'de>,
+fn parse_lenient_bool(text: &str) -> Result<bool> {
+    if ["true", "yes", "1"]
+        .iter()
+        .any(|t| text.eq_ignore_ascii_case(t))
     {
-        if let Some(Item::PopList) = self.deserializer.reader.peek() {
-            self.deserializer.reader.next();
-            return Ok(None);
+        Ok(true)
+    } else if ["false", "no", "0"]
+        .iter()
+        .any(|t| text.eq_ignore_ascii_case(t))
+    {
+        Ok(false)
+    } else {
+        Err(Error::InvalidBool(text.to_owned()))
+    }
+}
+
+/// Visit a signed integer as the smallest type which can hold it
+fn visit_signed<'de, V: de::Visitor<'de>>(n: impl Into<i128>, visitor: V) -> Result<V::Value> {
+    let n = n.into();
+    if let Ok(n) = i8::try_from(n) {
+        visitor.visit_i8(n)
+    } else if let Ok(n) = i16::try_from(n) {
+        visitor.visit_i16(n)
+    } else if let Ok(n) = i32::try_from(n) {
+        visitor.visit_i32(n)
+    } else if let Ok(n) = i64::try_from(n) {
+        visitor.visit_i64(n)
+    } else {
+        visitor.visit_i128(n)
+    }
+}
+
+/// Visit an unsigned integer as the smallest type which can hold it
+fn visit_unsigned<'de, V: de::Visitor<'de>>(n: impl Into<u128>, visitor: V) -> Result<V::Value> {
+    let n = n.into();
+    if let Ok(n) = u8::try_from(n) {
+        visitor.visit_u8(n)
+    } else if let Ok(n) = u16::try_from(n) {
+        visitor.visit_u16(n)
+    } else if let Ok(n) = u32::try_from(n) {
+        visitor.visit_u32(n)
+    } else if let Ok(n) = u64::try_from(n) {
+        visitor.visit_u64(n)
+    } else {
+        visitor.visit_u128(n)
+    }
+}
+
+/// The raw text of the `[text](uri)` link whose URI starts at `offset` in `source`, unless the
+/// link was written some other way, such as a checkbox or a reference link
+fn link_text(source: &str, offset: usize) -> Option<&str> {
+    let before = source[..offset].strip_suffix("](")?;
+    let escapes = |end: usize| end - before[..end].trim_end_matches('\\').len();
+
+    // Brackets in the text are escaped, unless the backslash before them is itself escaped
+    let mut start = before.rfind('[')?;
+    while escapes(start) % 2 == 1 {
+        start = before[..start].rfind('[')?;
+    }
+    Some(&before[start + 1..]).filter(|text| !text.contains('\n'))
+}
+
+/// Whether `error` is about the text of a link rather than its type URI
+fn is_text_error(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::ParseCharError(_)
+            | Error::ParseIntError(_)
+            | Error::ParseBoolError(_)
+            | Error::ParseFloatError(_)
+            | Error::B64DecodeError { .. }
+            | Error::HexDecodeError { .. }
+            | Error::InvalidBool(_)
+            | Error::InvalidChar(_)
+    )
+}
+
+/// Deserialize a `T` from MML text
+///
+/// Errors are wrapped in an `Error::Located` pointing out where they happened, as with
+/// `Deserializer::locate`; `Error::unlocated` gets the error itself back out.
+pub fn from_str<'de, T>(text: &'de str) -> Result<T>
+where
+    T: de::Deserialize<'de>,
+{
+    let mut deserializer = Deserializer::new(text);
+    T::deserialize(&mut deserializer).map_err(|error| deserializer.locate(error))
+}
+
+/// Splits apart documents which have been concatenated together
+///
+/// A bare link at the root indent already ends the reader's lists, so all that's left is finding
+/// where a top-level list is followed by another one, as they'd otherwise merge: that's wherever
+/// a list's header turns up after the first item of the root list.
+struct Documents<'de> {
+    reader: Reader<'de>,
+    scheme: &'static str,
+    bare_uris: bool,
+    depth: usize,
+    header_pending: bool,
+    pending: VecDeque<Item<'de>>,
+}
+
+impl<'de> Iterator for Documents<'de> {
+    type Item = Result<Item<'de>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.pending.pop_front() {
+            return Some(Ok(item));
+        }
+
+        let item = match self.reader.next()? {
+            Ok(item) => item,
+            Err(error) => return Some(Err(error)),
+        };
+
+        match item {
+            Item::PushOrderedList | Item::PushUnorderedList => {
+                self.depth += 1;
+                self.header_pending = self.depth == 1;
+            }
+
+            Item::PopList => self.depth = self.depth.saturating_sub(1),
+
+            Item::Link { .. } if self.depth == 1 && self.header_pending => {
+                self.header_pending = false;
+            }
+
+            Item::Link { uri, .. } if self.depth == 1 => {
+                let push = match parse_type_uri(uri, self.scheme, self.bare_uris) {
+                    Ok(Type::Map(_)) | Ok(Type::Struct(..)) | Ok(Type::StructVariant(..)) => {
+                        Item::PushUnorderedList
+                    }
+
+                    Ok(Type::Some)
+                    | Ok(Type::NewtypeStruct(_))
+                    | Ok(Type::NewtypeVariant(..))
+                    | Ok(Type::Seq(_))
+                    | Ok(Type::Tuple(_))
+                    | Ok(Type::TupleStruct(..))
+                    | Ok(Type::TupleVariant(..))
+                    | Ok(Type::ByteChunks(_)) => Item::PushOrderedList,
+
+                    _ => return Some(Ok(item)),
+                };
+
+                self.pending.push_back(push);
+                self.pending.push_back(item);
+                return Some(Ok(Item::PopList));
+            }
+
+            Item::Link { .. } | Item::Key(_) => {}
+        }
+
+        Some(Ok(item))
+    }
+}
+
+/// Lazily deserializes every value in a string of back-to-back documents
+///
+/// Documents may be separated by blank lines, but they don't have to be, and e.g. `cat`ing files
+/// which don't end in a newline works too.
+pub struct StreamDeserializer<'de, T> {
+    deserializer: Deserializer<'de, Documents<'de>>,
+    failed: bool,
+    output: PhantomData<T>,
+}
+
+impl<'de, T> StreamDeserializer<'de, T>
+where
+    T: de::Deserialize<'de>,
+{
+    pub fn new(text: &'de str) -> Self {
+        Self {
+            deserializer: Deserializer::from_reader(Documents {
+                reader: Reader::new(text),
+                scheme: ty::DEFAULT_SCHEME,
+                bare_uris: false,
+                depth: 0,
+                header_pending: false,
+                pending: VecDeque::new(),
+            }),
+            failed: false,
+            output: PhantomData,
+        }
+    }
+
+    /// Use `scheme` instead of `serde` for the type URIs, as with `Deserializer::with_scheme`
+    pub fn with_scheme(mut self, scheme: &'static str) -> Self {
+        self.deserializer.reader.inner.scheme = scheme;
+        self.deserializer.scheme = scheme;
+        self
+    }
+
+    /// Also accept type URIs without a scheme, as with `Deserializer::with_bare_uris`
+    pub fn with_bare_uris(mut self, bare_uris: bool) -> Self {
+        self.deserializer.reader.inner.bare_uris = bare_uris;
+        self.deserializer.bare_uris = bare_uris;
+        self
+    }
+}
+
+impl<'de, T> Iterator for StreamDeserializer<'de, T>
+where
+    T: de::Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let result = match self.deserializer.peek_item() {
+            Ok(None) => return None,
+            Ok(Some(_)) => T::deserialize(&mut self.deserializer),
+            Err(error) => Err(error),
+        };
+
+        // There's no telling where the next document starts after an error
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+impl<'de, T> core::iter::FusedIterator for StreamDeserializer<'de, T> where T: de::Deserialize<'de> {}
+
+/// Deserialize numbers as the type the visitor asks for, whichever type they were written as
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: de::Visitor<'de>,
+            {
+                match self.number_text()? {
+                    Some(text) => visitor.$visit(text.parse()?),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, R> de::Deserializer<'de> for &mut Deserializer<'de, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // Visitors which don't know what's coming can't be expected to handle enums, so do what
+        // other self-describing formats do: unit variants are just their name, and variants with
+        // data are maps from their name to it
+        match self.next_item()? {
+            Item::PushOrderedList => self.nested(|this| {
+                let uri = this.header()?;
+                match this.parse_type(uri)? {
+                    Type::NewtypeVariant(_, variant) => {
+                        this.variant_map(variant, VariantKind::Newtype, visitor)
+                    }
+                    Type::TupleVariant(_, variant, len) => {
+                        this.variant_map(variant, VariantKind::Tuple(len), visitor)
+                    }
+                    ty => this.ordered_list(ty, visitor),
+                }
+            }),
+
+            Item::PushUnorderedList => self.nested(|this| {
+                let uri = this.header()?;
+                match this.parse_type(uri)? {
+                    Type::StructVariant(_, variant, len) => {
+                        this.variant_map(variant, VariantKind::Struct(len), visitor)
+                    }
+                    ty => this.unordered_list(ty, visitor),
+                }
+            }),
+
+            // The current list ended where we expected a value
+            Item::PopList => Err(Error::UnexpectedEOF),
+
+            // Only map entries' keys may be written as definitions
+            Item::Key(_) => Err(Error::UnexpectedItem("a value")),
+
+            Item::Link { text, uri } => match self.parse_type(uri)? {
+                Type::UnitVariant(_, variant) => match ty::decode_name(variant) {
+                    Cow::Borrowed(variant) => visitor.visit_borrowed_str(variant),
+                    Cow::Owned(variant) => visitor.visit_string(variant),
+                },
+                _ => self.primitive(text, uri, visitor),
+            },
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // Structs may also be deserialized from sequences, so only intercept maps
+        if let Some(Item::PushUnorderedList) = self.peek_item()? {
+            self.next_item()?;
+            self.nested(|this| this.struct_map(fields, visitor))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // Variant names alone may be ambiguous, so also check the enum's name
+        match self.next_item()? {
+            Item::Link { text, uri } => {
+                let ty = self.parse_type(uri)?;
+                check_enum(name, uri, &ty, &visitor)?;
+                self.primitive(text, uri, visitor)
+            }
+
+            Item::PushOrderedList => self.nested(|this| {
+                let uri = this.header()?;
+                let ty = this.parse_type(uri)?;
+                check_enum(name, uri, &ty, &visitor)?;
+                this.ordered_list(ty, visitor)
+            }),
+
+            Item::PushUnorderedList => self.nested(|this| {
+                let uri = this.header()?;
+                let ty = this.parse_type(uri)?;
+                check_enum(name, uri, &ty, &visitor)?;
+                this.unordered_list(ty, visitor)
+            }),
+
+            // The current list ended where we expected a value
+            Item::PopList => Err(Error::UnexpectedEOF),
+
+            Item::Key(_) => Err(Error::UnexpectedItem("a value")),
+        }
+    }
+
+    deserialize_number! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.check_tuple_len(len, &visitor)?;
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.check_tuple_len(len, &visitor)?;
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        if !self.lenient_options {
+            return self.deserialize_any(visitor);
+        }
+
+        let absent = |text: &str, uri: &str| {
+            text.trim().is_empty() || text.eq_ignore_ascii_case("none") || uri == "none"
+        };
+        match self.peek_item()? {
+            // The value was left out altogether, e.g. a map entry with just its key
+            None | Some(Item::PopList) => return visitor.visit_none(),
+
+            Some(Item::Link { text, uri }) if absent(text, uri) => {
+                self.next_item()?;
+                return visitor.visit_none();
+            }
+
+            _ => {}
+        }
+
+        match self.peek_type()? {
+            Some(Type::Some) | Some(Type::None) => self.deserialize_any(visitor),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        // Field names are nearly always string links, so spare them from parsing their type
+        let uri = match self.peek_item()? {
+            Some(Item::Link { uri, .. }) => Some(*uri),
+            _ => None,
+        };
+        match uri {
+            Some(uri) if self.is_string_uri(uri) => match self.next_item()? {
+                Item::Link {
+                    text: Cow::Borrowed(text),
+                    ..
+                } => visitor.visit_borrowed_str(text),
+                Item::Link {
+                    text: Cow::Owned(text),
+                    ..
+                } => visitor.visit_string(text),
+                _ => unreachable!("peeked a link"),
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool char str string bytes byte_buf unit unit_struct newtype_struct seq map ignored_any
+    }
+}
+
+struct MapDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    deserializer: &'a mut Deserializer<'de, R>,
+    len: Option<usize>,
+    done: bool,
+}
+
+impl<'de, 'a, R> de::MapAccess<'de> for MapDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let deserializer = &mut *self.deserializer;
+        loop {
+            // In recovery mode, entries which are malformed are skipped over in their entirety
+            let error = match deserializer.next_opt()? {
+                Some(Item::PushOrderedList) => {
+                    let malformed = match deserializer.recovery {
+                        Some(_) => deserializer.malformed_entry(),
+                        None => None,
+                    };
+                    match malformed {
+                        Some(error) => {
+                            deserializer.skip_list()?;
+                            error
+                        }
+                        None => return deserializer.entry_key(seed).map(Some),
+                    }
+                }
+
+                Some(Item::PopList) => {
+                    self.done = true;
+                    return Ok(None);
+                }
+
+                Some(Item::PushUnorderedList) if deserializer.recovery.is_some() => {
+                    deserializer.skip_list()?;
+                    Error::UnexpectedItem("a map entry")
+                }
+
+                Some(Item::Link { .. }) | Some(Item::Key(_)) if deserializer.recovery.is_some() => {
+                    Error::UnexpectedItem("a map entry")
+                }
+
+                Some(Item::PushUnorderedList) | Some(Item::Link { .. }) | Some(Item::Key(_)) => {
+                    return Err(Error::UnexpectedItem("a map entry"))
+                }
+
+                None => return Err(Error::UnexpectedEOF),
+            };
+            (deserializer.recovery.as_ref().unwrap().borrow_mut())(error);
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = seed.deserialize(&mut *self.deserializer)?;
+        self.deserializer.end_list()?;
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.len.map(|len| self.deserializer.cautious(len))
+    }
+}
+
+/// The elements of a seq, as given out by `Deserializer::seq_access`
+pub struct SeqDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    deserializer: &'a mut Deserializer<'de, R>,
+    len: Option<usize>,
+    done: bool,
+}
+
+impl<'de, 'a, R> de::SeqAccess<'de> for SeqDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        self.deserializer.recover()?;
+
+        if let Some(Item::PopList) = self.deserializer.peek_item()? {
+            self.deserializer.next_item()?;
+            self.done = true;
+            return Ok(None);
         }
 
         seed.deserialize(&mut *self.deserializer).map(Some)
     }
 
     fn size_hint(&self) -> Option<usize> {
-        self.len
+        self.len.map(|len| self.deserializer.cautious(len))
+    }
+}
+
+/// The shape of the data held by an enum variant
+#[derive(Debug, Clone, Copy)]
+enum VariantKind {
+    Newtype,
+    Tuple(usize),
+    Struct(usize),
+}
+
+/// Presents an enum variant as a map with a single entry, for `deserialize_any`
+struct VariantMap<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    deserializer: &'a mut Deserializer<'de, R>,
+    variant: Option<&'de str>,
+    kind: Option<VariantKind>,
+}
+
+impl<'de, 'a, R> de::MapAccess<'de> for VariantMap<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.variant
+            .take()
+            .map(|variant| seed.deserialize(ty::decode_name(variant).into_deserializer()))
+            .transpose()
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let kind = self.kind.take().ok_or(Error::KeyValueMisorder)?;
+        seed.deserialize(VariantBody {
+            deserializer: &mut *self.deserializer,
+            kind,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.kind.is_some() as usize)
+    }
+}
+
+/// The data of an enum variant, whose header has already been consumed
+struct VariantBody<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    deserializer: &'a mut Deserializer<'de, R>,
+    kind: VariantKind,
+}
+
+impl<'de, 'a, R> de::Deserializer<'de> for VariantBody<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.kind {
+            VariantKind::Newtype => {
+                let value = de::Deserializer::deserialize_any(&mut *self.deserializer, visitor)?;
+                self.deserializer.end_list()?;
+                Ok(value)
+            }
+            VariantKind::Tuple(len) => self.deserializer.visit_seq(Some(len), visitor),
+            VariantKind::Struct(len) => self.deserializer.visit_map(Some(len), visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
-struct VariantDeserializer<'de, 'a> {
-    deserializer: &'a mut Deserializer<'de>,
+struct VariantDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
+    deserializer: &'a mut Deserializer<'de, R>,
     variant: &'de str,
+
+    /// Whether the variant's body is a single value rather than a list of fields
+    newtype: bool,
 }
 
-impl<'de, 'a> de::EnumAccess<'de> for VariantDeserializer<'de, 'a> {
+impl<'de, 'a, R> de::EnumAccess<'de> for VariantDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
     type Error = Error;
     type Variant = Self;
 
@@ -278,25 +1686,36 @@ impl<'de, 'a> de::EnumAccess<'de> for VariantDeserializer<'de, 'a> {
         self,
         seed: V,
     ) -> Result<(V::Value, Self::Variant), Self::Error> {
-        let value: Result<_> = seed.deserialize(self.variant.into_deserializer());
+        let value: Result<_> = seed.deserialize(ty::decode_name(self.variant).into_deserializer());
         Ok((value?, self))
     }
 }
 
-impl<'de, 'a> de::VariantAccess<'de> for VariantDeserializer<'de, 'a> {
+impl<'de, 'a, R> de::VariantAccess<'de> for VariantDeserializer<'de, 'a, R>
+where
+    R: Iterator<Item = Result<Item<'de>>>,
+{
     type Error = Error;
 
+    /// Unit variants are normally written as a single link and never get here, but a document
+    /// may have data for a variant which is a unit variant of the type we're deserializing into
+    ///
+    /// That's fine as long as the data is empty too: a `()` or no fields at all.
     fn unit_variant(self) -> Result<(), Self::Error> {
-        unreachable!("unit variants are handled separately")
+        if self.newtype {
+            // The closing PopList is consumed by `ordered_list`
+            de::Deserialize::deserialize(&mut *self.deserializer)
+        } else {
+            self.deserializer.end_list()
+        }
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
     where
         T: de::DeserializeSeed<'de>,
     {
-        let value = seed.deserialize(&mut *self.deserializer)?;
-        assert_eq!(self.deserializer.reader.next(), Some(Item::PopList));
-        Ok(value)
+        // The closing PopList is consumed by `ordered_list`
+        seed.deserialize(&mut *self.deserializer)
     }
 
     fn tuple_variant<V: de::Visitor<'de>>(
@@ -304,16 +1723,1463 @@ impl<'de, 'a> de::VariantAccess<'de> for VariantDeserializer<'de, 'a> {
         len: usize,
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        use de::Deserializer;
-        self.deserializer.deserialize_tuple(len, visitor)
+        // The variant's header has already been consumed, so we go straight to the elements
+        self.deserializer.visit_seq(Some(len), visitor)
     }
 
     fn struct_variant<V: de::Visitor<'de>>(
         self,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value, Self::Error> {
-        use de::Deserializer;
-        self.deserializer.deserialize_map(visitor)
+        self.deserializer.visit_map(Some(fields.len()), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::ser::tests::{render, Configure};
+    use crate::ser::Serializer;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Color {
+        r: u8,
+        g: u8,
+        b: u8,
+    }
+
+    fn to_string<T: Serialize>(value: &T) -> String {
+        let mut buf = Vec::new();
+        value.serialize(&mut Serializer::new(&mut buf)).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_struct() {
+        let doc = to_string(&Color { r: 1, g: 2, b: 255 });
+        let color = Color::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(color, Color { r: 1, g: 2, b: 255 });
+    }
+
+    #[test]
+    fn test_unescaped_field_names() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Config {
+            max_size: u8,
+            #[serde(rename = "log.level")]
+            log_level: String,
+        }
+
+        // Hand-written documents don't necessarily escape punctuation in keys
+        let doc = concat!(
+            "- [Config](serde://struct/Config/2)\n",
+            "-\n",
+            "  1. [max-size](serde://string)\n",
+            "  2. [7](serde://u8)\n",
+            "-\n",
+            "  1. [log.level](serde://string)\n",
+            "  2. [-debug](serde://string)\n",
+        );
+        let config = Config::deserialize(&mut Deserializer::new(doc)).unwrap();
+        assert_eq!(
+            config,
+            Config {
+                max_size: 7,
+                log_level: "-debug".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_tuple_in_seq() {
+        // Tuple visitors stop after their last element, before the list is closed
+        let value = vec![
+            Some((1u8, "one".to_owned())),
+            None,
+            Some((2, "two".to_owned())),
+        ];
+        let doc = to_string(&value);
+        let parsed =
+            Vec::<Option<(u8, String)>>::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(parsed, value);
+    }
+
+    #[test]
+    fn test_from_items() {
+        fn link<'a>(text: &'a str, uri: &'a str) -> Item<'a> {
+            Item::Link {
+                text: text.into(),
+                uri,
+            }
+        }
+
+        let items = vec![
+            Item::PushUnorderedList,
+            link("Color", "serde://struct/Color/3"),
+            Item::PushOrderedList,
+            link("r", "serde://string"),
+            link("1", "serde://u8"),
+            Item::PopList,
+            Item::PushOrderedList,
+            link("g", "serde://string"),
+            link("2", "serde://u8"),
+            Item::PopList,
+            Item::PushOrderedList,
+            link("b", "serde://string"),
+            link("255", "serde://u8"),
+            Item::PopList,
+            Item::PopList,
+        ];
+
+        let color = Color::deserialize(&mut Deserializer::from_items(items)).unwrap();
+        assert_eq!(color, Color { r: 1, g: 2, b: 255 });
+    }
+
+    #[test]
+    fn test_new_unbuffered() {
+        let doc = crate::ser::to_string(&Color { r: 1, g: 2, b: 255 }).unwrap();
+        let items = Reader::tokenize(&doc).unwrap();
+
+        let color = Color::deserialize(&mut Deserializer::new_unbuffered(&items)).unwrap();
+        assert_eq!(color, Color { r: 1, g: 2, b: 255 });
+
+        // The same items can be deserialized again, into something else entirely
+        let map = BTreeMap::<String, u8>::deserialize(&mut Deserializer::new_unbuffered(&items));
+        assert_eq!(
+            map.unwrap(),
+            BTreeMap::from([
+                ("r".to_owned(), 1),
+                ("g".to_owned(), 2),
+                ("b".to_owned(), 255)
+            ])
+        );
+
+        assert_eq!(
+            Reader::tokenize("[unterminated"),
+            Err(Error::UnterminatedLink)
+        );
+    }
+
+    #[test]
+    fn test_cautious_size_hint() {
+        struct SizeHint;
+
+        impl<'de> de::Visitor<'de> for SizeHint {
+            type Value = Option<usize>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a seq")
+            }
+
+            fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Ok(seq.size_hint())
+            }
+        }
+
+        let doc = "1. [Seq](serde://seq/18446744073709551615)\n";
+        let mut de = Deserializer::new(doc);
+        let hint = de::Deserializer::deserialize_seq(&mut de, SizeHint).unwrap();
+        assert_eq!(hint, Some(4096));
+
+        let mut de = Deserializer::new(doc).with_max_preallocation(16);
+        let hint = de::Deserializer::deserialize_seq(&mut de, SizeHint).unwrap();
+        assert_eq!(hint, Some(16));
+
+        let doc = to_string(&vec![1u8, 2]).replace("seq/2", "seq/4000000000");
+        assert!(doc.contains("seq/4000000000"));
+        let value = Vec::<u8>::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(value, [1, 2]);
+
+        let doc = to_string(&vec![1u8, 2, 3]);
+        let mut de = Deserializer::new(&doc).with_max_preallocation(1);
+        assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), [1, 2, 3]);
+
+        let doc = "1. [Seq](serde://seq/99999999999999999999)\n";
+        let err = Vec::<u8>::deserialize(&mut Deserializer::new(doc)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TypeParseError(ty::ParseError::LengthTooLarge(_))
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Declared length `99999999999999999999` is too large"
+        );
+    }
+
+    #[test]
+    fn test_enum_name_mismatch() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Light {
+            On,
+            Off,
+            Dimmed(u8),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Switch {
+            On,
+            Off,
+            Dimmed(u8),
+        }
+
+        let doc = to_string(&vec![Switch::On, Switch::Dimmed(3)]);
+        let parsed = Vec::<Switch>::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(parsed, vec![Switch::On, Switch::Dimmed(3)]);
+
+        for value in &[Switch::On, Switch::Dimmed(3)] {
+            let doc = to_string(value);
+            let err = Light::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+            assert!(err.to_string().contains("enum Light"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_link() {
+        for doc in &["[text](serde://string", "1. [Seq](serde://seq/1)\n2. [text"] {
+            let err = serde_value::Value::deserialize(&mut Deserializer::new(doc)).unwrap_err();
+            assert!(matches!(err, Error::UnterminatedLink), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_malformed_documents() {
+        let docs = [
+            // Stray characters and broken bullets
+            "hello",
+            "1 [x](serde://u8)\n",
+            "*[x](serde://u8)\n",
+            "1",
+            "[x]y",
+            "[maybe] <serde://bool>\n",
+            "[x] serde://bool\n",
+            // Lists where their headers or entries should be
+            "1. [Seq](serde://seq/1)\n2. \n    * [x](serde://u8)\n",
+            "* [Map](serde://map/1)\n* [x](serde://u8)\n",
+            "1. [Map](serde://map/1)\n",
+            "* [Seq](serde://seq/1)\n",
+            "1. [Some](serde://some)\n2. [1](serde://u8)\n3. [2](serde://u8)\n",
+            "1. \n    1. \n  1. [x](serde://u8)\n",
+            "1. [Bytes](serde://byte_chunks/1)\n2. \n    1. [AA](serde://bytes)\n",
+        ];
+
+        for doc in &docs {
+            assert!(from_str::<serde_value::Value>(doc).is_err(), "{:?}", doc);
+        }
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let mut doc = String::new();
+        for depth in 0..10_000 {
+            doc.push_str(&" ".repeat(depth * 4));
+            doc.push_str("1. [Some](serde://some)\n");
+        }
+
+        let err = from_str::<serde_value::Value>(&doc).unwrap_err();
+        assert!(
+            matches!(err.unlocated(), Error::RecursionLimitExceeded),
+            "{:?}",
+            err
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_transcode_to_json() {
+        #[derive(Serialize)]
+        enum Status {
+            Active,
+            Retired(u16),
+            Moved { to: String },
+        }
+
+        #[derive(Serialize)]
+        struct Node {
+            name: String,
+            weight: Option<f64>,
+            tags: Vec<&'static str>,
+            status: Vec<Status>,
+            children: BTreeMap<String, (u8, bool)>,
+        }
+
+        let mut children = BTreeMap::new();
+        children.insert("left".to_owned(), (1, true));
+        children.insert("right".to_owned(), (2, false));
+        let node = Node {
+            name: "root".to_owned(),
+            weight: None,
+            tags: vec!["a", "b"],
+            status: vec![
+                Status::Active,
+                Status::Retired(2019),
+                Status::Moved {
+                    to: "elsewhere".to_owned(),
+                },
+            ],
+            children,
+        };
+        let doc = to_string(&node);
+
+        let mut json = Vec::new();
+        serde_transcode::transcode(
+            &mut Deserializer::new(&doc),
+            &mut serde_json::Serializer::new(&mut json),
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            concat!(
+                r#"{"name":"root","weight":null,"tags":["a","b"],"#,
+                r#""status":["Active",{"Retired":2019},{"Moved":{"to":"elsewhere"}}],"#,
+                r#""children":{"left":[1,true],"right":[2,false]}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_128_bit_ints() {
+        let value = (i128::MIN, i128::MAX, u128::MAX);
+        let doc = to_string(&value);
+        assert!(doc.contains("(serde://i128)"));
+        assert!(doc.contains("(serde://u128)"));
+        assert_eq!(from_str::<(i128, i128, u128)>(&doc).unwrap(), value);
+    }
+
+    #[test]
+    fn test_stream() {
+        let doc = format!(
+            "{}{}\n\n{}",
+            to_string(&1u32),
+            to_string(&2u32),
+            to_string(&3u32)
+        );
+        let values = StreamDeserializer::<u32>::new(&doc)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), vec![1u8]);
+        let values = vec![
+            serde_value::to_value(vec![1u8, 2]).unwrap(),
+            serde_value::to_value(vec![3u8]).unwrap(),
+            serde_value::to_value(&map).unwrap(),
+            serde_value::to_value(Some(4u8)).unwrap(),
+            serde_value::to_value(5u8).unwrap(),
+            serde_value::to_value(vec![(6u8, 7u8)]).unwrap(),
+        ];
+        let doc = values.iter().map(to_string).collect::<String>();
+        let parsed = StreamDeserializer::<serde_value::Value>::new(&doc)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(parsed, values);
+
+        let mut stream = StreamDeserializer::<u32>::new("[1](serde://u32)\n[x](serde://u32)\n");
+        assert_eq!(stream.next().unwrap().unwrap(), 1);
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn test_nested_last_elements() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Outer {
+            nested: Vec<Vec<Vec<u8>>>,
+            after: u8,
+        }
+
+        // Each of these ends several lists at once, which must each be ended exactly once
+        let values = vec![
+            vec![vec![1u8], vec![2, 3]],
+            vec![vec![], vec![4]],
+            vec![vec![5], vec![]],
+            vec![vec![6]],
+        ];
+        for value in &values {
+            let doc = to_string(value);
+            assert_eq!(from_str::<Vec<Vec<u8>>>(&doc).unwrap(), *value, "{}", doc);
+        }
+
+        let outer = Outer {
+            nested: vec![vec![], vec![vec![7], vec![8, 9]]],
+            after: 10,
+        };
+        let doc = to_string(&outer);
+        assert_eq!(from_str::<Outer>(&doc).unwrap(), outer, "{}", doc);
+
+        // Nor may the outer seq's end be taken for that of its last element, or the other way
+        // around, when they come one right after the other
+        let doc = values.iter().map(to_string).collect::<String>();
+        let parsed = StreamDeserializer::<Vec<Vec<u8>>>::new(&doc)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(parsed, values);
+
+        let doc = to_string(&values[0]) + &to_string(&11u8);
+        let mut de = Deserializer::new(&doc);
+        assert_eq!(Vec::<Vec<u8>>::deserialize(&mut de).unwrap(), values[0]);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 11);
+    }
+
+    #[test]
+    fn test_seq_access() {
+        use serde::de::SeqAccess;
+
+        let doc = to_string(&(0..100_000u32).collect::<Vec<_>>()) + &to_string(&7u8);
+        let mut de = Deserializer::new(&doc);
+        let mut seq = de.seq_access().unwrap();
+        assert!(seq.size_hint().is_some());
+
+        let (mut count, mut sum) = (0, 0u64);
+        while let Some(n) = seq.next_element::<u32>().unwrap() {
+            assert_eq!(n, count);
+            count += 1;
+            sum += u64::from(n);
+        }
+        assert_eq!((count, sum), (100_000, 4_999_950_000));
+        assert_eq!(seq.next_element::<u32>().unwrap(), None);
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 7);
+
+        // Elements may be lists themselves, and the items may have been parsed beforehand
+        let items = Reader::tokenize("0. [Tuple](serde://tuple/2)\n1. [a](serde://string)\n2. \n    0. [Seq](serde://seq/1)\n    1. [1](serde://u8)\n").unwrap();
+        let mut de = Deserializer::new_unbuffered(&items);
+        let mut seq = de.seq_access().unwrap();
+        assert_eq!(seq.size_hint(), Some(2));
+        assert_eq!(seq.next_element::<String>().unwrap().unwrap(), "a");
+        assert_eq!(seq.next_element::<Vec<u8>>().unwrap().unwrap(), [1]);
+        assert_eq!(seq.next_element::<u8>().unwrap(), None);
+
+        for doc in ["[1](serde://u8)\n", "* [Map](serde://map/0)\n"] {
+            assert!(Deserializer::new(doc).seq_access().is_err(), "{}", doc);
+        }
+    }
+
+    #[test]
+    fn test_stream_concatenated_files() {
+        let mut map = BTreeMap::new();
+        map.insert("a".to_owned(), vec![vec![1u8], vec![2, 3]]);
+        let values = vec![
+            serde_value::to_value(1u8).unwrap(),
+            serde_value::to_value(vec![2u8, 3]).unwrap(),
+            serde_value::to_value(&map).unwrap(),
+            serde_value::to_value("[4](serde://u8)").unwrap(),
+            serde_value::to_value(Some(vec![5u8])).unwrap(),
+            serde_value::to_value(serde_bytes::ByteBuf::from(vec![6u8; 64])).unwrap(),
+            serde_value::to_value((7u8, 8u8)).unwrap(),
+            serde_value::to_value(9u8).unwrap(),
+        ];
+
+        let configs: [Configure; 3] = [
+            |serializer| serializer,
+            |serializer| serializer.with_inline_threshold(4),
+            |serializer| serializer.with_fenced_bytes(true),
+        ];
+        for configure in configs {
+            let files = values
+                .iter()
+                .map(|value| render(value, configure))
+                .collect::<Vec<_>>();
+
+            // Like `cat`ing files which end without a newline, with one, or with a blank line
+            for trailer in ["", "\n", "\n\n"] {
+                let doc = files
+                    .iter()
+                    .map(|file| format!("{}{}", file.trim_end(), trailer))
+                    .collect::<String>();
+                let parsed = StreamDeserializer::<serde_value::Value>::new(&doc)
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap();
+                assert_eq!(parsed, values, "{:?}", doc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_reference_uris() {
+        let values = vec![
+            serde_value::to_value(('a', 1u8)).unwrap(),
+            serde_value::to_value(vec![2u8, 3]).unwrap(),
+            serde_value::to_value(("b", 'c', 4u8)).unwrap(),
+        ];
+        let files = values
+            .iter()
+            .map(|value| {
+                let mut buf = Vec::new();
+                let mut serializer = Serializer::new(&mut buf).with_reference_uris(true);
+                value.serialize(&mut serializer).unwrap();
+                serializer.finish().unwrap();
+                String::from_utf8(buf).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert!(files.iter().all(|file| file.contains("\n[1]: ")));
+
+        for separator in ["", "\n"] {
+            let doc = files.join(separator);
+            let parsed = StreamDeserializer::<serde_value::Value>::new(&doc)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(parsed, values, "{:?}", doc);
+        }
+    }
+
+    #[test]
+    fn test_stream_scheme() {
+        let values = vec![
+            serde_value::to_value(vec![1u8, 2]).unwrap(),
+            serde_value::to_value(vec![3u8]).unwrap(),
+        ];
+        let doc = values
+            .iter()
+            .map(|value| {
+                let mut buf = Vec::new();
+                value
+                    .serialize(&mut Serializer::new(&mut buf).with_scheme("myapp"))
+                    .unwrap();
+                String::from_utf8(buf).unwrap()
+            })
+            .collect::<String>();
+        assert!(!doc.contains("serde://"));
+
+        let parsed = StreamDeserializer::<serde_value::Value>::new(&doc)
+            .with_scheme("myapp")
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(parsed, values, "{:?}", doc);
+    }
+
+    #[test]
+    fn test_stream_bare_uris() {
+        let values = vec![
+            serde_value::to_value(vec![1u8, 2]).unwrap(),
+            serde_value::to_value(vec![3u8]).unwrap(),
+        ];
+        let doc = values
+            .iter()
+            .map(|value| to_string(value).replace("serde://", ""))
+            .collect::<String>();
+
+        let parsed = StreamDeserializer::<serde_value::Value>::new(&doc)
+            .with_bare_uris(true)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(parsed, values, "{:?}", doc);
+    }
+
+    #[test]
+    fn test_number_conversions() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Now {
+            small: u16,
+            wide: i64,
+            narrow: f32,
+            float: f64,
+        }
+
+        let doc = to_string(&(70u64, -5i32, 0.1f64, 3u8));
+        assert_eq!(
+            from_str::<Now>(&doc).unwrap(),
+            Now {
+                small: 70,
+                wide: -5,
+                narrow: 0.1,
+                float: 3.0,
+            }
+        );
+
+        let doc = to_string(&70_000u64);
+        assert!(matches!(
+            from_str::<u16>(&doc).unwrap_err().unlocated(),
+            Error::ParseIntError(_)
+        ));
+        assert!(matches!(
+            from_str::<u8>(&to_string(&1.5f64)).unwrap_err().unlocated(),
+            Error::ParseIntError(_)
+        ));
+
+        // Numbers which were written as strings, e.g. with `collect_str`
+        assert_eq!(from_str::<u8>(&to_string(&"1")).unwrap(), 1);
+        assert!(matches!(
+            from_str::<u8>(&to_string(&"one")).unwrap_err().unlocated(),
+            Error::ParseIntError(_)
+        ));
+    }
+
+    #[test]
+    fn test_remaining() {
+        let list = to_string(&vec![1u8, 2]);
+        let link = to_string(&"second");
+
+        let text = format!("{}{}", list, link);
+        let mut de = Deserializer::new(&text);
+        assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), vec![1, 2]);
+        assert_eq!(de.remaining(), Some(link.as_str()));
+        assert_eq!(String::deserialize(&mut de).unwrap(), "second");
+        assert_eq!(de.remaining(), Some(""));
+
+        let text = format!("{}{}", link, list);
+        let mut de = Deserializer::new(&text);
+        assert_eq!(String::deserialize(&mut de).unwrap(), "second");
+        assert_eq!(de.remaining(), Some(list.as_str()));
+
+        // Looking ahead means the next item has already been parsed
+        de.peek_type().unwrap();
+        assert_eq!(de.remaining(), None);
+
+        let mut reader = Reader::new(&text);
+        assert!(reader.next().is_some());
+        assert_eq!(reader.remaining(), list);
+    }
+
+    #[test]
+    fn test_clone() {
+        use serde::de::SeqAccess;
+
+        /// Deserializes the first element, then hands back a clone of the deserializer
+        struct Snapshot;
+
+        impl<'de> de::Visitor<'de> for Snapshot {
+            type Value = (u8, Vec<u8>);
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a sequence")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let first = seq.next_element()?.unwrap();
+                let mut rest = Vec::new();
+                while let Some(n) = seq.next_element()? {
+                    rest.push(n);
+                }
+                Ok((first, rest))
+            }
+        }
+
+        let doc = to_string(&vec![1u8, 2, 3]);
+        let mut original = Deserializer::new(&doc);
+        assert_eq!(original.peek_type().unwrap(), Some(Type::Seq(Some(3))));
+
+        // Both see the whole document, independently
+        let mut clone = original.clone();
+        assert_eq!(
+            Vec::<u8>::deserialize(&mut original).unwrap(),
+            vec![1, 2, 3]
+        );
+        assert!(original.peek_type().unwrap().is_none());
+        assert_eq!(
+            de::Deserializer::deserialize_seq(&mut clone, Snapshot).unwrap(),
+            (1, vec![2, 3])
+        );
+
+        // Clones of a reader mid-document carry on from there
+        let mut reader = Reader::new(&doc);
+        reader.next();
+        reader.next();
+        let rest = reader.clone().collect::<Vec<_>>();
+        assert_eq!(reader.collect::<Vec<_>>(), rest);
+        assert_eq!(rest.len(), 4);
+
+        // Clones share the recovery callback
+        let skipped = Rc::new(Cell::new(0));
+        let counter = skipped.clone();
+        let bad = doc.replacen("[2]", "[two]", 1);
+        let de = Deserializer::new(&bad).with_recovery(move |_| counter.set(counter.get() + 1));
+        for mut de in [de.clone(), de] {
+            assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), vec![1, 3]);
+        }
+        assert_eq!(skipped.get(), 2);
+    }
+
+    #[test]
+    fn test_peek_type() {
+        let doc = to_string(&vec![1u8, 2, 3]);
+        let mut de = Deserializer::new(&doc);
+        assert_eq!(de.peek_type().unwrap(), Some(Type::Seq(Some(3))));
+        assert_eq!(de.peek_type().unwrap(), Some(Type::Seq(Some(3))));
+        assert_eq!(Vec::<u8>::deserialize(&mut de).unwrap(), vec![1, 2, 3]);
+        assert_eq!(de.peek_type().unwrap(), None);
+
+        let doc = to_string(&Color { r: 1, g: 2, b: 3 });
+        let mut de = Deserializer::new(&doc);
+        assert_eq!(de.peek_type().unwrap(), Some(Type::Struct("Color", 3)));
+
+        let mut de = Deserializer::new("[7](serde://u8)\n");
+        assert_eq!(de.peek_type().unwrap(), Some(Type::U8));
+        assert_eq!(u8::deserialize(&mut de).unwrap(), 7);
+
+        let mut de = Deserializer::new("1. [Seq](serde://seq/1\n");
+        assert!(matches!(de.peek_type(), Err(Error::UnterminatedLink)));
+    }
+
+    #[test]
+    fn test_struct_missing_field() {
+        #[derive(Serialize)]
+        #[serde(rename = "Color")]
+        struct Partial {
+            r: u8,
+            b: u8,
+        }
+
+        // serde's derive finds the missing field, but it's reported as our own error
+        let doc = to_string(&Partial { r: 1, b: 255 });
+        let err = Color::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+        assert!(matches!(err, Error::MissingField("g")));
+        assert_eq!(err.to_string(), "Missing field `g`");
+    }
+
+    #[test]
+    fn test_struct_unknown_field() {
+        #[derive(Serialize)]
+        #[serde(rename = "Color")]
+        struct Extra {
+            r: u8,
+            g: u8,
+            b: u8,
+            a: u8,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename = "Color", deny_unknown_fields)]
+        struct Strict {
+            r: u8,
+            g: u8,
+            b: u8,
+        }
+
+        let doc = to_string(&Extra {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 4,
+        });
+        assert_eq!(
+            Color::deserialize(&mut Deserializer::new(&doc)).unwrap(),
+            Color { r: 1, g: 2, b: 3 }
+        );
+        let err = Strict::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+        assert!(err.to_string().contains("unknown field `a`"), "{}", err);
+    }
+
+    #[test]
+    fn test_struct_renamed() {
+        #[derive(Serialize)]
+        struct Colour {
+            r: u8,
+            g: u8,
+            b: u8,
+        }
+
+        // Documents outlive the names of the structs which wrote them
+        let doc = to_string(&Colour { r: 1, g: 2, b: 3 });
+        assert_eq!(
+            Color::deserialize(&mut Deserializer::new(&doc)).unwrap(),
+            Color { r: 1, g: 2, b: 3 }
+        );
+    }
+
+    #[test]
+    fn test_struct_wrong_type() {
+        #[derive(Serialize)]
+        enum E {
+            V { r: u8, g: u8, b: u8 },
+        }
+
+        let doc = to_string(&E::V { r: 1, g: 2, b: 3 });
+        let err = Color::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+        assert!(matches!(err, Error::CustomDeserializeError(..)));
+    }
+
+    #[test]
+    fn test_recovery_seq() {
+        let doc = to_string(&vec![1u8, 2, 3]).replace("[2]", "[two]");
+
+        let errors = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&errors);
+        let mut de = Deserializer::new(&doc).with_recovery(move |_| counter.set(counter.get() + 1));
+        let value = Vec::<u8>::deserialize(&mut de).unwrap();
+        assert_eq!(value, vec![1, 3]);
+        assert_eq!(errors.get(), 1);
+
+        assert!(Vec::<u8>::deserialize(&mut Deserializer::new(&doc)).is_err());
+
+        // As are elements which are lists with a malformed header, along with what's in them
+        let value = vec![vec![1u8], vec![2, 3], vec![4]];
+        let doc = to_string(&value).replacen("serde://seq/2", "serde://seq/x", 1);
+        let errors = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&errors);
+        let mut de = Deserializer::new(&doc).with_recovery(move |_| counter.set(counter.get() + 1));
+        let value = Vec::<Vec<u8>>::deserialize(&mut de).unwrap();
+        assert_eq!(value, vec![vec![1], vec![4]]);
+        assert_eq!(errors.get(), 1);
+    }
+
+    #[test]
+    fn test_recovery_map() {
+        let map: BTreeMap<u8, u8> = vec![(1, 10), (2, 20), (3, 30)].into_iter().collect();
+        let doc = to_string(&map).replace("[2]", "[two]");
+
+        let errors = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&errors);
+        let mut de = Deserializer::new(&doc).with_recovery(move |_| counter.set(counter.get() + 1));
+        let value = BTreeMap::<u8, u8>::deserialize(&mut de).unwrap();
+        assert_eq!(value, vec![(1, 10), (3, 30)].into_iter().collect());
+        assert_eq!(errors.get(), 1);
+
+        // Malformed values skip their entry just the same
+        let doc = to_string(&map).replace("[20]", "[twenty]");
+        let errors = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&errors);
+        let mut de = Deserializer::new(&doc).with_recovery(move |_| counter.set(counter.get() + 1));
+        let value = BTreeMap::<u8, u8>::deserialize(&mut de).unwrap();
+        assert_eq!(value, vec![(1, 10), (3, 30)].into_iter().collect());
+        assert_eq!(errors.get(), 1);
+    }
+
+    #[test]
+    fn test_recovery_map_runs() {
+        fn recover(doc: &str) -> (BTreeMap<u8, u8>, usize) {
+            let errors = Rc::new(Cell::new(0));
+            let counter = Rc::clone(&errors);
+            let mut de =
+                Deserializer::new(doc).with_recovery(move |_| counter.set(counter.get() + 1));
+            let value = BTreeMap::<u8, u8>::deserialize(&mut de).unwrap();
+            (value, errors.get())
+        }
+
+        let map: BTreeMap<u8, u8> = vec![(1, 10), (2, 20)].into_iter().collect();
+        let doc = to_string(&map);
+        let (header, rest) = doc.split_at(doc.find('\n').unwrap() + 1);
+        let (first, second) = rest.split_at(rest.find("\n*").unwrap() + 1);
+
+        // Far more bad entries in a row than there's stack for recursing over
+        let bad = "* \n    0. [x](serde://u8)\n    1. [0](serde://u8)\n".repeat(10_000);
+        let doc = format!("{}{}{}{}", header, first, bad, second);
+        assert_eq!(recover(&doc), (map.clone(), 10_000));
+
+        // Entries which aren't a key and a value at all, whatever they hold
+        let bad = "* \n    * [3](serde://u8)\n        1. [30](serde://u8)\n* [4](serde://u8)\n";
+        let doc = format!("{}{}{}{}", header, first, bad, second);
+        assert_eq!(recover(&doc), (map, 2));
+    }
+
+    #[test]
+    fn test_struct_alias() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Renamed {
+            #[serde(alias = "old_name")]
+            new_name: u8,
+        }
+
+        let doc = to_string(&Renamed { new_name: 42 });
+        assert!(doc.contains("[new\\_name](serde://string)"));
+        let doc = doc.replace("[new\\_name]", "[old\\_name]");
+
+        let value = Renamed::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(value, Renamed { new_name: 42 });
+    }
+
+    #[test]
+    fn test_enum() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Color {
+            Unit,
+            Newtype(u8),
+            Tuple(u8, u8),
+            Rgb { r: u8, g: u8, b: u8 },
+        }
+
+        for value in [
+            Color::Unit,
+            Color::Newtype(1),
+            Color::Tuple(1, 2),
+            Color::Rgb { r: 1, g: 2, b: 3 },
+        ] {
+            let doc = to_string(&value);
+            assert_eq!(
+                Color::deserialize(&mut Deserializer::new(&doc)).unwrap(),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_unit_variant_with_data() {
+        // The same enum, before and after its `A` variant lost its data
+        #[derive(Serialize)]
+        #[serde(rename = "E")]
+        enum Before {
+            A(()),
+            #[serde(rename = "A")]
+            Tuple(),
+            #[serde(rename = "A")]
+            Struct {},
+            #[serde(rename = "A")]
+            Full(u8),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum E {
+            A,
+            B(u8),
+        }
+
+        for value in [Before::A(()), Before::Tuple(), Before::Struct {}] {
+            let doc = to_string(&vec![value]);
+            let parsed = Vec::<E>::deserialize(&mut Deserializer::new(&doc)).unwrap();
+            assert_eq!(parsed, vec![E::A]);
+        }
+
+        let doc = to_string(&vec![Before::Full(1)]);
+        assert!(Vec::<E>::deserialize(&mut Deserializer::new(&doc)).is_err());
+
+        // Unit variants written as such still work alongside the others
+        let doc = to_string(&vec![E::A, E::B(2)]);
+        let parsed = Vec::<E>::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert_eq!(parsed, vec![E::A, E::B(2)]);
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut buf = Vec::new();
+        vec![vec![0u8; 100]]
+            .serialize(&mut Serializer::new(&mut buf).summarize_over(16))
+            .unwrap();
+        let doc = String::from_utf8(buf).unwrap();
+
+        let err = Vec::<Vec<u8>>::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+        assert!(matches!(err, Error::Summarized));
+    }
+
+    #[test]
+    fn test_bytes() {
+        for buf in [&b""[..], b"\xfb\xff", b"hello world"] {
+            let doc = to_string(&serde_bytes::Bytes::new(buf));
+            let value = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(&doc)).unwrap();
+            assert_eq!(value.as_ref(), buf);
+        }
+    }
+
+    #[test]
+    fn test_hex_bytes() {
+        let mut doc = Vec::new();
+        let buf = serde_bytes::Bytes::new(b"\x00\xca\xfe");
+        buf.serialize(&mut Serializer::new(&mut doc).with_hex_bytes(true))
+            .unwrap();
+        let doc = String::from_utf8(doc).unwrap();
+        assert_eq!(doc, "[00cafe](serde://bytes/hex)\n");
+
+        let upper = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(
+            "[00CAFE](serde://bytes/hex)\n",
+        ));
+        assert_eq!(upper.unwrap().as_ref(), buf.as_ref());
+
+        for text in ["0", "0g", "+1", "éé"] {
+            let doc = format!("[{}](serde://bytes/hex)\n", text);
+            let err = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(&doc)).unwrap_err();
+            assert_eq!(
+                err,
+                Error::HexDecodeError {
+                    uri: "serde://bytes/hex".to_owned(),
+                    snippet: text.to_owned(),
+                }
+            );
+        }
+        assert_eq!(
+            from_str::<serde_bytes::ByteBuf>("[00](serde://bytes/hax)\n")
+                .unwrap_err()
+                .unlocated(),
+            &Error::TypeParseError(ty::ParseError::UnknownType)
+        );
+    }
+
+    #[test]
+    fn test_tiny_bytes() {
+        for buf in [&b""[..], b"\x00", b"\xff"] {
+            let value = vec![
+                serde_bytes::ByteBuf::from(buf),
+                serde_bytes::ByteBuf::from(buf),
+            ];
+            for chunk_size in [None, Some(1)] {
+                let mut doc = Vec::new();
+                let mut serializer = Serializer::new(&mut doc);
+                if let Some(chunk_size) = chunk_size {
+                    serializer = serializer.with_bytes_chunk_size(chunk_size);
+                }
+                value.serialize(&mut serializer).unwrap();
+                let doc = String::from_utf8(doc).unwrap();
+
+                let parsed = from_str::<Vec<serde_bytes::ByteBuf>>(&doc).unwrap();
+                assert_eq!(parsed, value, "{}", doc);
+            }
+        }
+
+        assert!(to_string(&serde_bytes::Bytes::new(b"")).starts_with("[](serde://bytes)\n"));
+    }
+
+    #[test]
+    fn test_fenced_bytes() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Blob {
+            #[serde(with = "serde_bytes")]
+            data: Vec<u8>,
+            after: u8,
+        }
+
+        for len in [0, 5, 57, 200] {
+            let data = (0..len).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+            let value = vec![Blob {
+                data: data.clone(),
+                after: 1,
+            }];
+
+            for fenced_bytes in [false, true] {
+                let mut doc = Vec::new();
+                value
+                    .serialize(&mut Serializer::new(&mut doc).with_fenced_bytes(fenced_bytes))
+                    .unwrap();
+                let doc = String::from_utf8(doc).unwrap();
+                assert_eq!(doc.contains("```base64\n"), fenced_bytes, "{}", doc);
+                assert_eq!(
+                    Vec::<Blob>::deserialize(&mut Deserializer::new(&doc)).unwrap(),
+                    value
+                );
+
+                let mut doc = Vec::new();
+                serde_bytes::Bytes::new(&data)
+                    .serialize(
+                        &mut Serializer::new(&mut doc)
+                            .with_fenced_bytes(fenced_bytes)
+                            .with_bytes_chunk_size(16),
+                    )
+                    .unwrap();
+                let doc = String::from_utf8(doc).unwrap();
+                let parsed = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(&doc));
+                assert_eq!(parsed.unwrap().into_vec(), data);
+            }
+        }
+
+        let doc = "[5 bytes](serde://bytes)\n```base64\nAQID\n";
+        let err = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(doc)).unwrap_err();
+        assert_eq!(err, Error::UnterminatedCodeBlock);
+
+        // Including when it's written without a scheme
+        let doc = "[3 bytes](bytes)\n```base64\nAQID\n```\n";
+        let parsed =
+            serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(doc).with_bare_uris(true));
+        assert_eq!(parsed.unwrap().into_vec(), [1, 2, 3]);
+        assert!(serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(doc)).is_err());
+
+        // Nothing but bytes may have its value in a block
+        for doc in [
+            "[a](serde://string)\n```\nb\n```\n",
+            "[1](serde://u8)\n```\n2\n```\n",
+        ] {
+            let err = serde_value::Value::deserialize(&mut Deserializer::new(doc)).unwrap_err();
+            assert_eq!(err, Error::UnexpectedCodeBlock, "{:?}", doc);
+        }
+    }
+
+    #[test]
+    fn test_byte_chunks() {
+        const CHUNK_SIZE: usize = 64 * 1024;
+        let buf = (0..4 * 1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let mut doc = Vec::new();
+        serde_bytes::Bytes::new(&buf)
+            .serialize(&mut Serializer::new(&mut doc).with_bytes_chunk_size(CHUNK_SIZE))
+            .unwrap();
+        let doc = String::from_utf8(doc).unwrap();
+
+        // Each chunk gets its own link, none of which is larger than a chunk's worth of base64
+        let links = Reader::new(&doc)
+            .map(Result::unwrap)
+            .filter_map(|item| match item {
+                Item::Link { text, .. } => Some(text.len()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(links.len(), 1 + buf.len() / CHUNK_SIZE);
+        assert!(links.iter().all(|&len| len <= CHUNK_SIZE * 4 / 3 + 4));
+
+        let value = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(&doc)).unwrap();
+        assert!(value.as_ref() == &buf[..]);
+    }
+
+    #[test]
+    fn test_minimize_int_widths() {
+        use serde_value::Value;
+
+        let cases = [
+            ("[5](serde://u64)\n", Value::U8(5)),
+            ("[300](serde://u32)\n", Value::U16(300)),
+            ("[-5](serde://i64)\n", Value::I8(-5)),
+            ("[-70000](serde://i128)\n", Value::I32(-70000)),
+            ("[5](serde://i8)\n", Value::I8(5)),
+        ];
+
+        for (doc, expected) in cases.iter() {
+            let mut de = Deserializer::new(doc).with_minimize_int_widths(true);
+            assert_eq!(Value::deserialize(&mut de).unwrap(), *expected);
+        }
+
+        let value = Value::deserialize(&mut Deserializer::new("[5](serde://u64)\n")).unwrap();
+        assert_eq!(value, Value::U64(5));
+
+        // The declared width is still enforced
+        let mut de = Deserializer::new("[300](serde://u8)\n").with_minimize_int_widths(true);
+        assert!(Value::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn test_locate() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let doc =
+            to_string(&vec![(1, 2), (3, 4)]).replace("[4](serde://i32)", "[four](serde://i32)");
+        let err = from_str::<Vec<Point>>(&doc).unwrap_err();
+
+        assert!(
+            matches!(&err, Error::Located { error, line: 9, column: 9, .. } if matches!(**error, Error::ParseIntError(_)))
+        );
+        assert_eq!(
+            err.to_string(),
+            concat!(
+                "invalid digit found in string\n",
+                " --> line 9, column 9\n",
+                "  |\n",
+                "9 |     2. [four](serde://i32)\n",
+                "  |         ^^^^",
+            )
+        );
+
+        // Only the text's own brackets end it
+        let err = from_str::<u8>("[a\\]b\\\\](serde://u8)\n").unwrap_err();
+        assert!(err.to_string().ends_with("\n  |  ^^^^^^"), "{}", err);
+
+        // Errors about the type point at the URI instead
+        let doc = to_string(&vec![(1, 2), (3, 4)]).replace("serde://i32)\n", "serde://i33)\n");
+        let err = from_str::<Vec<Point>>(&doc).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("\n4 |     1. [1](serde://i33)\n  |            ^^^^^^^^^^^"),
+            "{}",
+            err
+        );
+
+        // Errors from reading the document point at where reading stopped
+        let indented = format!("  {}", to_string(&vec![1u8, 2]));
+        let err = from_str::<Vec<u8>>(&indented).unwrap_err();
+        assert!(
+            matches!(&err, Error::Located { error, line: 2, column: 1, .. } if **error == Error::UnexpectedIndent),
+            "{:?}",
+            err
+        );
+
+        // There's nothing to point at before the first link, or without the source
+        assert_eq!(from_str::<u8>("").unwrap_err(), Error::UnexpectedEOF);
+
+        let items = Reader::tokenize(&doc).unwrap();
+        let mut de = Deserializer::new_unbuffered(&items);
+        let err = Vec::<Point>::deserialize(&mut de).unwrap_err();
+        assert!(matches!(de.locate(err), Error::TypeParseError(_)));
+    }
+
+    #[test]
+    fn test_tuple_len() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Pair(u8, u8);
+
+        let doc = to_string(&(1u8, 2u8, 3u8));
+        let err = from_str::<(u8, u8)>(&doc).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid length 3, expected a tuple of size 2"
+        );
+        assert_eq!(
+            from_str::<Pair>(&doc).unwrap_err().to_string(),
+            "invalid length 3, expected tuple struct Pair"
+        );
+        assert!(from_str::<[u8; 2]>(&doc).is_err());
+
+        let doc = to_string(&(1u8,));
+        let err = from_str::<(u8, u8)>(&doc).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid length 1, expected a tuple of size 2"
+        );
+        assert!(from_str::<Pair>(&doc).is_err());
+
+        // Sequences of the right length are fine, others aren't
+        assert_eq!(
+            from_str::<(u8, u8)>(&to_string(&vec![1u8, 2])).unwrap(),
+            (1, 2)
+        );
+        assert_eq!(
+            from_str::<[u8; 3]>(&to_string(&vec![1u8, 2, 3])).unwrap(),
+            [1, 2, 3]
+        );
+        assert!(from_str::<[u8; 3]>(&to_string(&vec![1u8, 2])).is_err());
+        assert_eq!(
+            from_str::<Pair>(&to_string(&(1u8, 2u8))).unwrap(),
+            Pair(1, 2)
+        );
+    }
+
+    #[test]
+    fn test_invalid_char() {
+        assert_eq!(from_str::<char>("[é](serde://char)\n").unwrap(), 'é');
+
+        let err = from_str::<char>("[ab](serde://char)\n").unwrap_err();
+        assert_eq!(err.unlocated(), &Error::InvalidChar("ab".to_owned()));
+        assert_eq!(
+            err.unlocated().to_string(),
+            "Expected exactly one character, found 2 in `ab`"
+        );
+
+        // Combining characters are separate scalars
+        let err = from_str::<char>("[e\u{301}](serde://char)\n").unwrap_err();
+        assert_eq!(
+            err.unlocated().to_string(),
+            "Expected exactly one character, found 2 in `e\u{301}`"
+        );
+
+        let err = from_str::<char>("[](serde://char)\n").unwrap_err();
+        assert_eq!(
+            err.unlocated().to_string(),
+            "Expected exactly one character, found 0 in ``"
+        );
+    }
+
+    #[test]
+    fn test_bare_uris() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Flags {
+            on: bool,
+            name: String,
+        }
+
+        let bare = "* [Struct Flags of length 2](struct/Flags/2)\n\
+                    * \n    0. [on](string)\n    1. [true](bool)\n\
+                    * \n    0. [name](serde://string)\n    1. [x](string)\n";
+        let flags = Flags {
+            on: true,
+            name: "x".to_owned(),
+        };
+
+        let mut de = Deserializer::new(bare).with_bare_uris(true);
+        assert_eq!(Flags::deserialize(&mut de).unwrap(), flags);
+
+        assert_eq!(
+            from_str::<bool>("[true](bool)\n").unwrap_err().unlocated(),
+            &Error::TypeParseError(ty::ParseError::UnknownSchema("serde"))
+        );
+        let mut de = Deserializer::new("[true](bool)\n").with_bare_uris(true);
+        assert!(bool::deserialize(&mut de).unwrap());
+
+        for bare_uris in [false, true] {
+            let mut de = Deserializer::new("[true](serde://bool)\n").with_bare_uris(bare_uris);
+            assert!(bool::deserialize(&mut de).unwrap());
+        }
+
+        // Other schemes are still rejected
+        let mut de = Deserializer::new("[true](https://bool)\n").with_bare_uris(true);
+        assert!(bool::deserialize(&mut de).is_err());
+    }
+
+    #[test]
+    fn test_lenient_bools() {
+        let cases = [
+            ("1", true),
+            ("0", false),
+            ("true", true),
+            ("false", false),
+            ("yes", true),
+            ("no", false),
+            ("True", true),
+            ("NO", false),
+        ];
+
+        for (text, expected) in cases.iter() {
+            let doc = format!("[{}](serde://bool)\n", text);
+            let mut de = Deserializer::new(&doc).with_lenient_bools(true);
+            assert_eq!(bool::deserialize(&mut de).unwrap(), *expected);
+        }
+
+        let mut de = Deserializer::new("[maybe](serde://bool)\n").with_lenient_bools(true);
+        let err = bool::deserialize(&mut de).unwrap_err();
+        assert_eq!(err, Error::InvalidBool("maybe".to_owned()));
+        assert_eq!(err.to_string(), "Invalid boolean `maybe`");
+
+        // Strict parsing is the default
+        for text in ["1", "True", "yes", ""] {
+            let doc = format!("[{}](serde://bool)\n", text);
+            assert_eq!(
+                from_str::<bool>(&doc).unwrap_err().unlocated(),
+                &Error::InvalidBool(text.to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn test_lenient_options() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Settings {
+            name: Option<String>,
+            port: Option<u16>,
+            tags: Option<Vec<String>>,
+            user: Option<String>,
+        }
+
+        // Every field is there, but only in spirit
+        let doc = "\
+* [Struct Settings of length 4](serde://struct/Settings/4)
+* 
+    0. [name](serde://string)
+    1. [](serde://string)
+* 
+    0. [port](serde://string)
+    1. [None](none)
+* 
+    0. [tags](serde://string)
+    1. 
+* 
+    0. [user](serde://string)
+    1. [NONE](serde://string)
+";
+        let none = Settings {
+            name: None,
+            port: None,
+            tags: None,
+            user: None,
+        };
+        let mut de = Deserializer::new(doc).with_lenient_options(true);
+        assert_eq!(Settings::deserialize(&mut de).unwrap(), none);
+        assert!(from_str::<Settings>(doc).is_err());
+
+        // Values without a `serde://some` list around them are there
+        let doc = doc
+            .replace("[](serde://string)", "[mml](serde://string)")
+            .replace("[None](none)", "[8080](serde://u16)");
+        let mut de = Deserializer::new(&doc).with_lenient_options(true);
+        let settings = Settings::deserialize(&mut de).unwrap();
+        assert_eq!(settings.name.as_deref(), Some("mml"));
+        assert_eq!(settings.port, Some(8080));
+
+        // What the serializer writes means the same as ever
+        let value: Vec<Option<Option<u8>>> = vec![Some(Some(1)), Some(None), None];
+        let doc = crate::ser::to_string(&value).unwrap();
+        let mut de = Deserializer::new(&doc).with_lenient_options(true);
+        assert_eq!(
+            Vec::<Option<Option<u8>>>::deserialize(&mut de).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_bad_base64() {
+        let doc = "[not\\_base64!!and\\_then\\_some](serde://bytes)\n";
+        let err = serde_bytes::ByteBuf::deserialize(&mut Deserializer::new(doc)).unwrap_err();
+
+        match &err {
+            Error::B64DecodeError { uri, snippet, .. } => {
+                assert_eq!(uri, "serde://bytes");
+                assert_eq!(snippet, "not_base64!!and_…");
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+
+        let message = err.to_string();
+        assert!(message.contains("bytes field"), "{}", message);
+        assert!(message.contains("not_base64"), "{}", message);
+    }
+
+    #[test]
+    fn test_identifiers() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Odd {
+            #[serde(rename = "1. [not](a link)")]
+            escaped: u8,
+            plain: u8,
+        }
+
+        let odd = Odd {
+            escaped: 1,
+            plain: 2,
+        };
+        assert_eq!(from_str::<Odd>(&to_string(&odd)).unwrap(), odd);
+
+        let doc = to_string(&odd).replace("serde://", "custom://");
+        let mut de = Deserializer::new(&doc).with_scheme("custom");
+        assert_eq!(Odd::deserialize(&mut de).unwrap(), odd);
+
+        // Identifiers which aren't strings still get to the visitor, e.g. field indices
+        let indices = BTreeMap::from([(0u64, 1u8), (1, 2)]);
+        assert_eq!(from_str::<Odd>(&to_string(&indices)).unwrap(), odd);
+    }
+
+    #[test]
+    fn test_nested_borrows() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Inner<'a> {
+            name: &'a str,
+            #[serde(borrow)]
+            tags: Option<Vec<&'a str>>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Outer<'a> {
+            items: Vec<&'a str>,
+            #[serde(borrow)]
+            lookup: BTreeMap<&'a str, &'a str>,
+            #[serde(borrow)]
+            inner: Vec<Inner<'a>>,
+        }
+
+        let value = Outer {
+            items: vec!["first", "second"],
+            lookup: BTreeMap::from([("key", "value")]),
+            inner: vec![Inner {
+                name: "nested",
+                tags: Some(vec!["deep"]),
+            }],
+        };
+        let doc = to_string(&value);
+        let parsed = from_str::<Outer>(&doc).unwrap();
+        assert_eq!(parsed, value);
+
+        let source = doc.as_bytes().as_ptr_range();
+        let strs = parsed
+            .items
+            .iter()
+            .chain(parsed.lookup.keys())
+            .chain(parsed.lookup.values())
+            .chain(Some(&parsed.inner[0].name))
+            .chain(parsed.inner[0].tags.iter().flatten());
+        for s in strs {
+            assert!(source.contains(&s.as_ptr()), "{} isn't borrowed", s);
+        }
+
+        // Text with escapes can't be borrowed, which serde reports as an invalid type
+        let err = from_str::<Outer>(&doc.replace("[second]", "[sec\\-ond]")).unwrap_err();
+        assert!(
+            err.to_string().contains("expected a borrowed string"),
+            "{}",
+            err
+        );
     }
 }
@@ -1,15 +1,39 @@
-use std::fmt;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use core::fmt;
+
+#[cfg(feature = "std")]
 use std::io;
 
 use serde::{de, ser};
 
+/// Everything that can go wrong while reading, writing, serializing or deserializing MML
+///
+/// New variants may be added in minor releases, so matches on it need a fallback arm:
+///
+/// ```
+/// # fn describe(error: serde_mml::Error) -> &'static str {
+/// match error {
+///     serde_mml::Error::UnexpectedEOF => "the document was cut short",
+///     _ => "the document is malformed",
+/// }
+/// # }
+/// ```
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
+    #[cfg(feature = "std")]
     #[error("{0}")]
     IOError(#[from] io::Error),
 
     #[error("{0}")]
-    CustomSerializeError(String),
+    FmtError(#[from] fmt::Error),
+
+    /// An error from a `Serialize` impl, along with where in the value it happened, e.g.
+    /// `.items[2].name` for the `name` field of the third element of the `items` field
+    #[error("{message}{}", At(.path))]
+    CustomSerializeError { message: String, path: String },
 
     #[error("{0}")]
     CustomDeserializeError(String),
@@ -18,27 +42,314 @@ pub enum Error {
     TypeParseError(#[from] crate::ty::ParseError),
 
     #[error("{0}")]
-    ParseCharError(#[from] std::char::ParseCharError),
+    ParseCharError(#[from] core::char::ParseCharError),
 
     #[error("{0}")]
-    ParseIntError(#[from] std::num::ParseIntError),
+    ParseIntError(#[from] core::num::ParseIntError),
 
     #[error("{0}")]
-    ParseBoolError(#[from] std::str::ParseBoolError),
+    ParseBoolError(#[from] core::str::ParseBoolError),
 
     #[error("{0}")]
-    ParseFloatError(#[from] std::num::ParseFloatError),
+    ParseFloatError(#[from] core::num::ParseFloatError),
 
-    #[error("{0}")]
-    B64DecodeError(#[from] base64::DecodeError),
+    #[error("Invalid base64 in bytes field `{uri}` starting with `{snippet}`: {error}")]
+    B64DecodeError {
+        uri: String,
+        snippet: String,
+        // `base64` only implements `Error` for its errors with `std`
+        #[cfg_attr(feature = "std", source)]
+        error: base64::DecodeError,
+    },
+
+    #[error("Invalid hex in bytes field `{uri}` starting with `{snippet}`")]
+    HexDecodeError { uri: String, snippet: String },
 
     #[error("Unexpected EOF")]
     UnexpectedEOF,
+
+    #[error("Unterminated link")]
+    UnterminatedLink,
+
+    #[error("Unterminated code block")]
+    UnterminatedCodeBlock,
+
+    #[error("Code block after a link which isn't for bytes")]
+    UnexpectedCodeBlock,
+
+    #[error("Line indented less than the first line of the document")]
+    UnexpectedIndent,
+
+    #[error("Definition without `**: ` and a link after its key")]
+    UnterminatedDefinition,
+
+    #[error("Invalid UTF-8 at byte {offset}")]
+    InvalidUtf8 { offset: usize },
+
+    #[error("Unexpected character `{0}`")]
+    UnexpectedChar(char),
+
+    #[error("Invalid boolean `{0}`")]
+    InvalidBool(String),
+
+    #[error("Expected exactly one character, found {count} in `{0}`", count = .0.chars().count())]
+    InvalidChar(String),
+
+    #[error("Invalid checkbox `[{0}]`")]
+    InvalidCheckbox(String),
+
+    #[error("Undefined link reference `[{0}]`")]
+    UndefinedReference(String),
+
+    #[error("URI `{0}` can't be written as a link destination")]
+    InvalidUri(String),
+
+    #[error("Key `{0}` can't be written as a definition")]
+    InvalidKey(String),
+
+    #[error("Unexpected item, expected {0}")]
+    UnexpectedItem(&'static str),
+
+    #[error("Recursion limit exceeded")]
+    RecursionLimitExceeded,
+
+    #[error("Missing field `{0}`")]
+    MissingField(&'static str),
+
+    #[error("Map keys and values must alternate, starting with a key")]
+    KeyValueMisorder,
+
+    #[error("Output limit exceeded")]
+    OutputLimitExceeded,
+
+    #[error("Summarized values can't be deserialized")]
+    Summarized,
+
+    /// Another error, along with where in the document it happened, from `de::from_str`,
+    /// `Deserializer::locate` or `md::validate`
+    ///
+    /// The line and column are 1-based, and the excerpt is the line with the place underlined.
+    #[error("{error}\n --> line {line}, column {column}\n{excerpt}")]
+    Located {
+        error: Box<Error>,
+        line: usize,
+        column: usize,
+        excerpt: String,
+    },
+}
+
+/// Formats the path of an error, if it's got one
+struct At<'a>(&'a str);
+
+impl fmt::Display for At<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " at `{}`", self.0)
+        }
+    }
+}
+
+impl Error {
+    /// The error itself, without the `Error::Located` saying where it happened if there's one
+    pub fn unlocated(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error.unlocated(),
+            error => error,
+        }
+    }
+
+    /// Wrap this error in an `Error::Located`, underlining the `len` bytes of `source` at `offset`
+    pub(crate) fn located(self, source: &str, offset: usize, len: usize) -> Self {
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let line = source[..offset].matches('\n').count() + 1;
+        let column = source[line_start..offset].chars().count() + 1;
+
+        // Underline at least one character, even at the end of a line
+        let underlined = source[offset..].get(..len).map_or(1, |s| s.chars().count());
+
+        let gutter = line.to_string().len();
+        let excerpt = alloc::format!(
+            "{:gutter$} |\n{} | {}\n{:gutter$} | {:column$}{}",
+            "",
+            line,
+            &source[line_start..line_end],
+            "",
+            "",
+            "^".repeat(underlined.max(1)),
+            gutter = gutter,
+            column = column - 1,
+        );
+        Error::Located {
+            error: Box::new(self),
+            line,
+            column,
+            excerpt,
+        }
+    }
+
+    /// Note that the error happened inside `segment` of the value, as the error bubbles out of it
+    pub(crate) fn at(self, segment: fmt::Arguments<'_>) -> Self {
+        match self {
+            Error::CustomSerializeError { message, path } => Error::CustomSerializeError {
+                message,
+                path: alloc::format!("{}{}", segment, path),
+            },
+            error => error,
+        }
+    }
+}
+
+/// Errors are equal if they're the same variant holding equal values, except for I/O errors which
+/// only need to be of the same kind
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        use Error::*;
+
+        // Matching on `self` alone, without a wildcard, so that new variants can't be forgotten
+        match self {
+            #[cfg(feature = "std")]
+            IOError(a) => matches!(other, IOError(b) if a.kind() == b.kind()),
+            FmtError(a) => matches!(other, FmtError(b) if a == b),
+            CustomSerializeError { message, path } => matches!(
+                other,
+                CustomSerializeError { message: b_message, path: b_path }
+                    if message == b_message && path == b_path
+            ),
+            CustomDeserializeError(a) => matches!(other, CustomDeserializeError(b) if a == b),
+            TypeParseError(a) => matches!(other, TypeParseError(b) if a == b),
+            ParseCharError(a) => matches!(other, ParseCharError(b) if a == b),
+            ParseIntError(a) => matches!(other, ParseIntError(b) if a == b),
+            ParseBoolError(a) => matches!(other, ParseBoolError(b) if a == b),
+            ParseFloatError(a) => matches!(other, ParseFloatError(b) if a == b),
+            B64DecodeError {
+                uri,
+                snippet,
+                error,
+            } => matches!(
+                other,
+                B64DecodeError { uri: b_uri, snippet: b_snippet, error: b_error }
+                    if uri == b_uri && snippet == b_snippet && error == b_error
+            ),
+            HexDecodeError { uri, snippet } => matches!(
+                other,
+                HexDecodeError { uri: b_uri, snippet: b_snippet }
+                    if uri == b_uri && snippet == b_snippet
+            ),
+            UnexpectedEOF => matches!(other, UnexpectedEOF),
+            UnterminatedLink => matches!(other, UnterminatedLink),
+            UnterminatedCodeBlock => matches!(other, UnterminatedCodeBlock),
+            UnexpectedCodeBlock => matches!(other, UnexpectedCodeBlock),
+            UnexpectedIndent => matches!(other, UnexpectedIndent),
+            UnterminatedDefinition => matches!(other, UnterminatedDefinition),
+            InvalidUtf8 { offset } => matches!(other, InvalidUtf8 { offset: b } if offset == b),
+            UnexpectedChar(a) => matches!(other, UnexpectedChar(b) if a == b),
+            InvalidBool(a) => matches!(other, InvalidBool(b) if a == b),
+            InvalidChar(a) => matches!(other, InvalidChar(b) if a == b),
+            InvalidCheckbox(a) => matches!(other, InvalidCheckbox(b) if a == b),
+            UndefinedReference(a) => matches!(other, UndefinedReference(b) if a == b),
+            InvalidUri(a) => matches!(other, InvalidUri(b) if a == b),
+            InvalidKey(a) => matches!(other, InvalidKey(b) if a == b),
+            UnexpectedItem(a) => matches!(other, UnexpectedItem(b) if a == b),
+            RecursionLimitExceeded => matches!(other, RecursionLimitExceeded),
+            MissingField(a) => matches!(other, MissingField(b) if a == b),
+            KeyValueMisorder => matches!(other, KeyValueMisorder),
+            OutputLimitExceeded => matches!(other, OutputLimitExceeded),
+            Summarized => matches!(other, Summarized),
+            Located {
+                error,
+                line,
+                column,
+                excerpt,
+            } => matches!(
+                other,
+                Located { error: b_error, line: b_line, column: b_column, excerpt: b_excerpt }
+                    if error == b_error
+                        && line == b_line
+                        && column == b_column
+                        && excerpt == b_excerpt
+            ),
+        }
+    }
+}
+
+/// I/O errors can't be cloned, so their clones only keep their kind and message
+impl Clone for Error {
+    fn clone(&self) -> Self {
+        use Error::*;
+
+        match self {
+            #[cfg(feature = "std")]
+            IOError(error) => IOError(io::Error::new(error.kind(), error.to_string())),
+            FmtError(error) => FmtError(*error),
+            CustomSerializeError { message, path } => CustomSerializeError {
+                message: message.clone(),
+                path: path.clone(),
+            },
+            CustomDeserializeError(message) => CustomDeserializeError(message.clone()),
+            TypeParseError(error) => TypeParseError(error.clone()),
+            ParseCharError(error) => ParseCharError(error.clone()),
+            ParseIntError(error) => ParseIntError(error.clone()),
+            ParseBoolError(error) => ParseBoolError(error.clone()),
+            ParseFloatError(error) => ParseFloatError(error.clone()),
+            B64DecodeError {
+                uri,
+                snippet,
+                error,
+            } => B64DecodeError {
+                uri: uri.clone(),
+                snippet: snippet.clone(),
+                error: error.clone(),
+            },
+            HexDecodeError { uri, snippet } => HexDecodeError {
+                uri: uri.clone(),
+                snippet: snippet.clone(),
+            },
+            UnexpectedEOF => UnexpectedEOF,
+            UnterminatedLink => UnterminatedLink,
+            UnterminatedCodeBlock => UnterminatedCodeBlock,
+            UnexpectedCodeBlock => UnexpectedCodeBlock,
+            UnexpectedIndent => UnexpectedIndent,
+            UnterminatedDefinition => UnterminatedDefinition,
+            InvalidUtf8 { offset } => InvalidUtf8 { offset: *offset },
+            UnexpectedChar(ch) => UnexpectedChar(*ch),
+            InvalidBool(text) => InvalidBool(text.clone()),
+            InvalidChar(text) => InvalidChar(text.clone()),
+            InvalidCheckbox(text) => InvalidCheckbox(text.clone()),
+            UndefinedReference(n) => UndefinedReference(n.clone()),
+            InvalidUri(uri) => InvalidUri(uri.clone()),
+            InvalidKey(key) => InvalidKey(key.clone()),
+            UnexpectedItem(expected) => UnexpectedItem(expected),
+            RecursionLimitExceeded => RecursionLimitExceeded,
+            MissingField(field) => MissingField(field),
+            KeyValueMisorder => KeyValueMisorder,
+            OutputLimitExceeded => OutputLimitExceeded,
+            Summarized => Summarized,
+            Located {
+                error,
+                line,
+                column,
+                excerpt,
+            } => Located {
+                error: error.clone(),
+                line: *line,
+                column: *column,
+                excerpt: excerpt.clone(),
+            },
+        }
+    }
 }
 
 impl ser::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
-        Self::CustomSerializeError(msg.to_string())
+        Self::CustomSerializeError {
+            message: msg.to_string(),
+            path: String::new(),
+        }
     }
 }
 
@@ -46,6 +357,53 @@ impl de::Error for Error {
     fn custom<T: fmt::Display>(msg: T) -> Self {
         Self::CustomDeserializeError(msg.to_string())
     }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::MissingField(field)
+    }
 }
 
-pub type Result<T, E = Error> = std::result::Result<T, E>;
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::from_str;
+
+    #[test]
+    fn test_error_eq() {
+        assert_eq!(from_str::<u8>("").unwrap_err(), Error::UnexpectedEOF);
+        assert_eq!(
+            from_str::<u8>("[x](serde://u8").unwrap_err().unlocated(),
+            &Error::UnterminatedLink
+        );
+        assert_eq!(
+            from_str::<bool>("[maybe] <serde://bool>\n")
+                .unwrap_err()
+                .unlocated(),
+            &Error::InvalidCheckbox("maybe".to_owned())
+        );
+        assert_eq!(
+            from_str::<u8>("[1](serde://nope)\n")
+                .unwrap_err()
+                .unlocated(),
+            &Error::TypeParseError(crate::ty::ParseError::UnknownType)
+        );
+        assert_eq!(
+            from_str::<u8>("[x](serde://u8)\n").unwrap_err().unlocated(),
+            &Error::ParseIntError("x".parse::<u8>().unwrap_err())
+        );
+        assert_ne!(Error::UnexpectedChar('a'), Error::UnexpectedChar('b'));
+        assert_ne!(Error::UnexpectedEOF, Error::UnterminatedLink);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_error_eq() {
+        let a = Error::from(io::Error::other("a"));
+        let b = Error::from(io::Error::other("b"));
+        let c = Error::from(io::Error::from(io::ErrorKind::BrokenPipe));
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+}
@@ -1,6 +1,10 @@
-// FIXME: we have to choose how we handel escapes cause rn it's wrong
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod error;
-mod ty;
+
+pub mod ty;
 
 pub mod md;
 
@@ -8,6 +12,16 @@ pub mod ser;
 
 pub mod de;
 
+#[cfg(feature = "std")]
+pub mod path;
+
+#[cfg(feature = "std")]
+mod transcode;
+
+pub use error::{Error, Result};
+#[cfg(feature = "std")]
+pub use transcode::{transcode_from_mml, transcode_to_mml};
+
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
@@ -56,6 +70,679 @@ mod tests {
         T::deserialize(&mut de::Deserializer::new(&buf)).unwrap()
     }
 
+    #[test]
+    fn test_unusual_field_names() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "kebab-case")]
+        struct Kebab {
+            my_field: u8,
+            other_field: String,
+            #[serde(rename = "-leading-dash")]
+            leading_dash: bool,
+            #[serde(rename = "1. numbered")]
+            numbered: u8,
+            #[serde(rename = "a.b.c")]
+            dotted: u8,
+            #[serde(rename = "with space")]
+            spaced: u8,
+            #[serde(rename = "* [weird](link)")]
+            weird: u8,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+        struct Screaming {
+            my_field: u8,
+            nested: Kebab,
+        }
+
+        let kebab = Kebab {
+            my_field: 1,
+            other_field: "- not a bullet".to_owned(),
+            leading_dash: true,
+            numbered: 2,
+            dotted: 3,
+            spaced: 4,
+            weird: 5,
+        };
+        let value = vec![Screaming {
+            my_field: 6,
+            nested: kebab,
+        }];
+
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_unusual_type_names() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "Point (2D)")]
+        struct Point {
+            x: u8,
+            y: u8,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "Shape)")]
+        enum Shape {
+            #[serde(rename = "(Dot")]
+            Dot(Point),
+            #[serde(rename = "Line)")]
+            Line(Point, Point),
+            #[serde(rename = "Empty )")]
+            Empty,
+        }
+
+        let value = vec![
+            Shape::Dot(Point { x: 1, y: 2 }),
+            Shape::Line(Point { x: 3, y: 4 }, Point { x: 5, y: 6 }),
+            Shape::Empty,
+        ];
+        let doc = ser::to_string(&value).unwrap();
+        assert!(doc.contains("](<serde://struct/Point (2D)/2>)\n"));
+        assert!(doc.contains("](<serde://tuple_variant/Shape)/Line)/2>)\n"));
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_slashed_type_names() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(rename = "net/Event")]
+        enum Event {
+            #[serde(rename = "Mod::Variant")]
+            Unit,
+            #[serde(rename = "a/b")]
+            Newtype(u8),
+            #[serde(rename = "100%")]
+            Tuple(u8, u8),
+            #[serde(rename = "<struct>")]
+            Struct { x: u8 },
+        }
+
+        let events = vec![
+            Event::Unit,
+            Event::Newtype(1),
+            Event::Tuple(2, 3),
+            Event::Struct { x: 4 },
+        ];
+        let doc = ser::to_string(&events).unwrap();
+        assert!(doc.contains("](serde://unit_variant/net%2FEvent/Mod::Variant)"));
+        assert!(doc.contains("(serde://newtype_variant/net%2FEvent/a%2Fb)"));
+        assert!(doc.contains("(serde://struct_variant/net%2FEvent/%3Cstruct%3E/1)"));
+        assert_eq!(roundtrip(&events), events);
+
+        // Also when deserializing without knowing the type up front
+        let value: SerdeValue = de::from_str(&doc).unwrap();
+        let newtype = vec![(SerdeValue::String("a/b".to_owned()), SerdeValue::U8(1))];
+        match value {
+            SerdeValue::Seq(values) => {
+                assert_eq!(values[0], SerdeValue::String("Mod::Variant".to_owned()));
+                assert_eq!(values[1], SerdeValue::Map(newtype.into_iter().collect()));
+            }
+            value => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_empty_values() {
+        use std::collections::HashMap;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Empties {
+            string: String,
+            seq: Vec<u8>,
+            map: HashMap<String, u8>,
+            unit: (),
+            nested: Vec<Vec<String>>,
+            after: u8,
+        }
+
+        assert_eq!(roundtrip(&String::new()), "");
+        assert_eq!(roundtrip(&Vec::<u8>::new()), Vec::<u8>::new());
+        assert_eq!(roundtrip(&HashMap::<String, u8>::new()), HashMap::new());
+        assert_eq!(roundtrip(&()), ());
+
+        let value = vec![
+            Empties {
+                string: String::new(),
+                seq: vec![],
+                map: HashMap::new(),
+                unit: (),
+                nested: vec![vec![], vec![String::new()], vec![]],
+                after: 1,
+            },
+            Empties {
+                string: "x".to_owned(),
+                seq: vec![0],
+                map: vec![(String::new(), 0)].into_iter().collect(),
+                unit: (),
+                nested: vec![],
+                after: 2,
+            },
+        ];
+        assert_eq!(roundtrip(&value), value);
+
+        // Editors tend to strip the space after the bullets of empty items
+        let doc = ser::to_string(&value)
+            .unwrap()
+            .replace(". \n", ".\n")
+            .replace("* \n", "*\n");
+        assert_eq!(de::from_str::<Vec<Empties>>(&doc).unwrap(), value);
+
+        let value = vec![
+            Some(vec![]),
+            Some(vec![HashMap::<String, Vec<u8>>::new()]),
+            None,
+        ];
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_some_collections() {
+        use std::collections::HashMap;
+
+        let bytes: Option<Vec<u8>> = Some(vec![1, 2, 3]);
+        assert_eq!(roundtrip(&bytes), bytes);
+
+        let map: Option<HashMap<String, Vec<u8>>> = Some(
+            vec![("a".to_owned(), vec![1]), ("b".to_owned(), vec![])]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(roundtrip(&map), map);
+
+        // Each `Some` must consume exactly its own list's end, or the siblings after it go astray
+        let nested: Vec<(Option<Option<Vec<u8>>>, u8)> = vec![
+            (Some(Some(vec![4, 5])), 6),
+            (Some(None), 7),
+            (Some(Some(vec![])), 8),
+            (None, 9),
+        ];
+        assert_eq!(roundtrip(&nested), nested);
+
+        let mut buf = Vec::new();
+        nested
+            .serialize(&mut ser::Serializer::new(&mut buf).with_inline_threshold(4))
+            .unwrap();
+        let buf = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            de::from_str::<Vec<(Option<Option<Vec<u8>>>, u8)>>(&buf).unwrap(),
+            nested
+        );
+    }
+
+    #[test]
+    fn test_none_fields() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct S {
+            a: Option<u8>,
+            b: u8,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Outer {
+            first: Option<S>,
+            list: Option<Vec<S>>,
+            last: S,
+        }
+
+        let value = S { a: None, b: 1 };
+        assert_eq!(
+            ser::to_string(&value).unwrap(),
+            concat!(
+                "* [Struct S of length 2](serde://struct/S/2)\n",
+                "* \n",
+                "    0. [a](serde://string)\n",
+                "    1. [None](serde://none)\n",
+                "* \n",
+                "    0. [b](serde://string)\n",
+                "    1. [1](serde://u8)\n",
+            )
+        );
+        assert_eq!(roundtrip(&value), value);
+
+        let value = vec![
+            Outer {
+                first: None,
+                list: None,
+                last: S { a: None, b: 2 },
+            },
+            Outer {
+                first: Some(S { a: None, b: 3 }),
+                list: Some(vec![S { a: None, b: 4 }, S { a: Some(5), b: 6 }]),
+                last: S { a: Some(7), b: 8 },
+            },
+        ];
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_nested_maps() {
+        use std::collections::HashMap;
+
+        let value = HashMap::from([
+            (
+                "a".to_owned(),
+                HashMap::from([("x".to_owned(), 1u8), ("y".to_owned(), 2)]),
+            ),
+            ("empty".to_owned(), HashMap::new()),
+            ("b".to_owned(), HashMap::from([("z".to_owned(), 3)])),
+        ]);
+        assert_eq!(roundtrip(&value), value);
+
+        // Each level's entries must end up back in the right map, including after an inner one
+        let deeper = vec![
+            HashMap::from([(
+                "outer".to_owned(),
+                HashMap::from([("inner".to_owned(), value.clone())]),
+            )]),
+            HashMap::new(),
+        ];
+        assert_eq!(roundtrip(&deeper), deeper);
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct After {
+            maps: HashMap<String, HashMap<String, u8>>,
+            after: u8,
+        }
+
+        let after = After {
+            maps: value,
+            after: 4,
+        };
+        assert_eq!(roundtrip(&after), after);
+    }
+
+    #[test]
+    fn test_bullet_like_strings() {
+        let value = vec![
+            "* item".to_owned(),
+            "3. step".to_owned(),
+            "- dash".to_owned(),
+            "[x] <serde://bool>".to_owned(),
+            "first\n* second\n    3. third\n".to_owned(),
+            "\n\n1. \n".to_owned(),
+        ];
+        assert_eq!(roundtrip(&value), value);
+
+        let value = vec![("10. ten".to_owned(), vec!["* nested".to_owned()])];
+        assert_eq!(roundtrip(&value), value);
+    }
+
+    #[test]
+    fn test_list_marker_keys() {
+        use std::collections::BTreeMap;
+
+        // Every ASCII punctuation character is escaped, so list markers at the start of link text
+        // never look like bullets to other Markdown parsers either
+        let map = [
+            "* note",
+            "- dash",
+            "+ plus",
+            "1. first",
+            "12) twelve",
+            "# heading",
+            "> quote",
+        ]
+        .iter()
+        .map(|key| (key.to_string(), key.len()))
+        .collect::<BTreeMap<_, _>>();
+        let doc = ser::to_string(&map).unwrap();
+
+        for escaped in [
+            "[\\* note]",
+            "[\\- dash]",
+            "[\\+ plus]",
+            "[1\\. first]",
+            "[12\\) twelve]",
+            "[\\# heading]",
+            "[\\> quote]",
+        ] {
+            assert!(doc.contains(escaped), "{} not in {}", escaped, doc);
+        }
+        assert_eq!(roundtrip(&map), map);
+    }
+
+    #[typetag::serde]
+    trait Shape: std::fmt::Debug {
+        fn area(&self) -> f64;
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Circle {
+        radius: f64,
+    }
+
+    #[typetag::serde]
+    impl Shape for Circle {
+        fn area(&self) -> f64 {
+            std::f64::consts::PI * self.radius * self.radius
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Rect(f64, f64);
+
+    #[typetag::serde]
+    impl Shape for Rect {
+        fn area(&self) -> f64 {
+            self.0 * self.1
+        }
+    }
+
+    #[test]
+    fn test_typetag() {
+        let shapes: Vec<Box<dyn Shape>> =
+            vec![Box::new(Circle { radius: 2.0 }), Box::new(Rect(3.0, 4.0))];
+        let shapes = roundtrip(&shapes);
+
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(format!("{:?}", shapes[0]), "Circle { radius: 2.0 }");
+        assert_eq!(format!("{:?}", shapes[1]), "Rect(3.0, 4.0)");
+        assert_eq!(shapes[1].area(), 12.0);
+    }
+
+    #[test]
+    fn test_tagged_enums() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(tag = "type")]
+        enum Internal {
+            Unit,
+            Struct { id: u32, name: String },
+            Newtype(Inner),
+            Map(std::collections::BTreeMap<String, i8>),
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Inner {
+            tags: Vec<String>,
+            parent: Option<u8>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Unit,
+            Newtype(Vec<u8>),
+            Tuple(u8, char),
+            Struct { ok: bool },
+        }
+
+        let internal = vec![
+            Internal::Unit,
+            Internal::Struct {
+                id: 7,
+                name: "seven".to_owned(),
+            },
+            Internal::Newtype(Inner {
+                tags: vec!["a".to_owned(), "b".to_owned()],
+                parent: Some(1),
+            }),
+            Internal::Map(vec![("x".to_owned(), -1)].into_iter().collect()),
+        ];
+        let doc = ser::to_string(&internal).unwrap();
+        assert!(doc.contains("0. [type](serde://string)\n        1. [Struct](serde://string)\n"));
+        assert_eq!(roundtrip(&internal), internal);
+
+        let adjacent = vec![
+            Adjacent::Unit,
+            Adjacent::Newtype(vec![1, 2]),
+            Adjacent::Tuple(3, 'c'),
+            Adjacent::Struct { ok: true },
+        ];
+        assert_eq!(roundtrip(&adjacent), adjacent);
+
+        // The tag doesn't have to come first
+        let doc = ser::to_string(&Internal::Struct {
+            id: 1,
+            name: "one".to_owned(),
+        })
+        .unwrap();
+        let (header, entries) = doc.split_once("\n* \n").unwrap();
+        let mut entries: Vec<&str> = entries.split("* \n").collect();
+        entries.rotate_left(1);
+        let reordered = format!("{}\n* \n{}", header, entries.join("* \n"));
+        assert_ne!(reordered, doc);
+        assert_eq!(
+            de::from_str::<Internal>(&reordered).unwrap(),
+            Internal::Struct {
+                id: 1,
+                name: "one".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_task_lists() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Todo {
+            name: String,
+            done: bool,
+            steps: Vec<bool>,
+        }
+
+        let value = vec![
+            Todo {
+                name: "[x] not a checkbox".to_owned(),
+                done: true,
+                steps: vec![true, false, true],
+            },
+            Todo {
+                name: "later".to_owned(),
+                done: false,
+                steps: vec![],
+            },
+        ];
+
+        let mut buf = Vec::new();
+        value
+            .serialize(&mut ser::Serializer::new(&mut buf).with_task_lists(true))
+            .unwrap();
+        let buf = String::from_utf8(buf).unwrap();
+        assert!(buf.contains("1. [x] <serde://bool>\n"));
+        assert!(buf.contains("2. [ ] <serde://bool>\n"));
+        assert!(!buf.contains("](serde://bool)"));
+
+        let parsed = Vec::<Todo>::deserialize(&mut de::Deserializer::new(&buf)).unwrap();
+        assert_eq!(parsed, value);
+
+        let bools = vec![false, true, true];
+        let mut buf = Vec::new();
+        bools
+            .serialize(&mut ser::Serializer::new(&mut buf).with_task_lists(true))
+            .unwrap();
+        let buf = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            Vec::<bool>::deserialize(&mut de::Deserializer::new(&buf)).unwrap(),
+            bools
+        );
+    }
+
+    #[cfg(feature = "bignum")]
+    #[test]
+    fn test_bignum() {
+        use rust_decimal::Decimal;
+
+        /// A stand-in for big integer types which serialize as strings of digits
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        #[serde(transparent)]
+        struct BigInt(String);
+
+        let big = BigInt("-1234567890123456789012345678901234567890".to_owned());
+        let decimal = "-79228162514264.337593543950335"
+            .parse::<Decimal>()
+            .unwrap();
+        let value = (big, decimal, "12".to_owned(), "1.2.3".to_owned());
+
+        // Only opted into, since they're still just strings to Serde
+        let doc = ser::to_string(&value).unwrap();
+        assert!(doc.contains("5678901234567890](serde://string)"));
+        assert!(doc.contains("[12](serde://string)"));
+        assert_eq!(roundtrip(&value), value);
+
+        let mut buf = Vec::new();
+        value
+            .serialize(&mut ser::Serializer::new(&mut buf).with_bignum_strings(true))
+            .unwrap();
+        let doc = String::from_utf8(buf).unwrap();
+        assert!(doc.contains("5678901234567890](serde://bignum)"));
+        assert!(doc.contains("950335](serde://decimal)"));
+        assert!(doc.contains("[12](serde://bignum)"));
+        assert!(doc.contains("[1\\.2\\.3](serde://string)"));
+        assert_eq!(
+            <(BigInt, Decimal, String, String)>::deserialize(&mut de::Deserializer::new(&doc))
+                .unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_time() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let durations = vec![
+            Duration::new(0, 0),
+            Duration::new(5, 999_999_999),
+            Duration::new(u64::MAX, 1),
+        ];
+        assert_eq!(roundtrip(&durations), durations);
+
+        let times = vec![
+            UNIX_EPOCH,
+            UNIX_EPOCH + Duration::new(1_600_000_000, 123_456_789),
+            SystemTime::now(),
+        ];
+        assert_eq!(roundtrip(&times), times);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        let times: Vec<DateTime<Utc>> = vec![
+            Utc.timestamp_opt(0, 0).unwrap(),
+            Utc.timestamp_opt(1_600_000_000, 123_456_789).unwrap(),
+            Utc::now(),
+        ];
+        assert_eq!(roundtrip(&times), times);
+    }
+
+    #[test]
+    fn test_byte_arrays() {
+        // Serde has arrays go through `serialize_tuple`, so every byte gets a link of its own
+        let hash: [u8; 16] = core::array::from_fn(|i| (i * 17) as u8);
+        assert_eq!(roundtrip(&hash), hash);
+        let text = ser::to_string(&hash).unwrap();
+        assert!(text.starts_with("0. [Tuple of length 16](serde://tuple/16)\n"));
+        assert_eq!(text.matches("(serde://u8)").count(), 16);
+
+        // Tuples must have exactly the length they're deserialized as, whatever wrote them
+        let short = ser::to_string(&[0u8; 15]).unwrap();
+        let long = ser::to_string(&vec![0u8; 17]).unwrap();
+        for text in [&short, &long] {
+            let error = de::from_str::<[u8; 16]>(text).unwrap_err();
+            assert!(error.to_string().contains("invalid length"), "{}", error);
+        }
+        let seq = ser::to_string(&hash.to_vec()).unwrap();
+        assert_eq!(de::from_str::<[u8; 16]>(&seq).unwrap(), hash);
+
+        // `serde_bytes` makes them a single base64 link, which is what hashes, keys and IDs want
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Key {
+            #[serde(with = "serde_bytes")]
+            id: [u8; 16],
+            digest: serde_bytes::ByteArray<32>,
+        }
+
+        let key = Key {
+            id: hash,
+            digest: serde_bytes::ByteArray::new([0xab; 32]),
+        };
+        assert_eq!(roundtrip(&key), key);
+        let text = ser::to_string(&key).unwrap();
+        assert_eq!(text.matches("(serde://bytes)").count(), 2, "{}", text);
+
+        // Fields can start using it without breaking documents written before, but not go back
+        let text = ser::to_string(&serde_bytes::ByteArray::new(hash)).unwrap();
+        let error = de::from_str::<[u8; 16]>(&text).unwrap_err();
+        assert!(
+            error.to_string().contains("invalid type: byte array"),
+            "{}",
+            error
+        );
+        let bytes: serde_bytes::ByteArray<16> = de::from_str(&seq).unwrap();
+        assert_eq!(bytes.into_array(), hash);
+    }
+
+    #[test]
+    fn test_addresses() {
+        use std::net::{IpAddr, SocketAddr};
+
+        fn textual_forms<T: Serialize>(values: &[T]) -> Vec<String> {
+            let text = ser::to_string(values).unwrap();
+            de::from_str(&text).unwrap()
+        }
+
+        let ips: Vec<IpAddr> = vec![
+            "127.0.0.1".parse().unwrap(),
+            "::1".parse().unwrap(),
+            "fe80::1:2:3:4".parse().unwrap(),
+        ];
+        assert_eq!(roundtrip(&ips), ips);
+        assert_eq!(textual_forms(&ips), ["127.0.0.1", "::1", "fe80::1:2:3:4"]);
+
+        let sockets: Vec<SocketAddr> = vec![
+            "127.0.0.1:8080".parse().unwrap(),
+            "[::1]:443".parse().unwrap(),
+        ];
+        assert_eq!(roundtrip(&sockets), sockets);
+        assert_eq!(textual_forms(&sockets), ["127.0.0.1:8080", "[::1]:443"]);
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_uuid() {
+        use uuid::Uuid;
+
+        let ids = vec![
+            Uuid::nil(),
+            Uuid::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8),
+        ];
+        assert_eq!(roundtrip(&ids), ids);
+
+        let text = ser::to_string(&ids).unwrap();
+        let forms: Vec<String> = de::from_str(&text).unwrap();
+        assert_eq!(forms[1], "67e55044-10b1-426f-9247-bb680e5fe0c8");
+    }
+
+    #[test]
+    fn test_custom_scheme() {
+        let value: Vec<Option<String>> = vec![Some("one".to_owned()), None];
+
+        let mut buf = Vec::new();
+        value
+            .serialize(&mut ser::Serializer::new(&mut buf).with_scheme("myapp"))
+            .unwrap();
+        let buf = String::from_utf8(buf).unwrap();
+        assert!(buf.contains("(myapp://seq/2)"));
+        assert!(!buf.contains("serde://"));
+
+        let mut deserializer = de::Deserializer::new(&buf).with_scheme("myapp");
+        assert_eq!(
+            Vec::<Option<String>>::deserialize(&mut deserializer).unwrap(),
+            value
+        );
+
+        let result = Vec::<Option<String>>::deserialize(&mut de::Deserializer::new(&buf));
+        assert!(matches!(
+            result,
+            Err(Error::TypeParseError(ty::ParseError::UnknownSchema(
+                "serde"
+            )))
+        ));
+    }
+
     proptest! {
         // Property: a Value is equal to itself if roundtripped
         #[test]
@@ -70,5 +757,25 @@ mod tests {
             prop_assume!(value1 != value2);
             prop_assert_ne!(roundtrip(&value1), roundtrip(&value2));
         }
+
+        // Property: bytes come back the same whichever way they're encoded, and in any chunks
+        #[test]
+        fn proptest_bytes_roundtrip(
+            buf in prop::collection::vec(any::<u8>(), 0..100),
+            chunk_size in prop::option::of(1..20usize),
+        ) {
+            let buf = serde_bytes::ByteBuf::from(buf);
+            for (hex, raw) in [(false, false), (true, false), (false, true), (true, true)] {
+                let mut doc = String::new();
+                let mut serializer = ser::Serializer::new(md::FmtOutput(&mut doc))
+                    .with_hex_bytes(hex)
+                    .with_raw_bytes(raw);
+                if let Some(chunk_size) = chunk_size {
+                    serializer = serializer.with_bytes_chunk_size(chunk_size);
+                }
+                buf.serialize(&mut serializer).unwrap();
+                prop_assert_eq!(&de::from_str::<serde_bytes::ByteBuf>(&doc).unwrap(), &buf);
+            }
+        }
     }
 }
@@ -1,7 +1,6 @@
-use std::io::{self};
+use std::io;
 
 fn main() {
     let mut deserializer = serde_json::Deserializer::from_reader(io::stdin());
-    let mut serializer = serde_mml::ser::Serializer::new(io::stdout());
-    serde_transcode::transcode(&mut deserializer, &mut serializer).unwrap();
+    serde_mml::transcode_to_mml(&mut deserializer, io::stdout()).unwrap();
 }
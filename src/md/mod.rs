@@ -1,5 +1,757 @@
+//! A codec for the nested Markdown lists of links which MML is made of, independent of serde
+//!
+//! `Writer` writes documents, and `Reader` parses them into a stream of `Item`s. For anything
+//! `Writer` produces, reading it back gives the exact same structure and link texts, whatever the
+//! texts contain. `Reader` also accepts some of what people write by hand, like `-` bullets and
+//! other indentation widths.
+//!
+//! ```
+//! use serde_mml::md::{FmtOutput, Item, Reader, Writer};
+//!
+//! # fn main() -> serde_mml::Result<()> {
+//! let mut writer = Writer::new(FmtOutput(String::new()));
+//! let mut fruits = writer.unordered_list(None)?;
+//! writer.link(Some(&mut fruits), "apples", "https://example.com/apples")?;
+//! let mut kinds = writer.ordered_list(Some(&mut fruits))?;
+//! writer.link(Some(&mut kinds), "Granny Smith", "https://example.com/apples/1")?;
+//! writer.link(Some(&mut fruits), "pears", "https://example.com/pears")?;
+//! let doc = writer.into_inner().0;
+//!
+//! assert_eq!(
+//!     doc,
+//!     concat!(
+//!         "* [apples](https://example.com/apples)\n",
+//!         "* \n",
+//!         "    0. [Granny Smith](https://example.com/apples/1)\n",
+//!         "* [pears](https://example.com/pears)\n",
+//!     )
+//! );
+//!
+//! let items = Reader::new(&doc).collect::<serde_mml::Result<Vec<_>>>()?;
+//! assert_eq!(items[0], Item::PushUnorderedList);
+//! assert_eq!(items[2], Item::PushOrderedList);
+//! assert_eq!(items[4], Item::PopList);
+//! assert_eq!(items.len(), 7);
+//! # Ok(())
+//! # }
+//! ```
 mod reader;
 mod writer;
 
-pub use reader::{Item, Reader};
-pub use writer::{List, Writer};
+pub use reader::{unescape, Item, Reader};
+pub use writer::{FmtOutput, LineCapture, List, Output, Writer};
+
+// Showing a value as MML needs serde after all, but it's where people look for formatting
+pub use crate::ser::{display, Display};
+
+use alloc::string::String;
+
+use crate::error::Result;
+use crate::ty;
+use writer::INDENT;
+
+/// Check whether two documents have the same structure and links, regardless of formatting
+///
+/// Indentation width, bullet numbering and unordered bullet style (`*` or `-`) are ignored,
+/// while link texts are compared after unescaping
+pub fn semantically_eq(a: &str, b: &str) -> Result<bool> {
+    let (mut a, mut b) = (Reader::new(a), Reader::new(b));
+    loop {
+        match (a.next().transpose()?, b.next().transpose()?) {
+            (None, None) => return Ok(true),
+            (a, b) if a != b => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Show the items `text` is read as, one per line and indented by how deeply they're nested,
+/// for debugging the reader
+///
+/// Like the reader, this stops at the first error, which is shown on the last line.
+pub fn dump_items(text: &str) -> String {
+    use core::fmt::Write;
+
+    let mut dump = String::new();
+    let mut depth = 0;
+    for item in Reader::new(text) {
+        if let Ok(Item::PopList) = item {
+            depth -= 1;
+        }
+
+        let indent = depth * INDENT;
+        match &item {
+            Ok(item) => writeln!(dump, "{:indent$}{:?}", "", item, indent = indent),
+            Err(error) => writeln!(dump, "{:indent$}Error: {}", "", error, indent = indent),
+        }
+        .expect("writing to a String can't fail");
+
+        if let Ok(Item::PushOrderedList) | Ok(Item::PushUnorderedList) = item {
+            depth += 1;
+        }
+    }
+    dump
+}
+
+/// Check that `text` is a well-formed document whose links all have valid type URIs, without
+/// deserializing it into anything
+///
+/// Errors are wrapped in an `Error::Located` pointing out where they are. As the type URIs are
+/// checked one at a time, this doesn't catch e.g. a list which is shorter than it says.
+pub fn validate(text: &str) -> Result<()> {
+    validate_with(text, ty::DEFAULT_SCHEME, false)
+}
+
+/// Like `validate`, but for documents whose type URIs use `scheme` instead of `serde`, or, if
+/// `bare_uris` is set, no scheme at all, as with `Deserializer::with_scheme` and `with_bare_uris`
+pub fn validate_with(text: &str, scheme: &'static str, bare_uris: bool) -> Result<()> {
+    // Both the reader's position and the URIs it gives out are slices of `text`, even for
+    // reference links whose URIs are further down
+    let offset = |s: &str| s.as_ptr() as usize - text.as_ptr() as usize;
+
+    // Lists can't be unbalanced, as the reader closes them by itself
+    let mut reader = Reader::new(text);
+    while let Some(item) = reader.next() {
+        match item {
+            Ok(Item::Link { uri, .. }) => {
+                if let Err(error) = crate::de::parse_type(uri, scheme, bare_uris) {
+                    return Err(error.located(text, offset(uri), uri.len()));
+                }
+            }
+            Ok(_) => {}
+            Err(error) => return Err(error.located(text, offset(reader.remaining()), 1)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use alloc::format;
+    use proptest::prelude::*;
+    use writer::EscapedFormatter;
+
+    const DOC: &str = "\
+* [Map of length 1](serde://map/1)
+* 
+    0. [key](serde://string)
+    1. 
+        0. [Seq of length 2](serde://seq/2)
+        1. [a\\.b](serde://string)
+        2. [2](serde://u8)
+";
+
+    #[test]
+    fn test_semantically_eq() {
+        assert!(semantically_eq(DOC, DOC).unwrap());
+
+        let reformatted = "\
+- [Map of length 1](serde://map/1)
+- 
+  1. [key](serde://string)
+  1. 
+     5. [Seq of length 2](serde://seq/2)
+     6. [a\\.b](serde://string)
+     7. [2](serde://u8)
+";
+        assert!(semantically_eq(DOC, reformatted).unwrap());
+    }
+
+    #[test]
+    fn test_inline_lists() {
+        let inline = "\
+* [Map of length 1](serde://map/1)
+* 
+    0. [key](serde://string)
+    1. [Seq of length 2](serde://seq/2) [a\\.b](serde://string) [2](serde://u8)
+";
+        assert!(semantically_eq(DOC, inline).unwrap());
+
+        // Link text is still opaque, even when it looks like the next link
+        let tricky =
+            "[Seq of length 2](serde://seq/2) [a\\] \\[b](serde://string) [x] <serde://bool>";
+        let items = Reader::tokenize(tricky).unwrap();
+        assert_eq!(
+            items,
+            [
+                Item::PushOrderedList,
+                Item::Link {
+                    text: "Seq of length 2".into(),
+                    uri: "serde://seq/2"
+                },
+                Item::Link {
+                    text: "a] [b".into(),
+                    uri: "serde://string"
+                },
+                Item::Link {
+                    text: "true".into(),
+                    uri: "serde://bool"
+                },
+                Item::PopList,
+            ]
+        );
+
+        let mut writer = Writer::new(FmtOutput(String::new()));
+        let mut list = writer.unordered_list(None).unwrap();
+        writer
+            .inline_list(Some(&mut list), ["[a](x)\n", "[b](y)"].iter().copied())
+            .unwrap();
+        assert_eq!(writer.into_inner().0, "* [a](x) [b](y)\n");
+    }
+
+    #[test]
+    fn test_definitions() {
+        let link = |text: &'static str, uri: &'static str| Item::Link {
+            text: text.into(),
+            uri,
+        };
+
+        let doc = "- [Map](serde://map/2)\n- **a\\*\\*:b**: [1](x)\n- **c**: [x] <y>\n";
+        assert_eq!(
+            Reader::tokenize(doc).unwrap(),
+            [
+                Item::PushUnorderedList,
+                link("Map", "serde://map/2"),
+                Item::PushOrderedList,
+                Item::Key("a**:b".into()),
+                link("1", "x"),
+                Item::PopList,
+                Item::PushOrderedList,
+                Item::Key("c".into()),
+                link("true", "y"),
+                Item::PopList,
+                Item::PopList,
+            ]
+        );
+
+        let mut writer = Writer::new(FmtOutput(String::new()));
+        let mut list = writer.unordered_list(None).unwrap();
+        writer
+            .definition(Some(&mut list), "a**:b", "[1](x)\n")
+            .unwrap();
+        writer.definition(Some(&mut list), "", "[2](x)").unwrap();
+        assert_eq!(
+            writer.definition(Some(&mut list), "a\nb", "[3](x)"),
+            Err(Error::InvalidKey("a\nb".to_owned()))
+        );
+        assert_eq!(
+            writer.into_inner().0,
+            "* **a\\*\\*\\:b**: [1](x)\n* ****: [2](x)\n"
+        );
+        assert!(validate("* [Map](serde://map/1)\n* **a**: [1](serde://u8)\n").is_ok());
+
+        // Only definitions may leave out their key's type URI, not links in its place
+        type Map = std::collections::BTreeMap<String, u8>;
+        let nested = crate::ser::to_string(&Map::from([("a".to_owned(), 1)])).unwrap();
+        let untyped = nested.replace("[a](serde://string)", "[a]()");
+        assert_ne!(nested, untyped);
+        assert!(validate(&untyped).is_err());
+        assert!(crate::de::from_str::<Map>(&untyped).is_err());
+        assert!(crate::de::from_str::<String>("[a]()\n").is_err());
+
+        for doc in [
+            "* **a**\n",
+            "* **a**: 1\n",
+            "* **a: [1](x)\n",
+            "* **a**:\n  [1](x)\n",
+        ] {
+            let error = Reader::tokenize(doc).unwrap_err();
+            assert_eq!(error, Error::UnterminatedDefinition, "{:?}", doc);
+        }
+    }
+
+    #[test]
+    fn test_raw_text() {
+        let link = |text: &'static str| Item::Link {
+            text: text.into(),
+            uri: "serde://string",
+        };
+
+        let doc = "[a\\.b](serde://string)\n";
+        assert_eq!(Reader::tokenize(doc).unwrap(), [link("a.b")]);
+
+        let raw = Reader::new(doc)
+            .with_raw_text(true)
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(raw.unwrap(), [link("a\\.b")]);
+        assert_eq!(unescape("a\\.b"), "a.b");
+        assert_eq!(unescape("\\\\\\]"), "\\]");
+
+        // The text still ends at the first unescaped bracket
+        let doc = "[\\]\\\\](serde://string)\n";
+        let raw = Reader::new(doc)
+            .with_raw_text(true)
+            .collect::<Result<Vec<_>>>();
+        assert_eq!(raw.unwrap(), [link("\\]\\\\")]);
+    }
+
+    /// Escape `text` into a link, both in one go and a char at a time
+    fn escape(text: &str) -> (String, String) {
+        use core::fmt::Write;
+
+        let mut whole = FmtOutput(String::new());
+        EscapedFormatter::new(&mut whole).write_str(text).unwrap();
+
+        let mut chars = FmtOutput(String::new());
+        let mut formatter = EscapedFormatter::new(&mut chars);
+        for ch in text.chars() {
+            formatter.write_char(ch).unwrap();
+        }
+
+        (whole.0, chars.0)
+    }
+
+    fn check_escaping(
+        text: &str,
+    ) -> core::result::Result<(), proptest::test_runner::TestCaseError> {
+        let (whole, chars) = escape(text);
+        prop_assert_eq!(&whole, &chars);
+        prop_assert_eq!(unescape(&whole), text);
+
+        // The reader has to find where the text ends by itself
+        let doc = format!("[{}](serde://string)\n", whole);
+        let items = Reader::tokenize(&doc).unwrap();
+        prop_assert_eq!(
+            items,
+            [Item::Link {
+                text: text.into(),
+                uri: "serde://string",
+            }]
+        );
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn proptest_escaping(text in "(?s).*") {
+            check_escaping(&text)?;
+        }
+
+        #[test]
+        fn proptest_escaping_punctuation(text in "[[:punct:]]*") {
+            check_escaping(&text)?;
+        }
+
+        #[test]
+        fn proptest_escaping_mixed(text in "[a-z \\\\\\[\\]()*_`#<>!.-]*") {
+            check_escaping(&text)?;
+        }
+    }
+
+    #[test]
+    fn test_semantically_ne() {
+        let different_value = DOC.replace("[2]", "[3]");
+        assert!(!semantically_eq(DOC, &different_value).unwrap());
+
+        let different_type = DOC.replace("serde://u8", "serde://u16");
+        assert!(!semantically_eq(DOC, &different_type).unwrap());
+
+        let different_nesting = DOC.replace("        2. [2]", "    2. [2]");
+        assert!(!semantically_eq(DOC, &different_nesting).unwrap());
+    }
+
+    #[test]
+    fn test_unterminated_link() {
+        for doc in &[
+            "* [text",
+            "* [text\n",
+            "* [text](uri",
+            "* [text](uri\n* [next](uri)\n",
+        ] {
+            let mut reader = Reader::new(doc);
+            assert_eq!(reader.next().unwrap().unwrap(), Item::PushUnorderedList);
+            assert!(matches!(reader.next(), Some(Err(Error::UnterminatedLink))));
+            assert!(reader.next().is_none());
+        }
+
+        assert!(matches!(
+            semantically_eq("* [text](uri)\n", "* [text](uri"),
+            Err(Error::UnterminatedLink)
+        ));
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        let items = Reader::new("* [text](uri)")
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::PushUnorderedList,
+                Item::Link {
+                    text: "text".into(),
+                    uri: "uri"
+                },
+                Item::PopList,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fused() {
+        for doc in &[DOC, "* [text](uri", ""] {
+            let mut reader = Reader::new(doc);
+            reader.by_ref().for_each(drop);
+            for _ in 0..5 {
+                assert!(reader.next().is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_link_text() {
+        let items = Reader::new("1. [](a)\n2. [](b)\n[]\n").collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                Ok(Item::PushOrderedList),
+                Ok(Item::Link {
+                    text: "".into(),
+                    uri: "a"
+                }),
+                Ok(Item::Link {
+                    text: "".into(),
+                    uri: "b"
+                }),
+                Ok(Item::PopList),
+                Err(Error::UnexpectedChar('\n')),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_slices() {
+        let link = |text: &'static str, uri: &'static str| {
+            Ok(Item::Link {
+                text: text.into(),
+                uri,
+            })
+        };
+
+        // Whatever's being looked for may come first, or be the very last char of the input
+        for (doc, item) in [
+            ("[](serde://string)", link("", "serde://string")),
+            ("[]()\n", link("", "")),
+            ("[a](<>)", link("a", "")),
+            ("[é](é)", link("é", "é")),
+            ("[\\é]()", link("é", "")),
+            ("[][]", Err(Error::UndefinedReference("".to_owned()))),
+            ("[]", Err(Error::UnterminatedLink)),
+            ("[", Err(Error::UnterminatedLink)),
+            ("[](", Err(Error::UnterminatedLink)),
+        ] {
+            assert_eq!(Reader::new(doc).next(), Some(item), "{:?}", doc);
+        }
+
+        let empty = crate::de::from_str::<String>("[](serde://string)\n");
+        assert_eq!(empty.unwrap(), "");
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let spaced = "\
+* [Map of length 1](serde://map/1)
+
+* 
+    0. [key](serde://string)
+  
+    1. 
+
+
+        0. [Seq of length 2](serde://seq/2)
+            
+        1. [a\\.b](serde://string)
+
+        2. [2](serde://u8)
+
+";
+        assert!(semantically_eq(DOC, spaced).unwrap());
+
+        let value = crate::de::from_str::<std::collections::BTreeMap<String, (String, u8)>>(spaced)
+            .unwrap();
+        assert_eq!(value["key"], ("a.b".to_owned(), 2));
+    }
+
+    #[test]
+    fn test_leading_indentation() {
+        // A document cut out of a bigger one reads the same as it did on its own
+        let indented = DOC
+            .lines()
+            .map(|line| format!("        {}\n", line))
+            .collect::<String>();
+        assert_eq!(
+            Reader::tokenize(&indented).unwrap(),
+            Reader::tokenize(DOC).unwrap()
+        );
+        assert_eq!(
+            crate::de::from_str::<u8>("\n  [2](serde://u8)\n").unwrap(),
+            2
+        );
+
+        // But it can't go back further left than where it started
+        let doc = "    0. [Seq of length 2](serde://seq/2)\n    1. [1](serde://u8)\n  2. [2](serde://u8)\n";
+        let items = Reader::new(doc).collect::<Vec<_>>();
+        assert_eq!(items.last(), Some(&Err(Error::UnexpectedIndent)));
+        assert_eq!(
+            crate::de::from_str::<Vec<u8>>(doc).unwrap_err().unlocated(),
+            &Error::UnexpectedIndent
+        );
+        let error = validate(doc).unwrap_err();
+        assert!(error.to_string().contains("  2. [2]"), "{}", error);
+    }
+
+    #[test]
+    fn test_leading_junk() {
+        type Value = std::collections::BTreeMap<String, (String, u8)>;
+        let expected = crate::de::from_str::<Value>(DOC).unwrap();
+
+        for prefix in ["\u{feff}", "\n\n", "  \n\n    \n", "\u{feff}\n  \n"] {
+            let doc = format!("{}{}", prefix, DOC);
+            assert!(semantically_eq(DOC, &doc).unwrap(), "{:?}", prefix);
+            assert_eq!(crate::de::from_str::<Value>(&doc).unwrap(), expected);
+        }
+
+        let doc = "\u{feff}[1](serde://u8)\n";
+        assert_eq!(crate::de::from_str::<u8>(doc).unwrap(), 1);
+
+        // Only at the very start is it a byte order mark rather than content
+        let doc = format!("{}\u{feff}", DOC);
+        assert!(Reader::tokenize(&doc).is_err());
+    }
+
+    #[test]
+    fn test_restarted_numbering() {
+        let link = |text: &'static str| Item::Link {
+            text: text.into(),
+            uri: "serde://u8",
+        };
+
+        // Numbers going backwards, jumping and growing wider are all just siblings
+        let doc = "\
+5. [a](serde://u8)
+1. [b](serde://u8)
+1. [c](serde://u8)
+100. [d](serde://u8)
+    7. [e](serde://u8)
+    3. [f](serde://u8)
+2. [g](serde://u8)
+";
+        let items = Reader::tokenize(doc).unwrap();
+        assert_eq!(
+            items,
+            vec![
+                Item::PushOrderedList,
+                link("a"),
+                link("b"),
+                link("c"),
+                link("d"),
+                Item::PushOrderedList,
+                link("e"),
+                link("f"),
+                Item::PopList,
+                link("g"),
+                Item::PopList,
+            ]
+        );
+
+        let renumbered = crate::ser::to_string(&[1u8, 2, 3, 4, 5, 6])
+            .unwrap()
+            .replace("5. ", "1. ")
+            .replace("6. ", "1. ");
+        assert_eq!(
+            crate::de::from_str::<Vec<u8>>(&renumbered).unwrap(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn test_references() {
+        let doc = "\
+* [a][1]
+* [b][2]
+* 
+    0. [c][1]
+    1. [d](inline)
+
+[2]: second
+[1]: first
+";
+        let items = Reader::tokenize(doc).unwrap();
+        let uris = items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Link { uri, .. } => Some(*uri),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(uris, vec!["first", "second", "first", "inline"]);
+
+        assert_eq!(
+            Reader::tokenize("* [a][1]\n* [b][3]\n\n[1]: first\n"),
+            Err(Error::UndefinedReference("3".to_owned()))
+        );
+
+        let mut writer = Writer::new(FmtOutput(String::new()));
+        writer.set_reference_links(true);
+        let mut list = writer.ordered_list(None).unwrap();
+        for text in ["x", "y", "z"] {
+            writer.link(Some(&mut list), text, "serde://char").unwrap();
+        }
+        writer
+            .bytes_link(Some(&mut list), b"hi", "serde://bytes")
+            .unwrap();
+        writer.references().unwrap();
+        assert_eq!(
+            writer.into_inner().0,
+            "0. [x][1]\n1. [y][1]\n2. [z][1]\n3. [aGk=][2]\n\n[1]: serde://char\n[2]: serde://bytes\n"
+        );
+    }
+
+    #[test]
+    fn test_awkward_uris() {
+        for references in [false, true] {
+            let mut writer = Writer::new(FmtOutput(String::new()));
+            writer.set_reference_links(references);
+            let mut list = writer.unordered_list(None).unwrap();
+            for uri in ["a)b", "(a)", "with space", "a>b"] {
+                writer.link(Some(&mut list), uri, uri).unwrap();
+            }
+            writer.references().unwrap();
+            let doc = writer.into_inner().0;
+            if !references {
+                assert!(doc.contains("[a\\)b](<a)b>)\n"), "{}", doc);
+                assert!(doc.contains("[a\\>b](a>b)\n"), "{}", doc);
+            }
+
+            for item in Reader::tokenize(&doc).unwrap() {
+                if let Item::Link { text, uri } = item {
+                    assert_eq!(text, uri);
+                }
+            }
+        }
+
+        let mut writer = Writer::new(FmtOutput(String::new()));
+        for uri in ["a\nb", "(a>)", "<a>"] {
+            assert_eq!(
+                writer.link(None, "x", uri),
+                Err(Error::InvalidUri(uri.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_slice() {
+        let doc = "* [a](serde://string)\n* [b](serde://string)\n".as_bytes();
+        assert_eq!(
+            Reader::from_slice(doc).collect::<Vec<_>>(),
+            Reader::new(core::str::from_utf8(doc).unwrap()).collect::<Vec<_>>()
+        );
+
+        let mut doc = b"* [a](serde://string)\n* [b".to_vec();
+        let offset = doc.len();
+        doc.extend_from_slice(b"\xff](serde://string)\n* [c](serde://string)\n");
+        let items = Reader::from_slice(&doc).collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            [
+                Ok(Item::PushUnorderedList),
+                Ok(Item::Link {
+                    text: "a".into(),
+                    uri: "serde://string"
+                }),
+                Err(Error::InvalidUtf8 { offset }),
+            ]
+        );
+        assert_eq!(
+            items[2].as_ref().unwrap_err().to_string(),
+            "Invalid UTF-8 at byte 26"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate(DOC), Ok(()));
+        assert_eq!(validate(&format!("\u{feff}{}", DOC)), Ok(()));
+
+        let tabbed = DOC.replace("    1. \n", "\t1. \n");
+        match validate(&tabbed).unwrap_err() {
+            Error::Located {
+                error,
+                line,
+                column,
+                ..
+            } => {
+                assert_eq!(*error, Error::UnexpectedChar('\t'));
+                assert_eq!((line, column), (4, 2));
+            }
+            error => panic!("{:?}", error),
+        }
+
+        let bad_uri = DOC.replace("serde://u8", "serde://u9");
+        let error = validate(&bad_uri).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            concat!(
+                "Unknown type URI\n",
+                " --> line 7, column 16\n",
+                "  |\n",
+                "7 |         2. [2](serde://u9)\n",
+                "  |                ^^^^^^^^^^",
+            )
+        );
+
+        let unterminated = DOC.replace("[2](serde://u8)", "[2](serde://u8");
+        assert!(matches!(
+            validate(&unterminated),
+            Err(Error::Located { error, .. }) if *error == Error::UnterminatedLink
+        ));
+
+        let custom = DOC.replace("serde://", "myapp://");
+        assert_eq!(validate_with(&custom, "myapp", false), Ok(()));
+        assert!(validate(&custom).is_err());
+        assert!(validate_with(DOC, "myapp", false).is_err());
+
+        // The same URIs as the deserializer accepts
+        let bare = DOC.replace("serde://", "");
+        assert_eq!(validate_with(&bare, ty::DEFAULT_SCHEME, true), Ok(()));
+        assert!(validate(&bare).is_err());
+        let shown = DOC.replace("[2](serde://u8)", "[two](serde://u8#2)");
+        assert_eq!(validate(&shown), Ok(()));
+        assert_eq!(
+            crate::de::from_str::<serde_value::Value>(&shown),
+            crate::de::from_str(DOC)
+        );
+        let shown = DOC.replace("[2](serde://u8)", "[two](u8#2)");
+        assert_eq!(validate_with(&shown, ty::DEFAULT_SCHEME, true), Ok(()));
+        assert!(validate_with(&shown, ty::DEFAULT_SCHEME, false).is_err());
+    }
+
+    #[test]
+    fn test_dump_items() {
+        assert_eq!(
+            dump_items(DOC),
+            concat!(
+                "PushUnorderedList\n",
+                "    Link { text: \"Map of length 1\", uri: \"serde://map/1\" }\n",
+                "    PushOrderedList\n",
+                "        Link { text: \"key\", uri: \"serde://string\" }\n",
+                "        PushOrderedList\n",
+                "            Link { text: \"Seq of length 2\", uri: \"serde://seq/2\" }\n",
+                "            Link { text: \"a.b\", uri: \"serde://string\" }\n",
+                "            Link { text: \"2\", uri: \"serde://u8\" }\n",
+                "        PopList\n",
+                "    PopList\n",
+                "PopList\n",
+            )
+        );
+
+        let broken = DOC.replace("[2](serde://u8)", "[2](serde://u8");
+        assert!(dump_items(&broken).ends_with(concat!(
+            "            Link { text: \"a.b\", uri: \"serde://string\" }\n",
+            "            Error: Unterminated link\n",
+        )));
+    }
+}
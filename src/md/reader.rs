@@ -1,35 +1,159 @@
-use std::borrow::Cow;
-use std::str::Chars;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+use core::str::Chars;
 
+use crate::error::{Error, Result};
+
+/// Parses the nested lists of a document into a stream of `Item`s
+///
+/// The first line may be indented, e.g. in a document cut out of a bigger one, in which case no
+/// other line may be indented less than it.
+///
+/// Once the input runs out, a `PopList` is returned for every list which is still open, after
+/// which the reader keeps returning `None`. The same goes for errors: after one is returned, the
+/// reader is done for good.
+///
+/// Link text is opaque: once a `[` has been found, everything up to the matching unescaped `]` is
+/// taken as the text, even if it spans lines which look like list items.
+///
+/// A line may hold several links separated by spaces, e.g. `[a](x) [b](y)`, which are read as an
+/// ordered list of them, just as if each one had its own bullet in a sublist.
+///
+/// Items may also be definitions, i.e. a key in bold followed by a colon and a link, such as
+/// `**name**: [Ferris](serde://string)`. They're read like a line of links, with the key as a `Key`
+/// in place of the first link.
+///
+/// Links may also refer to their URI by number, e.g. `[text][3]`, as long as the document is
+/// followed by the definitions of the numbers, e.g. `[3]: serde://u8`. Each of several documents
+/// which have been concatenated together may have its own definitions, which are only looked for
+/// once a link refers to one.
+///
+/// Cloning a reader is cheap, and the clone carries on from the same position.
+#[derive(Clone)]
 pub struct Reader<'a> {
     chars: Chars<'a>,
     indents: Vec<usize>,
-    state: State,
+    state: State<'a>,
+
+    /// The URIs defined after the current document, by their number
+    references: BTreeMap<&'a str, &'a str>,
+
+    /// Whether the current document's definitions have been looked for yet
+    references_loaded: bool,
+
+    /// Where the input stops being UTF-8, if it was given as bytes which aren't all valid
+    invalid_utf8: Option<usize>,
+
+    /// Whether to leave link texts escaped, as they are in the input
+    raw_text: bool,
+
+    /// The indentation of the first line, which no other line may have less of
+    base_indent: Option<usize>,
 }
+
+/// A single token of a document's structure
+///
+/// A document is either a single `Link`, or a list: a `Push*List`, its items, and a `PopList`.
+/// Each item is in turn either a `Link`, a definition's `Key` or a nested list, so lists are always
+/// balanced and never empty, as there'd be no bullet to find them by.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Item<'a> {
+    /// A `[text](uri)` link, with its text unescaped unless reading `with_raw_text`
+    ///
+    /// Checkboxes, i.e. `[x] <uri>` and `[ ] <uri>`, have `true` and `false` as their text
     Link { text: Cow<'a, str>, uri: &'a str },
+
+    /// The key of a definition, i.e. `**key**: <link>`, unescaped like a link's text
+    ///
+    /// It only ever comes first in an ordered list of it and the link, as a map entry's key,
+    /// which is always a string.
+    Key(Cow<'a, str>),
+
+    /// The start of a list with numbered bullets, such as `0.`
     PushOrderedList,
+
+    /// The start of a list with `*` or `-` bullets
     PushUnorderedList,
+
+    /// The end of the innermost list which is still open
     PopList,
 }
 
-#[derive(Debug)]
-enum State {
+#[derive(Debug, Clone)]
+enum State<'a> {
     BeforeItem,
-    InItem(usize),
-    EOF,
+    InItem {
+        depth: usize,
+        bulleted: bool,
+    },
+
+    /// In a list of links on a single line, with its first link yet to be returned if it's there
+    Inline(Option<Item<'a>>),
+
+    Eof,
 }
 
 impl<'a> Reader<'a> {
     pub fn new(text: &'a str) -> Self {
+        // Editors on Windows like to start files with a byte order mark
+        let text = text.strip_prefix('\u{feff}').unwrap_or(text);
         Self {
             chars: text.chars(),
-            indents: vec![],
+            indents: Vec::new(),
             state: State::BeforeItem,
+            references: BTreeMap::new(),
+            references_loaded: false,
+            invalid_utf8: None,
+            raw_text: false,
+            base_indent: None,
         }
     }
 
+    /// Give out link texts as they are in the input, still escaped, e.g. for tooling which
+    /// rewrites documents and wants to pass the texts through untouched
+    ///
+    /// `unescape` does what the reader would otherwise have done.
+    pub fn with_raw_text(mut self, raw_text: bool) -> Self {
+        self.raw_text = raw_text;
+        self
+    }
+
+    /// Parse a document which may not be valid UTF-8, such as one straight off the network
+    ///
+    /// The lines before any invalid bytes are read as usual, and then an `InvalidUtf8` error
+    /// points out where they are.
+    pub fn from_slice(bytes: &'a [u8]) -> Self {
+        let error = match core::str::from_utf8(bytes) {
+            Ok(text) => return Self::new(text),
+            Err(error) => error,
+        };
+
+        // Don't read the start of the line with the invalid bytes as if it were a whole line
+        let valid = &bytes[..error.valid_up_to()];
+        let lines = valid.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        let text = core::str::from_utf8(&valid[..lines]).expect("checked to be valid UTF-8");
+        Self {
+            invalid_utf8: Some(error.valid_up_to()),
+            ..Self::new(text)
+        }
+    }
+
+    /// The input which hasn't been parsed yet
+    ///
+    /// Between items this starts where the next item does, although the indentation of the line
+    /// may already have been skipped. Reference definitions are never part of it.
+    pub fn remaining(&self) -> &'a str {
+        self.chars.as_str()
+    }
+
+    /// Parse all of `text` up front, so that the items can be deserialized from repeatedly
+    pub fn tokenize(text: &'a str) -> Result<Vec<Item<'a>>> {
+        Self::new(text).collect()
+    }
+
     fn link_text(&mut self) -> Option<Cow<'a, str>> {
         // Parse out the text of the link, with escapes
         // We must be careful to not consider \] as an escape
@@ -50,35 +174,234 @@ impl<'a> Reader<'a> {
 
         let text = &start[..start.len() - end.len() - ']'.len_utf8()];
 
-        Some(if found_escape {
-            let mut escaped = false;
-            Cow::Owned(
-                text.chars()
-                    .filter_map(|ch| {
-                        if !escaped && ch == '\\' {
-                            escaped = true;
-                            found_escape = true;
-                            return None;
-                        }
-                        escaped = false;
-                        Some(ch)
-                    })
-                    .collect::<String>(),
-            )
+        Some(if found_escape && !self.raw_text {
+            Cow::Owned(unescape(text))
         } else {
             // If we've found no escapes, we can pass this through verbatim
             Cow::Borrowed(text)
         })
     }
 
+    /// Parse the key of a definition, whose opening `**` has already been consumed, up to and
+    /// including the colon after it
+    fn definition_key(&mut self) -> Option<Cow<'a, str>> {
+        let start = self.chars.as_str();
+        let mut escaped = false;
+        let mut found_escape = false;
+        let (end, _) = start.char_indices().find(|&(i, ch)| {
+            if escaped {
+                escaped = false;
+                return false;
+            }
+            escaped = ch == '\\';
+            found_escape |= escaped;
+            ch == '*' && start[i..].starts_with("**:")
+        })?;
+        self.chars = start[end + "**:".len()..].chars();
+
+        let text = &start[..end];
+        Some(if found_escape && !self.raw_text {
+            Cow::Owned(unescape(text))
+        } else {
+            Cow::Borrowed(text)
+        })
+    }
+
     /// Return the portion of the input string until the given char
     fn take_chars_until(&mut self, needle: char) -> Option<&'a str> {
         let start = self.chars.as_str();
         self.chars.by_ref().find(|&ch| ch == needle)?;
         let end = self.chars.as_str();
+
+        // The needle has been consumed, so it's always between the two, even when it came first
         Some(&start[..start.len() - end.len() - needle.len_utf8()])
     }
 
+    /// Like `take_chars_until`, but URIs never span more than one line
+    fn take_uri_until(&mut self, needle: char) -> Option<&'a str> {
+        self.take_chars_until(needle)
+            .filter(|uri| !uri.contains('\n'))
+    }
+
+    /// Look ahead for the definitions which follow the current document
+    fn load_references(&mut self) {
+        self.references = self
+            .chars
+            .as_str()
+            .split('\n')
+            .map(|line| line.trim_start_matches(' '))
+            .skip_while(|line| definition(line).is_none())
+            .filter(|line| !line.trim_end().is_empty())
+            .map_while(definition)
+            .collect();
+        self.references_loaded = true;
+    }
+
+    /// Skip to the start of the next line, which may also just be the end of the input
+    fn skip_line(&mut self) {
+        self.take_chars_until('\n');
+    }
+
+    /// Parse the rest of a link or checkbox, whose opening `[` has already been consumed, up to
+    /// the end of its URI
+    fn link(&mut self) -> Result<Item<'a>> {
+        let text = self.link_text().ok_or(Error::UnterminatedLink)?;
+        let (text, uri) = match self.chars.next() {
+            // URIs with parentheses or spaces are wrapped in angle brackets
+            Some('(') if self.chars.as_str().starts_with('<') => {
+                self.chars.next();
+                let uri = self.take_uri_until('>').ok_or(Error::UnterminatedLink)?;
+                self.expect(')')?;
+                (text, uri)
+            }
+
+            Some('(') => (
+                text,
+                self.take_uri_until(')').ok_or(Error::UnterminatedLink)?,
+            ),
+
+            Some('[') => {
+                let n = self.take_uri_until(']').ok_or(Error::UnterminatedLink)?;
+                if !self.references_loaded {
+                    self.load_references();
+                }
+                match self.references.get(n) {
+                    Some(&uri) => (text, uri),
+                    None => return Err(Error::UndefinedReference(n.to_owned())),
+                }
+            }
+
+            // Checkboxes are followed by their URI as an autolink
+            Some(' ') => {
+                let checked = match text.as_ref() {
+                    "x" | "X" => true,
+                    " " => false,
+                    _ => return Err(Error::InvalidCheckbox(text.into_owned())),
+                };
+                self.expect('<')?;
+                let uri = self.take_uri_until('>').ok_or(Error::UnterminatedLink)?;
+                (Cow::Borrowed(if checked { "true" } else { "false" }), uri)
+            }
+
+            Some(ch) => return Err(Error::UnexpectedChar(ch)),
+            None => return Err(Error::UnterminatedLink),
+        };
+        Ok(Item::Link { text, uri })
+    }
+
+    /// Skip whatever's after the links on a line, unless it's the start of another document
+    ///
+    /// Concatenating files which don't end in a newline glues the first line of each one onto the
+    /// last line of the one before, right after its last link. Nothing else puts a link or bullet
+    /// there, as links on the same line are separated by spaces.
+    fn end_line(&mut self) -> bool {
+        let rest = self.chars.as_str();
+        let after_bullet = match rest.strip_prefix(|ch| ch == '*' || ch == '-') {
+            Some(after) => after,
+            None => rest
+                .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                .strip_prefix('.')
+                .unwrap_or(rest),
+        };
+
+        let glued = rest.starts_with('[') || after_bullet.starts_with(" [");
+        if !glued {
+            self.skip_line();
+        }
+        !glued
+    }
+
+    /// Finish off a link which is alone on its line
+    fn line_link(&mut self, link: Item<'a>) -> Result<Item<'a>> {
+        if !self.end_line() {
+            return Ok(link);
+        }
+
+        // Byte buffers may have their contents in a code block right after the link instead, but
+        // nothing else may, lest a stray block silently replace some other value
+        match (link, self.code_block()?) {
+            (Item::Link { uri, .. }, Some(contents)) if is_bytes_uri(uri) => Ok(Item::Link {
+                text: Cow::Owned(contents),
+                uri,
+            }),
+            (_, Some(_)) => Err(Error::UnexpectedCodeBlock),
+            (link, None) => Ok(link),
+        }
+    }
+
+    /// Take the next line, without its newline
+    fn take_line(&mut self) -> Option<&'a str> {
+        let rest = self.chars.as_str();
+        if rest.is_empty() {
+            return None;
+        }
+
+        self.take_chars_until('\n').or_else(|| {
+            self.chars = "".chars();
+            Some(rest)
+        })
+    }
+
+    /// If the next line opens a fenced code block, parse it and return its lines joined together
+    fn code_block(&mut self) -> Result<Option<String>> {
+        if !self
+            .chars
+            .as_str()
+            .trim_start_matches(' ')
+            .starts_with("```")
+        {
+            return Ok(None);
+        }
+        self.skip_line();
+
+        let mut contents = String::new();
+        loop {
+            if let Some(after) = self.chars.as_str().trim_start().strip_prefix("```") {
+                self.chars = after.chars();
+                self.end_line();
+                break Ok(Some(contents));
+            }
+            let line = self.take_line().ok_or(Error::UnterminatedCodeBlock)?.trim();
+            contents.push_str(line);
+        }
+    }
+
+    /// Consume the next char, which must be `expected`
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.chars.next() {
+            Some(ch) if ch == expected => Ok(()),
+            Some(ch) => Err(Error::UnexpectedChar(ch)),
+            None => Err(Error::UnexpectedEOF),
+        }
+    }
+
+    /// Parse the rest of a bullet, whose first char has already been consumed
+    fn bullet(&mut self, first: char) -> Result<()> {
+        // If we found a number, we must parse more digits and the dot
+        if first.is_ascii_digit() {
+            match self.chars.by_ref().find(|c| !c.is_ascii_digit()) {
+                Some('.') => {}
+                Some(ch) => return Err(Error::UnexpectedChar(ch)),
+                None => return Err(Error::UnexpectedEOF),
+            }
+        }
+
+        // The Writer always puts a space after the bullet, but editors tend to strip it from
+        // empty items, so leave a newline for the next iteration
+        if self.chars.as_str().starts_with('\n') || self.chars.as_str().is_empty() {
+            Ok(())
+        } else {
+            self.expect(' ')
+        }
+    }
+
+    /// Give up on the rest of the input after an error, as there's no telling where it'd resync
+    fn fail(&mut self, error: Error) -> Option<Result<Item<'a>>> {
+        self.state = State::Eof;
+        self.indents.clear();
+        Some(Err(error))
+    }
+
     /// Calculate the indent of the current item and remove it from the input
     fn next_depth(&mut self) -> usize {
         // We use some Chars::as_str trickery to avoid consuming the first char after the indent
@@ -93,60 +416,153 @@ impl<'a> Reader<'a> {
     }
 }
 
+/// Remove the backslashes escaping the chars of a link's text, as found between its brackets
+pub fn unescape(text: &str) -> String {
+    let mut escaped = false;
+    text.chars()
+        .filter(|&ch| {
+            if !escaped && ch == '\\' {
+                escaped = true;
+                return false;
+            }
+            escaped = false;
+            true
+        })
+        .collect()
+}
+
+/// Whether `uri` is that of a byte buffer written as base64, with whatever scheme or none at all
+///
+/// The reader doesn't know whether URIs without a scheme are accepted, so it leaves rejecting
+/// those to the deserializer.
+fn is_bytes_uri(uri: &str) -> bool {
+    let path = uri.split_once("://").map_or(uri, |(_, path)| path);
+    path == "bytes"
+}
+
+/// Parse a line defining the URI of a reference link's number, e.g. `[3]: serde://u8`
+fn definition(line: &str) -> Option<(&str, &str)> {
+    let (n, uri) = line.strip_prefix('[')?.split_once("]: ")?;
+    let uri = uri.trim_end();
+    if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) || uri.is_empty() {
+        return None;
+    }
+    let uri = uri
+        .strip_prefix('<')
+        .and_then(|uri| uri.strip_suffix('>'))
+        .unwrap_or(uri);
+    Some((n, uri))
+}
+
 impl<'a> Iterator for Reader<'a> {
-    type Item = Item<'a>;
+    type Item = Result<Item<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             match self.state {
                 State::BeforeItem => {
-                    self.state = State::InItem(self.next_depth());
+                    if self.chars.as_str().is_empty() {
+                        if let Some(offset) = self.invalid_utf8.take() {
+                            break self.fail(Error::InvalidUtf8 { offset });
+                        }
+                    }
+
+                    let depth = self.next_depth();
+
+                    // Blank lines are just separators, so they mustn't affect the depth
+                    if self.chars.as_str().starts_with('\n') {
+                        self.chars.next();
+                        continue;
+                    }
+
+                    // The current document's definitions were already looked ahead for, and the
+                    // next document's links refer to its own
+                    let line = self.chars.as_str().split('\n').next().unwrap_or_default();
+                    if definition(line).is_some() {
+                        self.skip_line();
+                        self.references.clear();
+                        self.references_loaded = false;
+                        continue;
+                    }
+
+                    // Documents cut out of bigger ones keep their indentation, which is fine as
+                    // long as it's consistent, but anything to the left of them isn't theirs
+                    match self.base_indent {
+                        None => self.base_indent = Some(depth),
+                        Some(base) if depth < base && !self.chars.as_str().is_empty() => {
+                            break self.fail(Error::UnexpectedIndent)
+                        }
+                        Some(_) => {}
+                    }
+
+                    self.state = State::InItem {
+                        depth,
+                        bulleted: false,
+                    };
                 }
 
-                State::InItem(new_depth) => {
+                State::InItem {
+                    depth: new_depth,
+                    bulleted,
+                } => {
                     // If we've dedented, pop an indent and return
-                    if self
-                        .indents
-                        .last()
-                        .map_or(false, |&depth| new_depth < depth)
+                    if self.indents.last().is_some_and(|&depth| new_depth < depth) {
+                        self.indents.pop();
+                        break Some(Ok(Item::PopList));
+                    }
+
+                    // List items always have bullets, so a bare link at the root indent must be a
+                    // new top-level value, e.g. the next of many concatenated documents
+                    if !bulleted
+                        && self.chars.as_str().starts_with('[')
+                        && self.indents.first().is_some_and(|&root| new_depth <= root)
                     {
                         self.indents.pop();
-                        break Some(Item::PopList);
+                        break Some(Ok(Item::PopList));
+                    }
+
+                    // A definition's key is in bold, which would otherwise look like a bullet
+                    if bulleted && self.chars.as_str().starts_with("**") {
+                        self.chars.nth(1);
+                        let key = match self.definition_key() {
+                            Some(key) => key,
+                            None => break self.fail(Error::UnterminatedDefinition),
+                        };
+                        if !self.chars.as_str().starts_with(" [") {
+                            break self.fail(Error::UnterminatedDefinition);
+                        }
+
+                        self.state = State::Inline(Some(Item::Key(key)));
+                        break Some(Ok(Item::PushOrderedList));
                     }
 
                     // Get the next character or move to the EOF state
                     let ch = if let Some(ch) = self.chars.next() {
                         ch
                     } else {
-                        self.state = State::EOF;
+                        self.state = State::Eof;
                         continue;
                     };
 
                     match ch {
                         // If the first character represents a bullet, we've found a new list item
-                        '0'..='9' | '*' => {
-                            // If we found a number, we must parse more digits and the dot
-                            if let '0'..='9' = ch {
-                                assert_eq!(
-                                    self.chars
-                                        .by_ref()
-                                        .skip_while(|c| c.is_ascii_digit())
-                                        .next(),
-                                    Some('.')
-                                );
+                        '0'..='9' | '*' | '-' => {
+                            if let Err(error) = self.bullet(ch) {
+                                break self.fail(error);
                             }
-
-                            // The Writer always puts a space after the bullet
-                            assert_eq!(self.chars.next(), Some(' '));
+                            self.state = State::InItem {
+                                depth: new_depth,
+                                bulleted: true,
+                            };
 
                             // If we've indented, push on a new indent and reutrn a Push*List
-                            if self.indents.last().map_or(true, |&depth| new_depth > depth) {
+                            if self.indents.last().is_none_or(|&depth| new_depth > depth) {
                                 self.indents.push(new_depth);
-                                return Some(if ch == '*' {
+                                return Some(Ok(if ch == '*' || ch == '-' {
                                     Item::PushUnorderedList
                                 } else {
                                     Item::PushOrderedList
-                                });
+                                }));
                             }
 
                             // Stay in the same state to parse the item
@@ -157,25 +573,55 @@ impl<'a> Iterator for Reader<'a> {
                             self.state = State::BeforeItem;
                         }
 
-                        // This item a link, parse it
+                        // This item a link or a task list checkbox, parse it
                         '[' => {
-                            let text = self.link_text()?;
-                            assert_eq!(self.chars.next(), Some('('));
-                            let uri = self.take_chars_until(')')?;
-                            self.take_chars_until('\n')?;
+                            let link = match self.link() {
+                                Ok(link) => link,
+
+                                // There's no use going on after this, so stop here for good
+                                Err(error) => break self.fail(error),
+                            };
+
+                            // More links on the same line make up an ordered list
+                            if self.chars.as_str().starts_with(" [") {
+                                self.state = State::Inline(Some(link));
+                                break Some(Ok(Item::PushOrderedList));
+                            }
+
                             self.state = State::BeforeItem;
-                            break Some(Item::Link { text, uri });
+                            match self.line_link(link) {
+                                Ok(item) => break Some(Ok(item)),
+                                Err(error) => break self.fail(error),
+                            }
                         }
 
                         // The Writer never outputs anything else
-                        _ => unreachable!("{:?}", ch),
+                        _ => break self.fail(Error::UnexpectedChar(ch)),
                     }
                 }
 
+                State::Inline(ref mut first) => {
+                    if let Some(link) = first.take() {
+                        break Some(Ok(link));
+                    }
+
+                    if self.chars.as_str().starts_with(" [") {
+                        self.chars.nth(1);
+                        match self.link() {
+                            Ok(link) => break Some(Ok(link)),
+                            Err(error) => break self.fail(error),
+                        }
+                    }
+
+                    self.end_line();
+                    self.state = State::BeforeItem;
+                    break Some(Ok(Item::PopList));
+                }
+
                 // If we've ran out of characters, just pop out of all the lists and return
-                State::EOF => {
-                    break if let Some(..) = self.indents.pop() {
-                        Some(Item::PopList)
+                State::Eof => {
+                    break if self.indents.pop().is_some() {
+                        Some(Ok(Item::PopList))
                     } else {
                         None
                     }
@@ -184,3 +630,5 @@ impl<'a> Iterator for Reader<'a> {
         }
     }
 }
+
+impl FusedIterator for Reader<'_> {}
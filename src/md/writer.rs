@@ -1,12 +1,103 @@
-use std::fmt;
-use std::io::{self, prelude::*};
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::error::{Error, Result};
 
 /// How many spaces do we indent with?
 pub const INDENT: usize = 4;
 
-/// Handles writing Markdown to a `Write` object
+/// Something Markdown can be written to
+///
+/// This is implemented for every `io::Write` when the `std` feature is enabled, and wrapping
+/// a `fmt::Write` in a `FmtOutput` works everywhere
+pub trait Output {
+    fn write_str(&mut self, s: &str) -> Result<()>;
+
+    /// Allows using `write!` on outputs
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result<()> {
+        struct Adapter<'a, O: ?Sized> {
+            output: &'a mut O,
+            error: Option<Error>,
+        }
+
+        impl<O: ?Sized + Output> fmt::Write for Adapter<'_, O> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.output.write_str(s).map_err(|error| {
+                    self.error = Some(error);
+                    fmt::Error
+                })
+            }
+        }
+
+        let mut adapter = Adapter {
+            output: self,
+            error: None,
+        };
+        match fmt::write(&mut adapter, args) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(adapter.error.unwrap_or(Error::FmtError(error))),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> Output for W {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        Ok(self.write_all(s.as_bytes())?)
+    }
+}
+
+/// An `Output` which writes to a `fmt::Write`, such as a `String`
+#[derive(Debug, Default)]
+pub struct FmtOutput<W>(pub W);
+
+impl<W: fmt::Write> Output for FmtOutput<W> {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        Ok(self.0.write_str(s)?)
+    }
+}
+
+/// Handles writing Markdown to an `Output`
 pub struct Writer<W> {
-    output: W,
+    output: Counting<W>,
+
+    /// When writing reference links, the number of every URI used so far
+    references: Option<BTreeMap<String, usize>>,
+
+    /// Buffer for rendering URIs to look them up in `references`
+    uri: String,
+
+    /// How many links and lists have been written so far
+    links: usize,
+    lists: usize,
+
+    /// Whether to put a blank line before each list which is an item of a top-level one
+    spaced_lists: bool,
+}
+
+/// Counts the bytes written to an `Output`, refusing to go past a limit if there's one
+struct Counting<W> {
+    inner: W,
+    written: usize,
+    limit: Option<usize>,
+}
+
+impl<W: Output> Output for Counting<W> {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        let written = self.written.saturating_add(s.len());
+        if self.limit.is_some_and(|limit| written > limit) {
+            return Err(Error::OutputLimitExceeded);
+        }
+
+        self.inner.write_str(s)?;
+        self.written = written;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -33,16 +124,47 @@ impl Bullet {
     }
 }
 
-pub struct EscapedFormatter<W: Write> {
-    output: W,
-    error: Option<io::Error>,
+/// Writes link text to an output with a backslash before every char which needs one
+pub struct EscapedFormatter<'a, W: Output> {
+    output: &'a mut W,
+    error: Option<Error>,
+}
+
+impl<'a, W: Output> EscapedFormatter<'a, W> {
+    pub(crate) fn new(output: &'a mut W) -> Self {
+        Self {
+            output,
+            error: None,
+        }
+    }
 }
 
+/// Whether `ch` gets a backslash in front of it in link text
+///
+/// Only `[`, `]` and `\` would strictly need one to find the end of the text, but escaping all
+/// ASCII punctuation keeps Markdown renderers from reading any of it as emphasis, code spans and
+/// the like. As `unescape` drops the backslash in front of any char, this is free to change
+/// without breaking documents which have already been written.
 fn should_escape(ch: char) -> bool {
     ch.is_ascii_punctuation()
 }
 
-impl<W: Write> fmt::Write for EscapedFormatter<W> {
+/// Write out `text` escaped, in as few writes as possible
+fn write_escaped<W: Output>(output: &mut W, text: &str) -> Result<()> {
+    let mut start = 0;
+    for (i, byte) in text.bytes().enumerate() {
+        // Only ASCII needs escaping, so we can safely slice around single bytes
+        if should_escape(byte as char) {
+            output.write_str(&text[start..i])?;
+            output.write_str("\\")?;
+            output.write_str(&text[i..=i])?;
+            start = i + 1;
+        }
+    }
+    output.write_str(&text[start..])
+}
+
+impl<W: Output> fmt::Write for EscapedFormatter<'_, W> {
     fn write_char(&mut self, ch: char) -> fmt::Result {
         let result = if should_escape(ch) {
             write!(self.output, "\\{}", ch)
@@ -60,60 +182,354 @@ impl<W: Write> fmt::Write for EscapedFormatter<W> {
     }
 
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        s.chars().try_for_each(|ch| self.write_char(ch))
+        match write_escaped(self.output, s) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                self.error = Some(error);
+                Err(fmt::Error)
+            }
+        }
+    }
+}
+
+/// An output which splits what a `Writer` writes into one line per list item
+///
+/// Only newlines outside of link text end a line, as the text itself may contain some
+#[derive(Debug, Default)]
+pub struct LineCapture {
+    lines: Vec<String>,
+    line: String,
+    in_text: bool,
+    escaped: bool,
+}
+
+impl LineCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the captured lines, without their newlines
+    pub fn into_lines(mut self) -> Vec<String> {
+        if !self.line.is_empty() {
+            self.end_line();
+        }
+        self.lines
+    }
+
+    fn end_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        self.lines.push(line);
+    }
+}
+
+impl Output for LineCapture {
+    fn write_str(&mut self, s: &str) -> Result<()> {
+        for ch in s.chars() {
+            if self.escaped {
+                self.escaped = false;
+            } else {
+                match ch {
+                    '\\' if self.in_text => self.escaped = true,
+                    '[' if !self.in_text => self.in_text = true,
+                    ']' if self.in_text => self.in_text = false,
+                    '\n' if !self.in_text => {
+                        self.end_line();
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            self.line.push(ch);
+        }
+
+        Ok(())
+    }
+}
+
+/// Check whether `uri` has to be wrapped in angle brackets to be a link destination
+///
+/// URIs with spaces or parentheses do, as they'd otherwise end the destination early, and as
+/// such can't have any angle brackets of their own. No URI may contain a newline.
+fn needs_angle_brackets(uri: &str) -> Result<bool> {
+    // Nearly every URI needs neither, so look for everything in one pass
+    let special = |b: &u8| matches!(b, b'\n' | b' ' | b'(' | b')');
+    if !uri.as_bytes().iter().any(special) && !uri.starts_with('<') {
+        return Ok(false);
+    }
+    if uri.contains('\n') {
+        return Err(Error::InvalidUri(uri.into()));
+    }
+    if uri.contains(['<', '>']) {
+        return Err(Error::InvalidUri(uri.into()));
     }
+    Ok(true)
 }
 
+/// A list which is being written, which keeps track of its depth and next bullet
+///
+/// Passing `None` instead of a list to any of `Writer`'s methods writes at the top level, with
+/// no bullet.
 #[derive(Debug, Clone, Copy)]
 pub struct List {
     depth: usize,
     bullet: Bullet,
 }
 
-impl<W: Write> Writer<W> {
+impl<W: Output> Writer<W> {
     pub fn new(output: W) -> Self {
-        Self { output }
+        Self {
+            output: Counting {
+                inner: output,
+                written: 0,
+                limit: None,
+            },
+            references: None,
+            uri: String::new(),
+            links: 0,
+            lists: 0,
+            spaced_lists: false,
+        }
     }
 
-    #[must_use]
-    pub fn ordered_list(&mut self, mut parent: Option<&mut List>) -> io::Result<List> {
-        if let Some(parent) = &mut parent {
-            self.bullet(Some(parent))?;
+    /// Write links as references to numbered definitions, e.g. `[text][3]`, which saves a lot of
+    /// space when the same long URIs are used over and over
+    ///
+    /// The definitions must be written at the end of the document with `references`.
+    pub fn set_reference_links(&mut self, enabled: bool) {
+        self.references = if enabled { Some(BTreeMap::new()) } else { None };
+    }
+
+    /// Put a blank line before each list nested right inside a top-level one, e.g. before every
+    /// field of a struct which is itself a list, so that big documents are easier to scan
+    ///
+    /// The reader skips blank lines, so this doesn't change what's read back.
+    pub fn set_spaced_lists(&mut self, enabled: bool) {
+        self.spaced_lists = enabled;
+    }
+
+    /// Write the definitions of the references used since the last call, if there are any
+    pub fn references(&mut self) -> Result<()> {
+        let references = match &mut self.references {
+            Some(references) if !references.is_empty() => core::mem::take(references),
+            _ => return Ok(()),
+        };
+
+        let mut definitions = references.into_iter().collect::<Vec<_>>();
+        definitions.sort_by_key(|&(_, n)| n);
+        writeln!(self.output)?;
+        for (uri, n) in definitions {
+            if needs_angle_brackets(&uri)? {
+                writeln!(self.output, "[{}]: <{}>", n, uri)?;
+            } else {
+                writeln!(self.output, "[{}]: {}", n, uri)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hand the references used so far over to `other`, which will write in this one's stead
+    /// until it hands them back
+    pub(crate) fn lend_references<O>(&mut self, other: &mut Writer<O>) {
+        other.references = self.references.take();
+    }
+
+    /// Write the part of a link after its text, either `(uri)` or a reference to it
+    fn target<URI: fmt::Display>(&mut self, uri: URI) -> Result<()> {
+        use fmt::Write;
+        self.uri.clear();
+        write!(self.uri, "{}", uri)?;
+
+        let references = match &mut self.references {
+            Some(references) => references,
+            None => {
+                let (open, close) = if needs_angle_brackets(&self.uri)? {
+                    ("(<", ">)")
+                } else {
+                    ("(", ")")
+                };
+                self.output.write_str(open)?;
+                self.output.write_str(&self.uri)?;
+                return self.output.write_str(close);
+            }
+        };
+
+        let n = match references.get(self.uri.as_str()) {
+            Some(&n) => n,
+            None => {
+                let n = references.len() + 1;
+                references.insert(self.uri.clone(), n);
+                n
+            }
+        };
+        write!(self.output, "[{}]", n)
+    }
+
+    /// Fail with `Error::OutputLimitExceeded` instead of writing more than `limit` bytes in total
+    pub fn set_max_bytes(&mut self, limit: Option<usize>) {
+        self.output.limit = limit;
+    }
+
+    /// How many more bytes may be written before hitting the limit set with `set_max_bytes`
+    pub fn remaining_bytes(&self) -> Option<usize> {
+        let written = self.output.written;
+        self.output.limit.map(|limit| limit.saturating_sub(written))
+    }
+
+    /// How many bytes have been written so far
+    pub fn bytes_written(&self) -> usize {
+        self.output.written
+    }
+
+    /// How many links have been written so far, counting checkboxes and the links in inline lists
+    /// but not those in anything written with `raw` or `fragment`
+    pub fn links_written(&self) -> usize {
+        self.links
+    }
+
+    /// How many lists have been started so far, not counting those in anything written with
+    /// `raw` or `fragment`
+    pub fn lists_written(&self) -> usize {
+        self.lists
+    }
+
+    /// Count links and lists which were written some other way, e.g. with `raw`
+    pub(crate) fn count(&mut self, links: usize, lists: usize) {
+        self.links += links;
+        self.lists += lists;
+    }
+
+    /// Start writing a new document to `output`, handing back the old one
+    ///
+    /// Whether to write reference links and the limit on the bytes written are kept, but the
+    /// references used so far and the counts of what's been written start over.
+    pub fn reset(&mut self, output: W) -> W {
+        if let Some(references) = &mut self.references {
+            references.clear();
+        }
+        self.output.written = 0;
+        self.links = 0;
+        self.lists = 0;
+        core::mem::replace(&mut self.output.inner, output)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.output.inner
+    }
+
+    /// Write out some already rendered Markdown verbatim
+    pub fn raw(&mut self, markdown: &str) -> Result<()> {
+        self.output.write_str(markdown)
+    }
+
+    /// Write out a whole document which was already rendered, e.g. by another `Writer`, as an
+    /// item of `list`
+    ///
+    /// Its lines are indented to the depth of `list`, except for those inside of link text, so that
+    /// the texts stay the same. It mustn't use reference links, as its definitions would end up
+    /// in the middle of this document.
+    pub fn fragment(&mut self, list: Option<&mut List>, markdown: &str) -> Result<()> {
+        let indent = match &list {
+            Some(list) => INDENT * (list.depth + 1),
+            None => return self.raw(markdown),
+        };
+        self.bullet(list)?;
+
+        // Like with `ordered_list`, a list's first item goes on the line after the bullet
+        let mut line_start = !markdown.starts_with('[');
+        if line_start {
             writeln!(self.output)?;
         }
 
+        // Once a link's text ends, only another link after a space, as in `inline_list`, can start
+        // more text on the same line
+        let (mut in_text, mut escaped, mut after_text) = (false, false, false);
+        let mut prev = '\n';
+        let mut start = 0;
+        for (i, ch) in markdown.char_indices() {
+            if line_start && ch != '\n' {
+                self.output.write_str(&markdown[start..i])?;
+                write!(self.output, "{:indent$}", "", indent = indent)?;
+                start = i;
+            }
+            line_start = false;
+
+            let prev = core::mem::replace(&mut prev, ch);
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match ch {
+                '\\' if in_text => escaped = true,
+                '[' if !in_text && (!after_text || prev == ' ') => in_text = true,
+                ']' if in_text => {
+                    in_text = false;
+                    after_text = true;
+                }
+                '\n' if !in_text => {
+                    after_text = false;
+                    line_start = true;
+                }
+                _ => {}
+            }
+        }
+        self.output.write_str(&markdown[start..])
+    }
+
+    /// Start a list with numbered bullets, as an item of `parent`
+    ///
+    /// Nothing is written for the list itself until its first item, so lists which are left empty
+    /// leave only an empty item in their parent behind.
+    #[must_use = "the list is needed to write its items"]
+    pub fn ordered_list(&mut self, mut parent: Option<&mut List>) -> Result<List> {
+        if let Some(parent) = &mut parent {
+            self.sublist_bullet(parent)?;
+        }
+
+        self.lists += 1;
         Ok(List {
             depth: parent.map_or(0, |parent| parent.depth + 1),
             bullet: Bullet::DottedNumber(0),
         })
     }
 
-    #[must_use]
-    pub fn unordered_list(&mut self, mut parent: Option<&mut List>) -> io::Result<List> {
+    /// Like `ordered_list`, but with `*` bullets
+    #[must_use = "the list is needed to write its items"]
+    pub fn unordered_list(&mut self, mut parent: Option<&mut List>) -> Result<List> {
         if let Some(parent) = &mut parent {
-            self.bullet(Some(parent))?;
-            writeln!(self.output)?;
+            self.sublist_bullet(parent)?;
         }
 
+        self.lists += 1;
         Ok(List {
             depth: parent.map_or(0, |parent| parent.depth + 1),
             bullet: Bullet::Asterisk,
         })
     }
 
-    fn escaped<T: fmt::Display>(&mut self, value: T) -> io::Result<()> {
+    fn escaped<T: fmt::Display>(&mut self, value: T) -> Result<()> {
         use fmt::Write;
-        let mut formatter = EscapedFormatter {
-            output: &mut self.output,
-            error: None,
-        };
+        let mut formatter = EscapedFormatter::new(&mut self.output);
         match formatter.write_fmt(format_args!("{}", value)) {
             Ok(()) => Ok(()),
-            Err(fmt::Error) => Err(formatter.error.unwrap()),
+            Err(error) => Err(formatter.error.unwrap_or(Error::FmtError(error))),
+        }
+    }
+
+    /// Write the bullet of the empty item of `parent` which a sublist goes in
+    fn sublist_bullet(&mut self, parent: &mut List) -> Result<()> {
+        if self.spaced_lists && parent.depth == 0 {
+            writeln!(self.output)?;
         }
+        self.bullet(Some(parent))?;
+        writeln!(self.output)
     }
 
-    fn bullet(&mut self, list: Option<&mut List>) -> io::Result<()> {
+    fn bullet(&mut self, list: Option<&mut List>) -> Result<()> {
         if let Some(List { depth, bullet }) = list {
             write!(
                 self.output,
@@ -127,39 +543,191 @@ impl<W: Write> Writer<W> {
         Ok(())
     }
 
+    /// Write out a link as an item of `list`, escaping all ASCII punctuation in its text
+    ///
+    /// URIs with spaces or parentheses are wrapped in angle brackets, e.g. `[text](<a (b)>)`, as
+    /// Markdown wants. A URI can't contain a newline, nor angle brackets when it has to be wrapped.
     pub fn link<Text: fmt::Display, URI: fmt::Display>(
         &mut self,
         list: Option<&mut List>,
         text: Text,
         uri: URI,
-    ) -> io::Result<()> {
+    ) -> Result<()> {
         self.bullet(list)?;
         write!(self.output, "[")?;
         self.escaped(text)?;
-        writeln!(self.output, "]({})", uri)?;
+        write!(self.output, "]")?;
+        self.target(uri)?;
+        writeln!(self.output)?;
+        self.links += 1;
+        Ok(())
+    }
+
+    /// Write out some already rendered links on a single line as an item of `list`, separated by
+    /// spaces, e.g. `[a](x) [b](y)`
+    ///
+    /// This is read back as an ordered list with an item for each link, so there must be at least
+    /// two of them, as a link alone is just a link. Each may end with a newline, as they do when
+    /// written at the top level by another `Writer`.
+    pub fn inline_list<'l, Links>(&mut self, list: Option<&mut List>, links: Links) -> Result<()>
+    where
+        Links: IntoIterator<Item = &'l str>,
+    {
+        self.bullet(list)?;
+        for (i, link) in links.into_iter().enumerate() {
+            if i > 0 {
+                self.output.write_str(" ")?;
+            }
+            self.output
+                .write_str(link.strip_suffix('\n').unwrap_or(link))?;
+            self.links += 1;
+        }
+        writeln!(self.output)?;
+        self.lists += 1;
         Ok(())
     }
 
+    /// Write out an already rendered link as an item of `list`, defined as the value of `key`,
+    /// e.g. `**name**: [Ferris](serde://string)`
+    ///
+    /// This is read back as an ordered list of the key, as an `Item::Key`, and the link, so it
+    /// counts as one link and one list.
+    ///
+    /// Keys with newlines in them are rejected with `Error::InvalidKey`, as the reader wouldn't
+    /// know where they start otherwise.
+    pub fn definition<Key: fmt::Display>(
+        &mut self,
+        list: Option<&mut List>,
+        key: Key,
+        link: &str,
+    ) -> Result<()> {
+        let key = key.to_string();
+        if key.contains('\n') {
+            return Err(Error::InvalidKey(key));
+        }
+        self.bullet(list)?;
+        self.output.write_str("**")?;
+        self.escaped(key)?;
+        self.output.write_str("**: ")?;
+        self.output
+            .write_str(link.strip_suffix('\n').unwrap_or(link))?;
+        writeln!(self.output)?;
+        self.links += 1;
+        self.lists += 1;
+        Ok(())
+    }
+
+    /// Write out a task list item, with the URI as an autolink after the checkbox
+    pub fn checkbox<URI: fmt::Display>(
+        &mut self,
+        list: Option<&mut List>,
+        checked: bool,
+        uri: URI,
+    ) -> Result<()> {
+        self.bullet(list)?;
+        let mark = if checked { 'x' } else { ' ' };
+        writeln!(self.output, "[{}] <{}>", mark, uri)?;
+        self.links += 1;
+        Ok(())
+    }
+
+    /// Like `link`, but for text which has already been rendered, skipping the formatting machinery
+    pub fn str_link<URI: fmt::Display>(
+        &mut self,
+        list: Option<&mut List>,
+        text: &str,
+        uri: URI,
+    ) -> Result<()> {
+        self.bullet(list)?;
+        write!(self.output, "[")?;
+        write_escaped(&mut self.output, text)?;
+        write!(self.output, "]")?;
+        self.target(uri)?;
+        writeln!(self.output)?;
+        self.links += 1;
+        Ok(())
+    }
+
+    /// Write out a link whose text is `buf` encoded in URL-safe base64
     pub fn bytes_link<URI: fmt::Display>(
         &mut self,
         list: Option<&mut List>,
         buf: &[u8],
         uri: URI,
-    ) -> io::Result<()> {
+    ) -> Result<()> {
         self.bullet(list)?;
         write!(self.output, "[")?;
 
-        {
-            // This new scope brought to you by borrowck
-            let mut encoder = base64::write::EncoderWriter::new(
-                &mut self.output,
-                base64::Config::new(base64::CharacterSet::UrlSafe, true),
-            );
-            encoder.write(buf)?;
-            encoder.finish()?;
+        // Encode in pieces whose length is a multiple of 3, so that only the last one is padded
+        let mut encoded = [0; 1024];
+        for chunk in buf.chunks(encoded.len() / 4 * 3) {
+            let len = base64::encode_config_slice(chunk, base64::URL_SAFE, &mut encoded);
+            let encoded = core::str::from_utf8(&encoded[..len]).expect("base64 is ASCII");
+            self.output.write_str(encoded)?;
+        }
+
+        write!(self.output, "]")?;
+        self.target(uri)?;
+        writeln!(self.output)?;
+        self.links += 1;
+        Ok(())
+    }
+
+    /// Like `bytes_link`, but encoded in lowercase hex, which is easier to read for short buffers
+    pub fn hex_link<URI: fmt::Display>(
+        &mut self,
+        list: Option<&mut List>,
+        buf: &[u8],
+        uri: URI,
+    ) -> Result<()> {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        self.bullet(list)?;
+        write!(self.output, "[")?;
+
+        let mut encoded = [0; 1024];
+        for chunk in buf.chunks(encoded.len() / 2) {
+            for (byte, digits) in chunk.iter().zip(encoded.chunks_mut(2)) {
+                digits[0] = DIGITS[usize::from(byte >> 4)];
+                digits[1] = DIGITS[usize::from(byte & 0xf)];
+            }
+            let encoded = &encoded[..chunk.len() * 2];
+            self.output
+                .write_str(core::str::from_utf8(encoded).expect("hex is ASCII"))?;
+        }
+
+        write!(self.output, "]")?;
+        self.target(uri)?;
+        writeln!(self.output)?;
+        self.links += 1;
+        Ok(())
+    }
+
+    /// Like `bytes_link`, but with the base64 in a fenced code block below the link
+    pub fn bytes_block<URI: fmt::Display>(
+        &mut self,
+        list: Option<&mut List>,
+        buf: &[u8],
+        uri: URI,
+    ) -> Result<()> {
+        // The block must be indented like the item's sublists would be to stay part of the item
+        let indent = list.as_ref().map_or(0, |list| INDENT * (list.depth + 1));
+        self.bullet(list)?;
+        write!(self.output, "[{} bytes]", buf.len())?;
+        self.target(uri)?;
+        writeln!(self.output)?;
+        self.links += 1;
+        writeln!(self.output, "{:indent$}```base64", "", indent = indent)?;
+
+        // Wrap lines at 76 characters, like MIME does
+        let mut encoded = [0; 76];
+        for chunk in buf.chunks(encoded.len() / 4 * 3) {
+            let len = base64::encode_config_slice(chunk, base64::URL_SAFE, &mut encoded);
+            let encoded = core::str::from_utf8(&encoded[..len]).expect("base64 is ASCII");
+            writeln!(self.output, "{:indent$}{}", "", encoded, indent = indent)?;
         }
 
-        writeln!(self.output, "]({})", uri)?;
+        writeln!(self.output, "{:indent$}```", "", indent = indent)?;
         Ok(())
     }
 }
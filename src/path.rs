@@ -0,0 +1,118 @@
+//! Lossless (de)serialization of paths, for use with `#[serde(with = "serde_mml::path")]`
+//!
+//! Serde refuses to serialize paths which aren't valid UTF-8, so these functions fall back to
+//! serializing the path's raw bytes instead, which end up base64 encoded under `serde://bytes`.
+//! Paths which are valid UTF-8 are still serialized as strings, so they stay readable.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{de, ser};
+
+pub fn serialize<P, S>(path: &P, serializer: S) -> Result<S::Ok, S::Error>
+where
+    P: ?Sized + AsRef<Path>,
+    S: ser::Serializer,
+{
+    let path = path.as_ref();
+    if let Some(s) = path.to_str() {
+        return serializer.serialize_str(s);
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        serializer.serialize_bytes(path.as_os_str().as_bytes())
+    }
+
+    #[cfg(not(unix))]
+    {
+        Err(ser::Error::custom("path contains invalid UTF-8 characters"))
+    }
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    struct PathVisitor;
+
+    impl<'de> de::Visitor<'de> for PathVisitor {
+        type Value = PathBuf;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a path as a string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            Ok(PathBuf::from(v))
+        }
+
+        fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+            Ok(PathBuf::from(v))
+        }
+
+        #[cfg(unix)]
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            self.visit_byte_buf(v.to_vec())
+        }
+
+        #[cfg(unix)]
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            use std::ffi::OsString;
+            use std::os::unix::ffi::OsStringExt;
+            Ok(PathBuf::from(OsString::from_vec(v)))
+        }
+    }
+
+    deserializer.deserialize_any(PathVisitor)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::ffi::{OsStr, OsString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{de, ser};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Entry {
+        #[serde(with = "crate::path")]
+        path: PathBuf,
+        name: OsString,
+    }
+
+    fn roundtrip(entry: &Entry) -> (String, Entry) {
+        let doc = ser::to_string(entry).unwrap();
+        let parsed = Entry::deserialize(&mut de::Deserializer::new(&doc)).unwrap();
+        (doc, parsed)
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let raw = OsStr::from_bytes(b"caf\xe9 \xff");
+        let entry = Entry {
+            path: PathBuf::from("/tmp").join(raw),
+            name: raw.to_owned(),
+        };
+
+        let (doc, parsed) = roundtrip(&entry);
+        assert!(doc.contains("(serde://bytes)"));
+        assert_eq!(parsed, entry);
+    }
+
+    #[test]
+    fn test_valid_utf8() {
+        let entry = Entry {
+            path: PathBuf::from("/tmp/café"),
+            name: OsString::from("café"),
+        };
+
+        let (doc, parsed) = roundtrip(&entry);
+        assert!(doc.contains("[\\/tmp\\/café](serde://string)"));
+        assert_eq!(parsed, entry);
+    }
+}
@@ -1,33 +1,411 @@
-use std::fmt;
-use std::io::prelude::*;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
 
 use serde::ser;
+#[cfg(feature = "content-hash")]
+use sha2::{Digest, Sha256};
 
 use crate::error::Error;
-use crate::md::{List, Writer};
-use crate::ty::Type;
+use crate::md::{FmtOutput, LineCapture, List, Output, Writer};
+use crate::ty::{self, Type};
 
-pub struct Serializer<W: Write> {
+/// Options which apply to a whole document, copied into every scratch serializer
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    scheme: &'static str,
+    summary_budget: Option<usize>,
+    bytes_chunk_size: Option<usize>,
+    sort_maps: bool,
+    task_lists: bool,
+    fenced_bytes: bool,
+    raw_bytes: bool,
+    hex_bytes: bool,
+    unknown_length_slash: bool,
+    scalar_text: Option<ScalarText>,
+    inline_threshold: usize,
+    float_points: bool,
+    spaced_lists: bool,
+    definition_lists: bool,
+    unit_text: &'static str,
+    none_text: &'static str,
+    unit_struct_text: Option<&'static str>,
+    #[cfg(feature = "bignum")]
+    bignum_strings: bool,
+    exact_struct_lengths: bool,
+}
+
+/// How much a `Serializer` has written so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerializeStats {
+    pub bytes: usize,
+
+    /// Links of any kind, including the headers of lists and the summaries of values
+    pub links: usize,
+
+    pub lists: usize,
+}
+
+/// Gives the text to show for a boolean or number, given its type and canonical text
+pub type ScalarText = fn(&Type<'_>, &str) -> Option<String>;
+
+pub struct Serializer<W: Output> {
     writer: Writer<W>,
     list: Option<List>,
+    config: Config,
+
+    /// Whether we're serializing into a scratch buffer to check a value against the budget
+    measuring: bool,
+
+    /// When measuring, the type of the value being measured
+    description: Option<String>,
+
+    /// Buffer for rendering scalars, kept around to avoid an allocation for each one
+    scalar: String,
 }
 
-pub struct SublistSerializer<'ser, W: Write> {
+pub struct SublistSerializer<'ser, W: Output> {
     serializer: &'ser mut Serializer<W>,
     parent: Option<List>,
+
+    /// While the seq may still go on a single line, its header and elements rendered as links
+    inline: Option<Vec<String>>,
+
+    /// The index of the next element, for locating errors
+    index: usize,
 }
 
-pub struct MapSerializer<'ser, W: Write> {
+pub struct MapSerializer<'ser, W: Output> {
     serializer: &'ser mut Serializer<W>,
     parent: Option<List>,
     map: Option<List>,
+
+    /// When sorting or counting, every rendered entry along with the length of its key
+    entries: Option<Vec<(usize, String)>>,
+
+    /// For structs, the header to write once we know how many fields were actually serialized
+    header: Option<Type<'static>>,
+
+    /// When buffering entries, the entry whose key has been serialized but whose value hasn't
+    pending: Option<(usize, Serializer<FmtOutput<String>>)>,
+
+    /// Whether a key has been serialized but its value hasn't yet
+    awaiting_value: bool,
+
+    /// When writing definitions, the key whose value hasn't been serialized yet, if it's a string
+    definition: Option<String>,
+
+    /// The index of the current entry, for locating errors
+    index: usize,
 }
 
-impl<W: Write> Serializer<W> {
+impl<W: Output> Serializer<W> {
     pub fn new(output: W) -> Self {
         Self {
             writer: Writer::new(output),
             list: None,
+            config: Config {
+                scheme: ty::DEFAULT_SCHEME,
+                summary_budget: None,
+                bytes_chunk_size: None,
+                sort_maps: false,
+                task_lists: false,
+                fenced_bytes: false,
+                raw_bytes: false,
+                hex_bytes: false,
+                unknown_length_slash: true,
+                scalar_text: None,
+                inline_threshold: 0,
+                float_points: false,
+                spaced_lists: false,
+                definition_lists: false,
+                unit_text: "()",
+                none_text: "None",
+                unit_struct_text: None,
+                #[cfg(feature = "bignum")]
+                bignum_strings: false,
+                exact_struct_lengths: false,
+            },
+            measuring: false,
+            description: None,
+            scalar: String::new(),
+        }
+    }
+
+    /// Use `scheme` instead of `serde` for the type URIs, e.g. `myapp` to write `myapp://bool`
+    pub fn with_scheme(mut self, scheme: &'static str) -> Self {
+        self.config.scheme = scheme;
+        self
+    }
+
+    /// Replace any nested value which would take up more than `bytes` with a summary link
+    ///
+    /// The resulting `serde://summary` links can't be deserialized, so this is only useful for
+    /// things like logging large values
+    pub fn summarize_over(mut self, bytes: usize) -> Self {
+        self.config.summary_budget = Some(bytes);
+        self
+    }
+
+    /// Split byte buffers longer than `bytes` into a list of separately encoded chunks
+    pub fn with_bytes_chunk_size(mut self, bytes: usize) -> Self {
+        self.config.bytes_chunk_size = Some(bytes);
+        self
+    }
+
+    /// Write map entries sorted by their serialized keys, so that e.g. a `HashMap` always comes
+    /// out the same regardless of its iteration order
+    pub fn with_sorted_maps(mut self, sort_maps: bool) -> Self {
+        self.config.sort_maps = sort_maps;
+        self
+    }
+
+    /// Write booleans as GitHub task list checkboxes, e.g. `* [x] <serde://bool>`
+    pub fn with_task_lists(mut self, task_lists: bool) -> Self {
+        self.config.task_lists = task_lists;
+        self
+    }
+
+    /// Write byte buffers as fenced code blocks of base64 below their link, which is easier on the eyes
+    pub fn with_fenced_bytes(mut self, fenced_bytes: bool) -> Self {
+        self.config.fenced_bytes = fenced_bytes;
+        self
+    }
+
+    /// Write byte buffers in lowercase hex rather than base64, e.g. `[cafe](serde://bytes/hex)`
+    ///
+    /// This takes precedence over `with_fenced_bytes`, but not over `with_raw_bytes`.
+    pub fn with_hex_bytes(mut self, hex_bytes: bool) -> Self {
+        self.config.hex_bytes = hex_bytes;
+        self
+    }
+
+    /// Write byte buffers which are valid UTF-8 as plain link text, e.g. `[hello](serde://raw_bytes)`,
+    /// rather than as base64
+    ///
+    /// Besides being readable, they can then be deserialized as a `&[u8]` borrowed from the
+    /// document, as long as their text didn't need escaping. Other buffers are still base64.
+    pub fn with_raw_bytes(mut self, raw_bytes: bool) -> Self {
+        self.config.raw_bytes = raw_bytes;
+        self
+    }
+
+    /// Show booleans and numbers with the text `scalar_text` gives for them, e.g. with thousands
+    /// separators, instead of their canonical text
+    ///
+    /// Whenever it gives some text, the canonical text is put after the type URI instead, e.g.
+    /// `[1,000,000](serde://u32#1000000)`, and that's what is deserialized.
+    pub fn with_scalar_text(mut self, scalar_text: ScalarText) -> Self {
+        self.config.scalar_text = Some(scalar_text);
+        self
+    }
+
+    /// Write floats with whole values with a trailing `.0`, e.g. `[1.0](serde://f64)` rather than
+    /// `[1](serde://f64)`, so that their text alone still says they're floats
+    pub fn with_float_points(mut self, float_points: bool) -> Self {
+        self.config.float_points = float_points;
+        self
+    }
+
+    /// Put a blank line before each list nested right inside the top-level one, e.g. before each
+    /// field of a struct which holds a nested struct or seq, which makes big documents easier to
+    /// read without changing what they deserialize as
+    pub fn with_spaced_lists(mut self, spaced_lists: bool) -> Self {
+        self.config.spaced_lists = spaced_lists;
+        self.writer.set_spaced_lists(spaced_lists);
+        self
+    }
+
+    /// Show `()` as `text` instead, e.g. an empty string for cleaner output
+    ///
+    /// Like for the other texts below, only the `serde://unit` URI matters when deserializing.
+    pub fn with_unit_text(mut self, text: &'static str) -> Self {
+        self.config.unit_text = text;
+        self
+    }
+
+    /// Show `None` as `text` instead, such as `null` or `-`
+    pub fn with_none_text(mut self, text: &'static str) -> Self {
+        self.config.none_text = text;
+        self
+    }
+
+    /// Show every unit struct as `text` instead of its name, which its URI has anyway
+    pub fn with_unit_struct_text(mut self, text: &'static str) -> Self {
+        self.config.unit_struct_text = Some(text);
+        self
+    }
+
+    /// Give strings which hold an integer or decimal number, such as those written by
+    /// `rust_decimal`, the `bignum` or `decimal` type instead of `string`
+    #[cfg(feature = "bignum")]
+    pub fn with_bignum_strings(mut self, bignum_strings: bool) -> Self {
+        self.config.bignum_strings = bignum_strings;
+        self
+    }
+
+    /// Give structs and struct variants the number of fields actually serialized in their
+    /// headers, rather than the declared number which hand-written `Serialize` impls may get wrong
+    ///
+    /// This renders every struct's fields to the side first, as `with_sorted_maps` does for maps,
+    /// which still stops as soon as they'd go over `with_max_bytes`.
+    pub fn with_exact_struct_lengths(mut self, exact_struct_lengths: bool) -> Self {
+        self.config.exact_struct_lengths = exact_struct_lengths;
+        self
+    }
+
+    /// Write map and struct entries whose key is a string and whose value is a single link as
+    /// definitions, e.g. `* **name**: [Ferris](serde://string)`, rather than as a list of the two
+    ///
+    /// Entries with any other kind of key or value, e.g. a nested struct, are written as usual.
+    pub fn with_definition_lists(mut self, definition_lists: bool) -> Self {
+        self.config.definition_lists = definition_lists;
+        self
+    }
+
+    /// Write seqs and tuples of up to `elements` elements on a single line, as long as each one is
+    /// a single link, e.g. `[Tuple of length 2](serde://tuple/2) [1](serde://u8) [2](serde://u8)`
+    ///
+    /// This reads back just like the usual sublist, so it's only a matter of looks.
+    pub fn with_inline_threshold(mut self, elements: usize) -> Self {
+        self.config.inline_threshold = elements;
+        self
+    }
+
+    /// Write seqs and maps of unknown length as e.g. `serde://seq` instead of `serde://seq/`, for
+    /// consumers which don't like empty path fragments
+    pub fn with_slashless_unknown_lengths(mut self, slashless: bool) -> Self {
+        self.config.unknown_length_slash = !slashless;
+        self
+    }
+
+    /// Fail with `Error::OutputLimitExceeded` rather than write more than `bytes` bytes
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.writer.set_max_bytes(Some(bytes));
+        self
+    }
+
+    /// Write each distinct type URI only once, in a list of numbered definitions at the end of
+    /// the document which the links refer to, e.g. `[1](serde://u8)` becomes `[1][2]`
+    ///
+    /// This makes large collections of the same type much smaller. The definitions are only
+    /// written by `finish`, which must be called once the value has been serialized.
+    pub fn with_reference_uris(mut self, reference_uris: bool) -> Self {
+        self.writer.set_reference_links(reference_uris);
+        self
+    }
+
+    /// How much has been written so far, e.g. to log once a value has been serialized
+    ///
+    /// Anything spliced in with `serialize_raw` only counts towards the bytes.
+    pub fn stats(&self) -> SerializeStats {
+        SerializeStats {
+            bytes: self.writer.bytes_written(),
+            links: self.writer.links_written(),
+            lists: self.writer.lists_written(),
+        }
+    }
+
+    /// Start over with `output`, handing back the old one, so that a serializer can be pooled
+    /// instead of built anew for every value
+    ///
+    /// All of the options are kept. Anything left over from a value which failed to serialize
+    /// halfway through is dropped, as are the reference URIs which haven't been written by
+    /// `finish`, and `stats` starts again from zero.
+    pub fn reset(&mut self, output: W) -> W {
+        self.list = None;
+        self.description = None;
+        self.writer.reset(output)
+    }
+
+    /// Write out whatever has to come after the value, i.e. the definitions for
+    /// `with_reference_uris`
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.writer.references()
+    }
+
+    /// Splice in a document which was already serialized, such as from `to_string`, as the
+    /// next value
+    ///
+    /// It's indented to fit wherever it goes, but otherwise written as is, so it must have been
+    /// serialized without `with_reference_uris`.
+    pub fn serialize_raw(&mut self, fragment: &str) -> Result<(), Error> {
+        self.writer.fragment(self.list.as_mut(), fragment)
+    }
+
+    /// A serializer with the same configuration and position as this one, writing to memory
+    ///
+    /// It borrows this one's reference URIs, which must be given back with `unscratch`, and may
+    /// only write as many bytes as this one has left, so that it fails just as early.
+    fn scratch(&mut self) -> Serializer<FmtOutput<String>> {
+        let mut writer = Writer::new(FmtOutput(String::new()));
+        writer.set_spaced_lists(self.config.spaced_lists);
+        writer.set_max_bytes(self.writer.remaining_bytes());
+        self.writer.lend_references(&mut writer);
+        Serializer {
+            writer,
+            list: self.list,
+            config: self.config,
+            measuring: self.measuring,
+            description: None,
+            scalar: String::new(),
+        }
+    }
+
+    /// Take back the reference URIs from a scratch serializer, and get what it wrote
+    fn unscratch(&mut self, mut scratch: Serializer<FmtOutput<String>>) -> String {
+        scratch.writer.lend_references(&mut self.writer);
+        scratch.writer.into_inner().0
+    }
+
+    fn describe(&mut self, ty: &Type) {
+        if self.measuring && self.description.is_none() {
+            self.description = Some(ty.path().to_string());
+        }
+    }
+
+    /// Serialize a value nested inside another one, summarizing it if it's over budget
+    fn ser_child<Value>(&mut self, value: &Value) -> Result<(), Error>
+    where
+        Value: ?Sized + ser::Serialize,
+    {
+        let budget = match self.config.summary_budget {
+            Some(budget) => budget,
+            None => return value.serialize(self),
+        };
+
+        // Measuring stops a byte past the budget, as by then it's clear the value won't fit, and
+        // it mustn't run into the output limit instead, as a summary might still fit
+        let mut scratch = self.scratch();
+        scratch.writer.set_max_bytes(Some(budget.saturating_add(1)));
+        scratch.config.summary_budget = None;
+        scratch.measuring = true;
+        let result = value.serialize(&mut scratch);
+        let (list, description) = (scratch.list, scratch.description.take());
+        let (links, lists) = (
+            scratch.writer.links_written(),
+            scratch.writer.lists_written(),
+        );
+        let buf = self.unscratch(scratch);
+
+        match result {
+            Ok(()) if buf.len() <= budget => {
+                self.writer.raw(&buf)?;
+                self.writer.count(links, lists);
+                self.list = list;
+                Ok(())
+            }
+
+            Ok(()) | Err(Error::OutputLimitExceeded) => {
+                let description = description.unwrap_or_default();
+                self.ser_primitive(
+                    format_args!("<{}, more than {} bytes>", description, budget),
+                    Type::Summary,
+                )
+            }
+
+            Err(error) => Err(error),
         }
     }
 
@@ -35,10 +413,64 @@ impl<W: Write> Serializer<W> {
     where
         Value: fmt::Display,
     {
-        self.writer.link(self.list.as_mut(), value, ty)?;
+        self.describe(&ty);
+        self.writer.link(
+            self.list.as_mut(),
+            value,
+            ty.with_scheme(self.config.scheme)
+                .unknown_length_slash(self.config.unknown_length_slash),
+        )?;
         Ok(())
     }
 
+    /// Serialize a number or boolean, which is rendered into a reused buffer first
+    fn ser_scalar<Value>(&mut self, value: Value, ty: Type) -> Result<(), Error>
+    where
+        Value: fmt::Display,
+    {
+        self.describe(&ty);
+        self.scalar.clear();
+        write!(self.scalar, "{}", value).expect("writing to a String can't fail");
+
+        let uri = ty.with_scheme(self.config.scheme);
+        match self
+            .config
+            .scalar_text
+            .and_then(|text| text(&ty, &self.scalar))
+        {
+            Some(text) => self.writer.str_link(
+                self.list.as_mut(),
+                &text,
+                format_args!("{}#{}", uri, self.scalar),
+            ),
+            None => self.writer.str_link(self.list.as_mut(), &self.scalar, uri),
+        }
+    }
+
+    fn ser_bytes(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if self.config.raw_bytes {
+            if let Ok(text) = core::str::from_utf8(buf) {
+                self.describe(&Type::RawBytes);
+                let uri = Type::RawBytes.with_scheme(self.config.scheme);
+                return self.writer.str_link(self.list.as_mut(), text, uri);
+            }
+        }
+
+        if self.config.hex_bytes {
+            self.describe(&Type::HexBytes);
+            let uri = Type::HexBytes.with_scheme(self.config.scheme);
+            return self.writer.hex_link(self.list.as_mut(), buf, uri);
+        }
+
+        self.describe(&Type::Bytes);
+        let uri = Type::Bytes.with_scheme(self.config.scheme);
+        if self.config.fenced_bytes {
+            self.writer.bytes_block(self.list.as_mut(), buf, uri)
+        } else {
+            self.writer.bytes_link(self.list.as_mut(), buf, uri)
+        }
+    }
+
     fn ser_newtype<TypeName, Value>(
         &mut self,
         ty_name: TypeName,
@@ -53,7 +485,7 @@ impl<W: Write> Serializer<W> {
         let sublist = self.writer.ordered_list(parent.as_mut())?;
         self.list = Some(sublist);
         self.ser_primitive(ty_name, ty)?;
-        value.serialize(&mut *self)?;
+        self.ser_child(value)?;
         self.list = parent;
         Ok(())
     }
@@ -73,9 +505,60 @@ impl<W: Write> Serializer<W> {
         Ok(SublistSerializer {
             serializer: self,
             parent,
+            inline: None,
+            index: 0,
+        })
+    }
+
+    /// Like `ser_seq`, but holding off on writing anything until it's clear whether the seq fits
+    /// on a single line
+    fn ser_short_seq<'ser, SeqName>(
+        &'ser mut self,
+        seq_name: SeqName,
+        ty: Type,
+    ) -> Result<SublistSerializer<'ser, W>, Error>
+    where
+        SeqName: fmt::Display,
+    {
+        if self.config.inline_threshold == 0 {
+            return self.ser_seq(seq_name, ty);
+        }
+
+        self.describe(&ty);
+        let mut scratch = self.scratch();
+        scratch.list = None;
+        scratch.ser_primitive(seq_name, ty)?;
+        let header = self.unscratch(scratch);
+
+        Ok(SublistSerializer {
+            serializer: self,
+            parent: None,
+            inline: Some(vec![header]),
+            index: 0,
         })
     }
 
+    /// Render a seq's element as a link on its own, or give up on that if it's anything else
+    ///
+    /// Whether it's a link is found out with a `LinkProbe` first, as rendering anything else
+    /// here only for it to be rendered again in place would take exponential time in how deeply
+    /// it's nested.
+    fn ser_inline<Value>(&mut self, value: &Value) -> Result<Option<String>, Error>
+    where
+        Value: ?Sized + ser::Serialize,
+    {
+        if value.serialize(LinkProbe(&self.config)).is_err() {
+            return Ok(None);
+        }
+
+        let mut scratch = self.scratch();
+        scratch.list = None;
+        scratch.ser_child(value)?;
+        let link = self.unscratch(scratch);
+        debug_assert!(is_single_link(&link), "{}", link);
+        Ok(Some(link))
+    }
+
     fn ser_map<'ser, MapName>(
         &'ser mut self,
         map_name: MapName,
@@ -88,205 +571,293 @@ impl<W: Write> Serializer<W> {
         let sublist = self.writer.unordered_list(parent.as_mut())?;
         self.list = Some(sublist);
         self.ser_primitive(map_name, ty)?;
+        let entries = if self.config.sort_maps {
+            Some(Vec::new())
+        } else {
+            None
+        };
+
         Ok(MapSerializer {
             serializer: self,
             parent,
             map: None,
+            entries,
+            header: None,
+            pending: None,
+            awaiting_value: false,
+            definition: None,
+            index: 0,
         })
     }
-}
 
-macro_rules! serialize_int {
-    ($($name:ident: $ty:ty => $enum_ty:expr,)*) => {
-        $(
-        fn $name(self, num: $ty) -> Result<Self::Ok, Self::Error> {
-            self.ser_primitive(num, $enum_ty)
+    /// Like `ser_map`, but buffering the fields so that the header has the number of fields
+    /// actually serialized rather than the declared one, which may be wrong
+    fn ser_struct<'ser>(
+        &'ser mut self,
+        ty: Type<'static>,
+    ) -> Result<MapSerializer<'ser, W>, Error> {
+        self.describe(&ty);
+        let mut parent = self.list.take();
+        let sublist = self.writer.unordered_list(parent.as_mut())?;
+        self.list = Some(sublist);
+
+        Ok(MapSerializer {
+            serializer: self,
+            parent,
+            map: None,
+            entries: Some(Vec::new()),
+            header: Some(ty),
+            pending: None,
+            awaiting_value: false,
+            definition: None,
+            index: 0,
+        })
+    }
+
+    /// Find out whether `key` is a string which can be the key of a definition, and if so, what
+    fn definition_key<Key>(&self, key: &Key) -> Result<Option<String>, Error>
+    where
+        Key: ?Sized + ser::Serialize,
+    {
+        // Without borrowing the reference URIs, as the key's URI won't be written
+        let mut scratch = Serializer::new(FmtOutput(String::new()));
+        scratch.config = self.config;
+        scratch.measuring = true;
+        key.serialize(&mut scratch)?;
+
+        let is_string = scratch.description.as_deref() == Some("string");
+        let rendered = scratch.writer.into_inner().0;
+        if !is_string || !is_single_link(&rendered) {
+            return Ok(None);
         }
-        )*
-    };
+        let text = &rendered[1..rendered.rfind("](").expect("checked to be a link")];
+        Ok(Some(crate::md::unescape(text)).filter(|key| !key.contains('\n')))
+    }
+
+    /// Start a map entry, returning the map's list to restore once the value is serialized
+    fn ser_key<Key>(&mut self, key: &Key) -> Result<Option<List>, Error>
+    where
+        Key: ?Sized + ser::Serialize,
+    {
+        let pair = self.writer.ordered_list(self.list.as_mut())?;
+        let map = self.list.replace(pair);
+        key.serialize(&mut *self)?;
+        Ok(map)
+    }
+
+    fn ser_value<Value>(&mut self, value: &Value, map: Option<List>) -> Result<(), Error>
+    where
+        Value: ?Sized + ser::Serialize,
+    {
+        self.ser_child(value)?;
+        self.list = map;
+        Ok(())
+    }
 }
 
-impl<'ser, W: Write> ser::Serializer for &'ser mut Serializer<W> {
-    type Ok = ();
-    type Error = Error;
+/// Check whether `rendered`, a value serialized at the top level, is nothing but a link on one line
+///
+/// Anything else at the top level starts with a bullet, except for byte buffers written as a link
+/// followed by a code block.
+fn is_single_link(rendered: &str) -> bool {
+    let text = match rendered.strip_prefix('[') {
+        Some(text) => text,
+        None => return false,
+    };
 
-    type SerializeSeq = SublistSerializer<'ser, W>;
-    type SerializeTuple = SublistSerializer<'ser, W>;
-    type SerializeTupleStruct = SublistSerializer<'ser, W>;
-    type SerializeTupleVariant = SublistSerializer<'ser, W>;
-    type SerializeMap = MapSerializer<'ser, W>;
-    type SerializeStruct = MapSerializer<'ser, W>;
-    type SerializeStructVariant = MapSerializer<'ser, W>;
+    let mut escaped = false;
+    let end = text.find(|ch| {
+        let found = !escaped && ch == ']';
+        escaped = !escaped && ch == '\\';
+        found
+    });
+    end.is_some_and(|end| text[end..].find('\n') == Some(text.len() - end - 1))
+}
 
-    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive(v, Type::Bool)
+/// Returned by `LinkProbe` as soon as a value turns out not to be a single link
+#[derive(Debug)]
+struct NotALink;
+
+impl fmt::Display for NotALink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("not a single link")
     }
+}
 
-    serialize_int! {
-        serialize_i8: i8 => Type::I8,
-        serialize_i16: i16 => Type::I16,
-        serialize_i32: i32 => Type::I32,
-        serialize_i64: i64 => Type::I64,
-        serialize_u8: u8 => Type::U8,
-        serialize_u16: u16 => Type::U16,
-        serialize_u32: u32 => Type::U32,
-        serialize_u64: u64 => Type::U64,
-        serialize_f32: f32 => Type::F32,
-        serialize_f64: f64 => Type::F64,
+impl ser::StdError for NotALink {}
+
+impl ser::Error for NotALink {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        NotALink
     }
+}
+
+/// A serializer which writes nothing, but finds out whether a value would be written as a single
+/// link, stopping at the first list it would start
+///
+/// Containers are never single links here, even if they'd be summarized, so that a value is only
+/// ever rendered for real once. Errors from the value itself count as not a link, and are left
+/// for the real serializer to run into.
+struct LinkProbe<'a>(&'a Config);
 
-    serde::serde_if_integer128! {
-        serialize_int! {
-            serialize_i128: i128 => Type::I128,
-            serialize_u128: u128 => Type::U128,
+macro_rules! probe_leaves {
+    ($($name:ident: $ty:ty,)*) => {
+        $(
+        fn $name(self, _v: $ty) -> Result<Self::Ok, Self::Error> {
+            Ok(())
         }
-    }
+        )*
+    };
+}
 
-    fn serialize_char(self, ch: char) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive(ch, Type::Char)
-    }
+impl ser::Serializer for LinkProbe<'_> {
+    type Ok = ();
+    type Error = NotALink;
 
-    fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive(s, Type::String)
+    type SerializeSeq = ser::Impossible<(), NotALink>;
+    type SerializeTuple = ser::Impossible<(), NotALink>;
+    type SerializeTupleStruct = ser::Impossible<(), NotALink>;
+    type SerializeTupleVariant = ser::Impossible<(), NotALink>;
+    type SerializeMap = ser::Impossible<(), NotALink>;
+    type SerializeStruct = ser::Impossible<(), NotALink>;
+    type SerializeStructVariant = ser::Impossible<(), NotALink>;
+
+    probe_leaves! {
+        serialize_bool: bool,
+        serialize_i8: i8,
+        serialize_i16: i16,
+        serialize_i32: i32,
+        serialize_i64: i64,
+        serialize_i128: i128,
+        serialize_u8: u8,
+        serialize_u16: u16,
+        serialize_u32: u32,
+        serialize_u64: u64,
+        serialize_u128: u128,
+        serialize_f32: f32,
+        serialize_f64: f64,
+        serialize_char: char,
+        serialize_str: &str,
+        serialize_unit_struct: &'static str,
     }
 
     fn serialize_bytes(self, buf: &[u8]) -> Result<Self::Ok, Self::Error> {
-        // not worth it to make a ser_bytes_link
-        self.writer
-            .bytes_link(self.list.as_mut(), buf, Type::Bytes)?;
-        Ok(())
+        // Mirrors `Serializer::serialize_bytes` and `ser_bytes`
+        let config = self.0;
+        let chunked = config
+            .bytes_chunk_size
+            .is_some_and(|chunk_size| buf.len() > chunk_size);
+        let fenced = config.fenced_bytes
+            && !config.hex_bytes
+            && !(config.raw_bytes && core::str::from_utf8(buf).is_ok());
+        if chunked || fenced {
+            Err(NotALink)
+        } else {
+            Ok(())
+        }
     }
 
     fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive("None", Type::None)
+        Ok(())
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    fn serialize_some<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
-        self.ser_newtype("Some", Type::Some, value)
+        Err(NotALink)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive("()", Type::Unit)
-    }
-
-    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive("name", Type::UnitStruct(name))
+        Ok(())
     }
 
     fn serialize_unit_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
+        _variant: &'static str,
     ) -> Result<Self::Ok, Self::Error> {
-        self.ser_primitive(
-            format_args!("{}::{}", name, variant),
-            Type::UnitVariant(name, variant),
-        )
+        Ok(())
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
+    fn serialize_newtype_struct<T>(
         self,
-        name: &'static str,
-        value: &T,
+        _name: &'static str,
+        _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
-        self.ser_newtype(name, Type::NewtypeStruct(name), value)
+        Err(NotALink)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
-        value: &T,
+        _variant: &'static str,
+        _value: &T,
     ) -> Result<Self::Ok, Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
-        self.ser_newtype(
-            format_args!("{}::{}", name, variant),
-            Type::NewtypeVariant(name, variant),
-            value,
-        )
+        Err(NotALink)
     }
 
-    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
-        match len {
-            Some(len) => self.ser_seq(format_args!("Seq of length {}", len), Type::Seq(Some(len))),
-            None => self.ser_seq(format_args!("Seq of unknown length"), Type::Seq(None)),
-        }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(NotALink)
     }
 
-    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
-        self.ser_seq(format_args!("Tuple of length {}", len), Type::Tuple(len))
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(NotALink)
     }
 
     fn serialize_tuple_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeTupleStruct, Self::Error> {
-        self.ser_seq(
-            format_args!("Tuple struct {} of length {}", name, len),
-            Type::TupleStruct(name, len),
-        )
+        Err(NotALink)
     }
 
     fn serialize_tuple_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
-        len: usize,
+        _variant: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeTupleVariant, Self::Error> {
-        self.ser_seq(
-            format_args!("Tuple variant {}::{} of length {}", name, variant, len),
-            Type::TupleVariant(name, variant, len),
-        )
+        Err(NotALink)
     }
 
-    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        match len {
-            Some(len) => self.ser_map(format_args!("Map of length {}", len), Type::Map(Some(len))),
-            None => self.ser_map("Map of unknown length", Type::Map(None)),
-        }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(NotALink)
     }
 
     fn serialize_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeStruct, Self::Error> {
-        self.ser_map(
-            format_args!("Struct {} of length {}", name, len),
-            Type::Struct(name, len),
-        )
+        Err(NotALink)
     }
 
     fn serialize_struct_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
-        len: usize,
+        _variant: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeStructVariant, Self::Error> {
-        self.ser_map(
-            format_args!("Struct variant {}::{} of length {}", name, variant, len),
-            Type::StructVariant(name, variant, len),
-        )
+        Err(NotALink)
     }
 
-    fn collect_str<T: ?Sized>(self, s: &T) -> Result<Self::Ok, Self::Error>
+    fn collect_str<T>(self, _value: &T) -> Result<Self::Ok, Self::Error>
     where
-        T: fmt::Display,
+        T: ?Sized + fmt::Display,
     {
-        self.ser_primitive(s, Type::String)
+        Ok(())
     }
 
     fn is_human_readable(&self) -> bool {
@@ -294,30 +865,443 @@ impl<'ser, W: Write> ser::Serializer for &'ser mut Serializer<W> {
     }
 }
 
-impl<'ser, W: Write> ser::SerializeSeq for SublistSerializer<'ser, W> {
-    type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
+/// Serialize `value` to a `String`
+pub fn to_string<T>(value: &T) -> Result<String, Error>
+where
+    T: ?Sized + ser::Serialize,
+{
+    let mut buf = String::new();
+    to_fmt_writer(&mut buf, value)?;
+    Ok(buf)
+}
+
+/// Serialize `value` to a `fmt::Write`, such as a `String` being built up or a `fmt::Formatter`
+pub fn to_fmt_writer<W, T>(writer: W, value: &T) -> Result<(), Error>
+where
+    W: fmt::Write,
+    T: ?Sized + ser::Serialize,
+{
+    let mut serializer = Serializer::new(FmtOutput(writer));
+    value.serialize(&mut serializer)?;
+    serializer.finish()
+}
+
+/// Shows a value as MML, for embedding it into format strings and log lines
+///
+/// Serializing errors become a `fmt::Error`, which makes `to_string` and `format!` panic
+#[derive(Debug, Clone, Copy)]
+pub struct Display<'a, T: ?Sized>(pub &'a T);
+
+impl<T: ?Sized + ser::Serialize> fmt::Display for Display<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        to_fmt_writer(f, self.0).map_err(|_| fmt::Error)
+    }
+}
+
+/// Wrap `value` to show it as MML with `{}`
+pub fn display<T: ?Sized + ser::Serialize>(value: &T) -> Display<'_, T> {
+    Display(value)
+}
+
+/// Serialize `value` into one string per list item, for line-based post-processing
+///
+/// The lines don't include their trailing newline, so joining them back together with a newline
+/// after each gives the same result as `to_string`
+pub fn to_lines<T>(value: &T) -> Result<Vec<String>, Error>
+where
+    T: ?Sized + ser::Serialize,
+{
+    let mut serializer = Serializer::new(LineCapture::new());
+    value.serialize(&mut serializer)?;
+    Ok(serializer.writer.into_inner().into_lines())
+}
+
+/// Serialize `value` canonically, i.e. with sorted maps, and hash the result with SHA-256
+///
+/// Only the order of map and struct entries is normalized, so two `HashMap`s with the same
+/// entries always have the same hash. Seqs keep their order, since a serializer can't tell a
+/// `HashSet` from a `Vec`: collect sets into a `BTreeSet` before hashing them. Strings are always
+/// escaped the same way, and floats are written in their shortest round-tripping form, so each
+/// float has one spelling; `-0.0` and `0.0` hash differently, while all NaNs hash the same
+#[cfg(feature = "content-hash")]
+pub fn content_hash<T>(value: &T) -> Result<[u8; 32], Error>
+where
+    T: ?Sized + ser::Serialize,
+{
+    struct HashWriter(Sha256);
+
+    impl Output for HashWriter {
+        fn write_str(&mut self, s: &str) -> Result<(), Error> {
+            self.0.update(s);
+            Ok(())
+        }
+    }
+
+    let mut serializer = Serializer::new(HashWriter(Sha256::new())).with_sorted_maps(true);
+    value.serialize(&mut serializer)?;
+    Ok(serializer.writer.into_inner().0.finalize().into())
+}
+
+/// Recognize strings which hold arbitrary precision numbers, as written by e.g. `rust_decimal`
+#[cfg(feature = "bignum")]
+fn number_type(s: &str) -> Option<Type<'static>> {
+    fn digits(s: &str) -> bool {
+        !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    let s = s.strip_prefix('-').unwrap_or(s);
+    match s.split_once('.') {
+        None if digits(s) => Some(Type::BigNum),
+        Some((int, frac)) if digits(int) && digits(frac) => Some(Type::Decimal),
+        _ => None,
+    }
+}
+
+macro_rules! serialize_int {
+    ($($name:ident: $ty:ty => $enum_ty:expr,)*) => {
+        $(
+        fn $name(self, num: $ty) -> Result<Self::Ok, Self::Error> {
+            self.ser_scalar(num, $enum_ty)
+        }
+        )*
+    };
+}
+
+impl<'ser, W: Output> ser::Serializer for &'ser mut Serializer<W> {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = SublistSerializer<'ser, W>;
+    type SerializeTuple = SublistSerializer<'ser, W>;
+    type SerializeTupleStruct = SublistSerializer<'ser, W>;
+    type SerializeTupleVariant = SublistSerializer<'ser, W>;
+    type SerializeMap = MapSerializer<'ser, W>;
+    type SerializeStruct = MapSerializer<'ser, W>;
+    type SerializeStructVariant = MapSerializer<'ser, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        if self.config.task_lists {
+            self.describe(&Type::Bool);
+            self.writer.checkbox(
+                self.list.as_mut(),
+                v,
+                Type::Bool.with_scheme(self.config.scheme),
+            )?;
+            Ok(())
+        } else {
+            self.ser_scalar(v, Type::Bool)
+        }
+    }
+
+    serialize_int! {
+        serialize_i8: i8 => Type::I8,
+        serialize_i16: i16 => Type::I16,
+        serialize_i32: i32 => Type::I32,
+        serialize_i64: i64 => Type::I64,
+        serialize_u8: u8 => Type::U8,
+        serialize_u16: u16 => Type::U16,
+        serialize_u32: u32 => Type::U32,
+        serialize_u64: u64 => Type::U64,
+        serialize_i128: i128 => Type::I128,
+        serialize_u128: u128 => Type::U128,
+    }
+
+    // Floats are always written with `core`'s own formatting, which has no notion of locale: the
+    // decimal separator is always `.`, and the shortest text which parses back exactly is used.
+    // That never has an exponent, so a whole value only needs `.0` added to get a point.
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        if self.config.float_points && v.is_finite() && v.fract() == 0.0 {
+            self.ser_scalar(format_args!("{:.1}", v), Type::F32)
+        } else {
+            self.ser_scalar(v, Type::F32)
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        if self.config.float_points && v.is_finite() && v.fract() == 0.0 {
+            self.ser_scalar(format_args!("{:.1}", v), Type::F64)
+        } else {
+            self.ser_scalar(v, Type::F64)
+        }
+    }
+
+    fn serialize_char(self, ch: char) -> Result<Self::Ok, Self::Error> {
+        self.ser_primitive(ch, Type::Char)
+    }
+
+    fn serialize_str(self, s: &str) -> Result<Self::Ok, Self::Error> {
+        #[cfg(feature = "bignum")]
+        {
+            if let Some(ty) = number_type(s).filter(|_| self.config.bignum_strings) {
+                return self.ser_primitive(s, ty);
+            }
+        }
+
+        self.ser_primitive(s, Type::String)
+    }
+
+    fn serialize_bytes(self, buf: &[u8]) -> Result<Self::Ok, Self::Error> {
+        match self.config.bytes_chunk_size {
+            Some(chunk_size) if buf.len() > chunk_size => {
+                let chunks = self.ser_seq(
+                    format_args!("Bytes of length {}", buf.len()),
+                    Type::ByteChunks(buf.len()),
+                )?;
+                for chunk in buf.chunks(chunk_size) {
+                    chunks.serializer.ser_bytes(chunk)?;
+                }
+                ser::SerializeSeq::end(chunks)
+            }
+
+            _ => self.ser_bytes(buf),
+        }
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        self.ser_primitive(self.config.none_text, Type::None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.ser_newtype("Some", Type::Some, value)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.ser_primitive(self.config.unit_text, Type::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        let text = self.config.unit_struct_text.unwrap_or(name);
+        self.ser_primitive(text, Type::UnitStruct(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.ser_primitive(
+            format_args!("{}::{}", name, variant),
+            Type::UnitVariant(name, variant),
+        )
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.ser_newtype(name, Type::NewtypeStruct(name), value)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        self.ser_newtype(
+            format_args!("{}::{}", name, variant),
+            Type::NewtypeVariant(name, variant),
+            value,
+        )
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        match len {
+            Some(len) => {
+                self.ser_short_seq(format_args!("Seq of length {}", len), Type::Seq(Some(len)))
+            }
+            None => self.ser_short_seq(format_args!("Seq of unknown length"), Type::Seq(None)),
+        }
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.ser_short_seq(format_args!("Tuple of length {}", len), Type::Tuple(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.ser_short_seq(
+            format_args!("Tuple struct {} of length {}", name, len),
+            Type::TupleStruct(name, len),
+        )
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        self.ser_short_seq(
+            format_args!("{}::{}", name, variant),
+            Type::TupleVariant(name, variant, len),
+        )
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        match len {
+            Some(len) => self.ser_map(format_args!("Map of length {}", len), Type::Map(Some(len))),
+            None => self.ser_map("Map of unknown length", Type::Map(None)),
+        }
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        if self.config.exact_struct_lengths {
+            return self.ser_struct(Type::Struct(name, len));
+        }
+        self.ser_map(
+            format_args!("Struct {} of length {}", name, len),
+            Type::Struct(name, len),
+        )
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        if self.config.exact_struct_lengths {
+            return self.ser_struct(Type::StructVariant(name, variant, len));
+        }
+        self.ser_map(
+            format_args!("{}::{}", name, variant),
+            Type::StructVariant(name, variant, len),
+        )
+    }
+
+    fn collect_str<T>(self, s: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: ?Sized + fmt::Display,
+    {
+        self.ser_primitive(s, Type::String)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+}
+
+impl<W: Output> SublistSerializer<'_, W> {
+    /// Like `Serializer::serialize_raw`, splicing in an already serialized element
+    pub fn serialize_raw_element(&mut self, fragment: &str) -> Result<(), Error> {
+        self.index += 1;
+        self.unline()?;
+        self.serializer.serialize_raw(fragment)
+    }
+
+    /// Serialize an element while the seq may still go on a single line
+    fn inline_element<T>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: ?Sized + ser::Serialize,
+    {
+        let threshold = self.serializer.config.inline_threshold;
+        match (self.serializer.ser_inline(value)?, &mut self.inline) {
+            // The header is in there too
+            (Some(link), Some(links)) if links.len() <= threshold => {
+                links.push(link);
+                Ok(())
+            }
+
+            (Some(link), _) => {
+                self.unline()?;
+                self.serializer.serialize_raw(&link)?;
+                self.serializer.writer.count(1, 0);
+                Ok(())
+            }
+
+            // Serialize it in place, so that any seqs inside it may go on single lines
+            (None, _) => {
+                self.unline()?;
+                self.serializer.ser_child(value)
+            }
+        }
+    }
+
+    /// Give up on writing the seq on a single line, and write what's been held back as a sublist
+    fn unline(&mut self) -> Result<(), Error> {
+        let links = match self.inline.take() {
+            Some(links) => links,
+            None => return Ok(()),
+        };
+
+        let serializer = &mut *self.serializer;
+        self.parent = serializer.list.take();
+        let mut sublist = serializer.writer.ordered_list(self.parent.as_mut())?;
+        for link in &links {
+            serializer.writer.fragment(Some(&mut sublist), link)?;
+        }
+        serializer.writer.count(links.len(), 0);
+        serializer.list = Some(sublist);
+        Ok(())
+    }
+}
+
+impl<'ser, W: Output> ser::SerializeSeq for SublistSerializer<'ser, W> {
+    type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
-        value.serialize(&mut *self.serializer)
+        let index = self.index;
+        self.index += 1;
+        let result = if self.inline.is_some() {
+            self.inline_element(value)
+        } else {
+            self.serializer.ser_child(value)
+        };
+        result.map_err(|error| error.at(format_args!("[{}]", index)))
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        match &self.inline {
+            // An empty seq on its own line would just be its header
+            Some(links) if links.len() > 1 => {
+                let serializer = self.serializer;
+                let links = links.iter().map(String::as_str);
+                return serializer
+                    .writer
+                    .inline_list(serializer.list.as_mut(), links);
+            }
+            Some(_) => self.unline()?,
+            None => {}
+        }
+
         self.serializer.list = self.parent;
         Ok(())
     }
 }
 
-impl<'ser, W: Write> ser::SerializeTuple for SublistSerializer<'ser, W> {
+impl<'ser, W: Output> ser::SerializeTuple for SublistSerializer<'ser, W> {
     type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
         <Self as ser::SerializeSeq>::serialize_element(self, value)
     }
@@ -327,13 +1311,13 @@ impl<'ser, W: Write> ser::SerializeTuple for SublistSerializer<'ser, W> {
     }
 }
 
-impl<'ser, W: Write> ser::SerializeTupleStruct for SublistSerializer<'ser, W> {
+impl<'ser, W: Output> ser::SerializeTupleStruct for SublistSerializer<'ser, W> {
     type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
         <Self as ser::SerializeSeq>::serialize_element(self, value)
     }
@@ -343,13 +1327,13 @@ impl<'ser, W: Write> ser::SerializeTupleStruct for SublistSerializer<'ser, W> {
     }
 }
 
-impl<'ser, W: Write> ser::SerializeTupleVariant for SublistSerializer<'ser, W> {
+impl<'ser, W: Output> ser::SerializeTupleVariant for SublistSerializer<'ser, W> {
     type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: ser::Serialize,
+        T: ?Sized + ser::Serialize,
     {
         <Self as ser::SerializeSeq>::serialize_element(self, value)
     }
@@ -359,52 +1343,170 @@ impl<'ser, W: Write> ser::SerializeTupleVariant for SublistSerializer<'ser, W> {
     }
 }
 
-impl<'ser, W: Write> ser::SerializeMap for MapSerializer<'ser, W> {
-    type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
-    type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
+impl<W: Output> MapSerializer<'_, W> {
+    /// Serialize an entry's key, leaving it to the caller to say where any error happened
+    fn key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        if self.awaiting_value {
+            return Err(Error::KeyValueMisorder);
+        }
+        self.awaiting_value = true;
+
+        if self.serializer.config.definition_lists {
+            self.definition = self.serializer.definition_key(key)?;
+            if self.definition.is_some() {
+                return Ok(());
+            }
+        }
+        self.entry_key(key)
+    }
+
+    /// Start an entry in the usual form, a list of its key and value
+    fn entry_key<T>(&mut self, key: &T) -> Result<(), Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        if self.entries.is_some() {
+            let mut scratch = self.serializer.scratch();
+            self.map = scratch.ser_key(key)?;
+            self.pending = Some((scratch.writer.get_ref().0.len(), scratch));
+        } else {
+            self.map = self.serializer.ser_key(key)?;
+        }
+        Ok(())
+    }
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
+    /// Serialize the value for the key which has just been serialized
+    fn value<T>(&mut self, value: &T) -> Result<(), Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        let pair = self
-            .serializer
+        if !self.awaiting_value {
+            return Err(Error::KeyValueMisorder);
+        }
+        self.awaiting_value = false;
+        self.index += 1;
+
+        if let Some(key) = self.definition.take() {
+            match self.serializer.ser_inline(value)? {
+                Some(link) => return self.definition(&key, &link),
+                None => self.entry_key(key.as_str())?,
+            }
+        }
+
+        match (&mut self.entries, self.pending.take()) {
+            (Some(entries), Some((key_len, mut scratch))) => {
+                scratch.ser_value(value, self.map.take())?;
+
+                // Every entry gets written in the end
+                let (links, lists) = (
+                    scratch.writer.links_written(),
+                    scratch.writer.lists_written(),
+                );
+                self.serializer.writer.count(links, lists);
+                entries.push((key_len, self.serializer.unscratch(scratch)));
+                Ok(())
+            }
+
+            _ => self.serializer.ser_value(value, self.map.take()),
+        }
+    }
+
+    /// Write out an entry as a definition, given its value rendered as a link
+    fn definition(&mut self, key: &str, link: &str) -> Result<(), Error> {
+        let entries = match &mut self.entries {
+            Some(entries) => entries,
+            None => {
+                let serializer = &mut *self.serializer;
+                return serializer
+                    .writer
+                    .definition(serializer.list.as_mut(), key, link);
+            }
+        };
+
+        let mut scratch = self.serializer.scratch();
+        scratch
             .writer
-            .ordered_list(self.serializer.list.as_mut())?;
+            .definition(scratch.list.as_mut(), key, link)?;
+        let (links, lists) = (
+            scratch.writer.links_written(),
+            scratch.writer.lists_written(),
+        );
+        self.serializer.writer.count(links, lists);
+        let entry = self.serializer.unscratch(scratch);
 
-        self.map = self.serializer.list.replace(pair);
+        // Escaping leaves no other `**:` before the end of the key
+        let key_len = entry.find("**:").expect("just written");
+        entries.push((key_len, entry));
+        Ok(())
+    }
+}
+
+impl<'ser, W: Output> ser::SerializeMap for MapSerializer<'ser, W> {
+    type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
+    type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-        key.serialize(&mut *self.serializer)
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        self.key(key)
+            .map_err(|error| error.at(format_args!("[{}]", self.index)))
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<(), Self::Error>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.serializer)?;
-        self.serializer.list = self.map.take();
-        Ok(())
+        let index = self.index;
+        self.value(value)
+            .map_err(|error| error.at(format_args!("[{}]", index)))
     }
 
-    fn end(self) -> Result<Self::Ok, Self::Error> {
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        if self.awaiting_value {
+            return Err(Error::KeyValueMisorder);
+        }
+
+        if let Some(entries) = &mut self.entries {
+            match self.header {
+                Some(Type::Struct(name, _)) => self.serializer.ser_primitive(
+                    format_args!("Struct {} of length {}", name, entries.len()),
+                    Type::Struct(name, entries.len()),
+                )?,
+                Some(Type::StructVariant(name, variant, _)) => self.serializer.ser_primitive(
+                    format_args!("{}::{}", name, variant),
+                    Type::StructVariant(name, variant, entries.len()),
+                )?,
+                _ => {}
+            }
+
+            if self.serializer.config.sort_maps {
+                entries.sort_by(|(a_len, a), (b_len, b)| a[..*a_len].cmp(&b[..*b_len]));
+            }
+            for (_, entry) in entries {
+                self.serializer.writer.raw(entry)?;
+            }
+        }
+
         self.serializer.list = self.parent;
         Ok(())
     }
 }
 
-impl<'ser, W: Write> ser::SerializeStruct for MapSerializer<'ser, W> {
+impl<'ser, W: Output> ser::SerializeStruct for MapSerializer<'ser, W> {
     type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        <Self as ser::SerializeMap>::serialize_entry(self, key, value)
+        self.key(key)
+            .and_then(|()| self.value(value))
+            .map_err(|error| error.at(format_args!(".{}", key)))
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
@@ -412,22 +1514,1308 @@ impl<'ser, W: Write> ser::SerializeStruct for MapSerializer<'ser, W> {
     }
 }
 
-impl<'ser, W: Write> ser::SerializeStructVariant for MapSerializer<'ser, W> {
+impl<'ser, W: Output> ser::SerializeStructVariant for MapSerializer<'ser, W> {
     type Ok = <&'ser mut Serializer<W> as ser::Serializer>::Ok;
     type Error = <&'ser mut Serializer<W> as ser::Serializer>::Error;
 
-    fn serialize_field<T: ?Sized>(
-        &mut self,
-        key: &'static str,
-        value: &T,
-    ) -> Result<(), Self::Error>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        <Self as ser::SerializeMap>::serialize_entry(self, key, value)
+        self.key(key)
+            .and_then(|()| self.value(value))
+            .map_err(|error| error.at(format_args!(".{}", key)))
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
         <Self as ser::SerializeMap>::end(self)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use serde::Serialize;
+
+    use super::*;
+    use crate::md::{self, Item, Reader};
+
+    /// Sets up a serializer with some options, for tests which try several of them
+    pub(crate) type Configure = fn(Serializer<Vec<u8>>) -> Serializer<Vec<u8>>;
+
+    /// Serialize `value` with a serializer set up by `configure`
+    pub(crate) fn render<T, F>(value: &T, configure: F) -> String
+    where
+        T: ?Sized + Serialize,
+        F: FnOnce(Serializer<Vec<u8>>) -> Serializer<Vec<u8>>,
+    {
+        let mut serializer = configure(Serializer::new(Vec::new()));
+        value.serialize(&mut serializer).unwrap();
+        serializer.finish().unwrap();
+        String::from_utf8(serializer.writer.into_inner()).unwrap()
+    }
+
+    fn first_link_text<T: Serialize>(value: &T) -> String {
+        let doc = to_string(value).unwrap();
+        Reader::new(&doc)
+            .map(Result::unwrap)
+            .find_map(|item| match item {
+                Item::Link { text, .. } => Some(text.into_owned()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[derive(Serialize)]
+    enum Color {
+        Unit,
+        Newtype(u8),
+        Tuple(u8, u8),
+        Rgb { r: u8, g: u8, b: u8 },
+    }
+
+    #[test]
+    fn test_variant_link_text() {
+        assert_eq!(first_link_text(&Color::Unit), "Color::Unit");
+        assert_eq!(first_link_text(&Color::Newtype(0)), "Color::Newtype");
+        assert_eq!(first_link_text(&Color::Tuple(0, 0)), "Color::Tuple");
+        assert_eq!(
+            first_link_text(&Color::Rgb { r: 0, g: 0, b: 0 }),
+            "Color::Rgb"
+        );
+    }
+
+    #[test]
+    fn test_summarize_over() {
+        #[derive(Serialize)]
+        struct Log {
+            name: &'static str,
+            data: Vec<u32>,
+            tags: Vec<u8>,
+        }
+
+        let log = Log {
+            name: "big",
+            data: (0..10000).collect(),
+            tags: vec![1, 2],
+        };
+
+        let doc = render(&log, |serializer| serializer.summarize_over(256));
+
+        let links = Reader::new(&doc)
+            .map(Result::unwrap)
+            .filter_map(|item| match item {
+                Item::Link { text, uri } => Some((text.into_owned(), uri)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert!(links.contains(&("big".to_owned(), "serde://string")));
+        assert!(links.contains(&("tags".to_owned(), "serde://string")));
+        assert!(links.contains(&("2".to_owned(), "serde://u8")));
+        assert!(!links.contains(&("0".to_owned(), "serde://u32")));
+
+        let summary = links
+            .iter()
+            .find(|(_, uri)| *uri == "serde://summary")
+            .unwrap();
+        assert_eq!(summary.0, "<seq/10000, more than 256 bytes>");
+
+        // Measuring stops as soon as a value goes over budget
+        struct Counted<'a>(&'a core::cell::Cell<usize>);
+
+        impl Serialize for Counted<'_> {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let count = |_: &u32| self.0.set(self.0.get() + 1);
+                serializer.collect_seq((0..1_000_000u32).inspect(count))
+            }
+        }
+
+        let serialized = core::cell::Cell::new(0);
+        let doc = render(&vec![Counted(&serialized)], |serializer| {
+            serializer.summarize_over(256)
+        });
+        assert!(doc.contains("more than 256 bytes"), "{}", doc);
+        assert!(serialized.get() < 100, "{}", serialized.get());
+
+        let mut full = Vec::new();
+        log.serialize(&mut Serializer::new(&mut full)).unwrap();
+        assert!(doc.len() < 1024 && full.len() > 10000);
+    }
+
+    #[cfg(feature = "content-hash")]
+    #[test]
+    fn test_content_hash() {
+        use std::collections::HashMap;
+
+        let forwards = (0..100u32)
+            .map(|i| (i.to_string(), vec![i; 3]))
+            .collect::<HashMap<_, _>>();
+        let backwards = (0..100u32)
+            .rev()
+            .map(|i| (i.to_string(), vec![i; 3]))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            content_hash(&forwards).unwrap(),
+            content_hash(&backwards).unwrap()
+        );
+
+        let mut different = forwards.clone();
+        different.insert("42".to_owned(), vec![0; 3]);
+        assert_ne!(
+            content_hash(&forwards).unwrap(),
+            content_hash(&different).unwrap()
+        );
+    }
+
+    #[cfg(feature = "content-hash")]
+    #[test]
+    fn test_content_hash_sets_and_floats() {
+        use std::collections::{BTreeSet, HashSet};
+
+        // Seqs keep their order, so sets only hash stably once they're sorted
+        let forwards = (0..100u32).collect::<HashSet<_>>();
+        let backwards = (0..100u32).rev().collect::<HashSet<_>>();
+        assert_eq!(
+            content_hash(&forwards.iter().collect::<BTreeSet<_>>()).unwrap(),
+            content_hash(&backwards.iter().collect::<BTreeSet<_>>()).unwrap()
+        );
+        assert_ne!(
+            content_hash(&[1u32, 2]).unwrap(),
+            content_hash(&[2u32, 1]).unwrap()
+        );
+
+        assert_ne!(
+            content_hash(&0.0f64).unwrap(),
+            content_hash(&-0.0f64).unwrap()
+        );
+        assert_eq!(
+            content_hash(&f64::NAN).unwrap(),
+            content_hash(&-f64::NAN).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sorted_maps_deterministic() {
+        use std::collections::HashMap;
+
+        // Every HashMap gets its own random state, so these are very unlikely to iterate alike
+        let sorted = || {
+            let value = (0..100u32)
+                .map(|i| (i.to_string(), i))
+                .collect::<HashMap<_, _>>();
+            render(&value, |serializer| serializer.with_sorted_maps(true))
+        };
+
+        assert_eq!(sorted(), sorted());
+    }
+
+    #[test]
+    fn test_sorted_maps_roundtrip() {
+        use std::collections::HashMap;
+
+        let value = (0..20u8)
+            .map(|i| (i, (0..i).map(|j| (j.to_string(), j)).collect()))
+            .collect::<HashMap<u8, HashMap<String, u8>>>();
+
+        let mut buf = Vec::new();
+        let mut serializer = Serializer::new(&mut buf).with_sorted_maps(true);
+        value.serialize(&mut serializer).unwrap();
+
+        let doc = String::from_utf8(buf).unwrap();
+        let mut deserializer = crate::de::Deserializer::new(&doc);
+        let parsed: HashMap<u8, HashMap<String, u8>> =
+            serde::Deserialize::deserialize(&mut deserializer).unwrap();
+        assert_eq!(parsed, value);
+    }
+
+    #[test]
+    fn test_map_key_value_misorder() {
+        use serde::ser::{SerializeMap, Serializer as _};
+
+        for sort_maps in [false, true] {
+            let mut serializer =
+                Serializer::new(FmtOutput(String::new())).with_sorted_maps(sort_maps);
+
+            let mut map = (&mut serializer).serialize_map(Some(2)).unwrap();
+            assert!(matches!(
+                map.serialize_value(&1),
+                Err(Error::KeyValueMisorder)
+            ));
+
+            map.serialize_key("a").unwrap();
+            assert!(matches!(
+                map.serialize_key("b"),
+                Err(Error::KeyValueMisorder)
+            ));
+            assert!(matches!(map.end(), Err(Error::KeyValueMisorder)));
+
+            let mut map = (&mut serializer).serialize_map(Some(1)).unwrap();
+            map.serialize_key("a").unwrap();
+            map.serialize_value(&1).unwrap();
+            map.end().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let value = (0..10_000u32).collect::<Vec<_>>();
+        let mut buf = Vec::new();
+        let result = value.serialize(&mut Serializer::new(&mut buf).with_max_bytes(1024));
+        assert_eq!(result, Err(Error::OutputLimitExceeded));
+        assert!(buf.len() <= 1024);
+
+        // Sorted maps are rendered to the side first, but still count once they're written out
+        let value = (0..1_000u32)
+            .map(|i| (i, i))
+            .collect::<std::collections::HashMap<_, _>>();
+        let mut serializer = Serializer::new(FmtOutput(String::new()))
+            .with_max_bytes(1024)
+            .with_sorted_maps(true);
+        assert_eq!(
+            value.serialize(&mut serializer),
+            Err(Error::OutputLimitExceeded)
+        );
+        assert!(serializer.writer.bytes_written() <= 1024);
+
+        let doc = to_string(&value).unwrap();
+        let mut buf = Vec::new();
+        value
+            .serialize(&mut Serializer::new(&mut buf).with_max_bytes(doc.len()))
+            .unwrap();
+
+        // Nor do values rendered to the side get any further than those written as they go
+        struct Endless<'a>(&'a core::cell::Cell<usize>);
+
+        impl fmt::Display for Endless<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                for _ in 0..1_000_000 {
+                    f.write_char('x')?;
+                    self.0.set(self.0.get() + 1);
+                }
+                Ok(())
+            }
+        }
+
+        impl Serialize for Endless<'_> {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        let configs: [Configure; 3] = [
+            |serializer| serializer.with_sorted_maps(true),
+            |serializer| serializer.with_definition_lists(true),
+            |serializer| {
+                serializer
+                    .with_sorted_maps(true)
+                    .with_definition_lists(true)
+            },
+        ];
+        for configure in configs.iter() {
+            let written = core::cell::Cell::new(0);
+            let value = std::collections::BTreeMap::from([("a", Endless(&written))]);
+            let mut serializer = configure(Serializer::new(Vec::new()).with_max_bytes(1024));
+            assert_eq!(
+                value.serialize(&mut serializer),
+                Err(Error::OutputLimitExceeded)
+            );
+            assert!(written.get() <= 1024, "{}", written.get());
+        }
+
+        // Nested structs are written as they go, failing long before the end
+        #[derive(Serialize)]
+        struct Node {
+            value: u32,
+            child: Option<Box<Node>>,
+        }
+
+        let deep = (0..1_000).fold(None, |child, value| Some(Box::new(Node { value, child })));
+        let mut buf = Vec::new();
+        let result = deep.serialize(&mut Serializer::new(&mut buf).with_max_bytes(1024));
+        assert_eq!(result, Err(Error::OutputLimitExceeded));
+        assert!(!buf.is_empty() && buf.len() <= 1024);
+    }
+
+    #[test]
+    fn test_error_path() {
+        use std::collections::BTreeMap;
+
+        struct Fails;
+
+        impl Serialize for Fails {
+            fn serialize<S: ser::Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+                Err(ser::Error::custom("boom"))
+            }
+        }
+
+        #[derive(Serialize)]
+        struct Inner {
+            ok: u8,
+            name: Fails,
+        }
+
+        #[derive(Serialize)]
+        struct Outer {
+            items: Vec<Option<Inner>>,
+            lookup: BTreeMap<&'static str, (u8, Fails)>,
+        }
+
+        let value = Outer {
+            items: vec![None, None, Some(Inner { ok: 1, name: Fails })],
+            lookup: BTreeMap::new(),
+        };
+        let error = to_string(&value).unwrap_err();
+        assert_eq!(
+            error,
+            Error::CustomSerializeError {
+                message: "boom".to_owned(),
+                path: ".items[2].name".to_owned(),
+            }
+        );
+        assert_eq!(error.to_string(), "boom at `.items[2].name`");
+
+        let mut lookup = BTreeMap::new();
+        lookup.insert("a", (0, Fails));
+        let value = Outer {
+            items: vec![],
+            lookup,
+        };
+        for sort_maps in [false, true] {
+            let mut serializer =
+                Serializer::new(FmtOutput(String::new())).with_sorted_maps(sort_maps);
+            let error = value.serialize(&mut serializer).unwrap_err();
+            assert_eq!(error.to_string(), "boom at `.lookup[0][1]`");
+        }
+
+        assert_eq!(to_string(&Fails).unwrap_err().to_string(), "boom");
+    }
+
+    #[test]
+    fn test_to_lines() {
+        #[derive(Serialize)]
+        struct Note {
+            title: &'static str,
+            body: &'static str,
+            tags: Vec<&'static str>,
+        }
+
+        let note = Note {
+            title: "Groceries",
+            body: "eggs\nmilk\n[x] bread",
+            tags: vec!["home", "todo"],
+        };
+
+        let lines = to_lines(&note).unwrap();
+        let doc = to_string(&note).unwrap();
+
+        // One link for the header, one per key, two for the scalar values and one per tag
+        let links = Reader::new(&doc)
+            .map(Result::unwrap)
+            .filter(|item| matches!(item, Item::Link { .. }))
+            .count();
+        assert_eq!(links, 1 + 3 + 2 + 3);
+
+        // Along with an empty item opening each entry and one opening the tags
+        assert_eq!(lines.len(), links + 3 + 1);
+
+        let rejoined = lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        assert_eq!(rejoined, doc);
+    }
+
+    #[test]
+    fn test_scalars_match_escaped_path() {
+        fn escaped<T: fmt::Display>(value: T, ty: Type) -> String {
+            let mut writer = Writer::new(Vec::new());
+            writer.link(None, value, ty).unwrap();
+            String::from_utf8(writer.into_inner()).unwrap()
+        }
+
+        for &n in &[0, 1, -1, i64::MIN, i64::MAX] {
+            assert_eq!(to_string(&n).unwrap(), escaped(n, Type::I64));
+        }
+        for &n in &[0, u128::MAX] {
+            assert_eq!(to_string(&n).unwrap(), escaped(n, Type::U128));
+        }
+        for &x in &[
+            0.0,
+            -0.0,
+            1.5,
+            -1e300,
+            f64::NAN,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+        ] {
+            assert_eq!(to_string(&x).unwrap(), escaped(x, Type::F64));
+        }
+        for &b in &[true, false] {
+            assert_eq!(to_string(&b).unwrap(), escaped(b, Type::Bool));
+        }
+    }
+
+    #[test]
+    fn test_skipped_fields() {
+        use serde::ser::SerializeStruct;
+
+        let exact: Configure = |serializer| serializer.with_exact_struct_lengths(true);
+
+        fn header(doc: &str) -> String {
+            Reader::new(doc)
+                .map(Result::unwrap)
+                .find_map(|item| match item {
+                    Item::Link { uri, .. } => Some(uri.to_owned()),
+                    _ => None,
+                })
+                .unwrap()
+        }
+
+        #[derive(Serialize)]
+        struct Profile {
+            name: &'static str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            nickname: Option<&'static str>,
+            age: u8,
+        }
+
+        let profile = Profile {
+            name: "Ada",
+            nickname: None,
+            age: 36,
+        };
+        assert_eq!(header(&render(&profile, exact)), "serde://struct/Profile/2");
+
+        // serde's derive leaves skipped fields out of the declared number already
+        assert_eq!(
+            header(&to_string(&profile).unwrap()),
+            "serde://struct/Profile/2"
+        );
+
+        let profile = Profile {
+            nickname: Some("Countess"),
+            ..profile
+        };
+        assert_eq!(header(&render(&profile, exact)), "serde://struct/Profile/3");
+
+        /// Declares more fields than it serializes, which nothing in serde forbids
+        struct Liar;
+
+        impl Serialize for Liar {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut state = serializer.serialize_struct("Liar", 3)?;
+                state.serialize_field("only", &1)?;
+                state.end()
+            }
+        }
+
+        assert_eq!(header(&to_string(&Liar).unwrap()), "serde://struct/Liar/3");
+        let doc = render(&Liar, exact);
+        assert_eq!(header(&doc), "serde://struct/Liar/1");
+        assert_eq!(
+            doc,
+            to_string(&std::collections::BTreeMap::from([("only", 1)]))
+                .unwrap()
+                .replace(
+                    "Map of length 1](serde://map/1)",
+                    "Struct Liar of length 1](serde://struct/Liar/1)"
+                )
+        );
+    }
+
+    #[test]
+    fn test_reference_uris() {
+        use serde::Deserialize;
+
+        #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+        struct VeryLongStructName {
+            first_field: u32,
+            second_field: Option<String>,
+            third_field: Vec<bool>,
+        }
+
+        let value = vec![
+            VeryLongStructName {
+                first_field: 7,
+                second_field: Some("text".to_owned()),
+                third_field: vec![true, false],
+            };
+            1000
+        ];
+
+        let expanded = to_string(&value).unwrap();
+        let mut serializer = Serializer::new(FmtOutput(String::new())).with_reference_uris(true);
+        value.serialize(&mut serializer).unwrap();
+        serializer.finish().unwrap();
+        let doc = serializer.writer.into_inner().0;
+
+        assert!(
+            doc.len() < expanded.len() * 3 / 4,
+            "{} vs {}",
+            doc.len(),
+            expanded.len()
+        );
+        assert!(doc.contains("\n\n[1]: serde://seq/1000\n"));
+        assert!(doc.contains("]: serde://struct/VeryLongStructName/3\n"));
+        assert_eq!(doc.matches("]: serde://").count(), 7);
+        assert!(crate::md::semantically_eq(&doc, &expanded).unwrap());
+        assert_eq!(
+            crate::de::from_str::<Vec<VeryLongStructName>>(&doc).unwrap(),
+            value
+        );
+
+        // Scratch serializers share the same numbers
+        let mut map = std::collections::HashMap::new();
+        map.insert("b", value[..2].to_vec());
+        map.insert("a", Vec::new());
+        let mut serializer = Serializer::new(FmtOutput(String::new()))
+            .with_reference_uris(true)
+            .with_sorted_maps(true)
+            .summarize_over(10_000);
+        map.serialize(&mut serializer).unwrap();
+        serializer.finish().unwrap();
+        let doc = serializer.writer.into_inner().0;
+        assert_eq!(doc.matches("]: serde://string\n").count(), 1);
+        assert_eq!(
+            crate::de::from_str::<std::collections::HashMap<&str, Vec<VeryLongStructName>>>(&doc)
+                .unwrap(),
+            map.into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_slashless_unknown_lengths() {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        use serde::Deserialize;
+
+        /// A seq and a map which don't know their lengths up front
+        struct Unknown;
+
+        impl Serialize for Unknown {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut seq = serializer.serialize_seq(None)?;
+                seq.serialize_element(&1u8)?;
+                seq.serialize_element(&Map)?;
+                seq.end()
+            }
+        }
+
+        struct Map;
+
+        impl Serialize for Map {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry(&2u8, &3u8)?;
+                map.end()
+            }
+        }
+
+        let slashed = to_string(&Unknown).unwrap();
+        let mut serializer =
+            Serializer::new(FmtOutput(String::new())).with_slashless_unknown_lengths(true);
+        Unknown.serialize(&mut serializer).unwrap();
+        let slashless = serializer.writer.into_inner().0;
+
+        assert!(slashed.contains("](serde://seq/)\n") && slashed.contains("](serde://map/)\n"));
+        assert!(slashless.contains("](serde://seq)\n") && slashless.contains("](serde://map)\n"));
+        assert_eq!(
+            slashless
+                .replace("://seq)", "://seq/)")
+                .replace("://map)", "://map/)"),
+            slashed
+        );
+
+        for doc in [&slashed, &slashless] {
+            let mut de = crate::de::Deserializer::new(doc);
+            assert_eq!(de.peek_type().unwrap(), Some(Type::Seq(None)));
+
+            #[derive(Debug, PartialEq, Deserialize)]
+            #[serde(untagged)]
+            enum Element {
+                Int(u8),
+                Map(std::collections::BTreeMap<u8, u8>),
+            }
+
+            let parsed = Vec::<Element>::deserialize(&mut de).unwrap();
+            assert_eq!(
+                parsed,
+                vec![
+                    Element::Int(1),
+                    Element::Map(std::collections::BTreeMap::from([(2, 3)]))
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_fmt_writer() {
+        let mut buf = String::from("# Numbers\n\n");
+        to_fmt_writer(&mut buf, &vec![1u8, 2]).unwrap();
+        assert_eq!(
+            buf,
+            format!("# Numbers\n\n{}", to_string(&vec![1u8, 2]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", md::display(&42u8)), "[42](serde://u8)\n");
+        assert_eq!(
+            format!("values: {}", md::Display(&vec![1u8, 2])),
+            format!("values: {}", to_string(&vec![1u8, 2]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_serialize_raw() {
+        use ser::{SerializeSeq, Serializer as _};
+
+        #[derive(Debug, Clone, PartialEq, Serialize, serde::Deserialize)]
+        struct Cached {
+            text: String,
+            lines: Vec<String>,
+        }
+
+        fn splice<T: Serialize>(first: &T, fragment: &str) -> String {
+            let mut serializer = Serializer::new(FmtOutput(String::new()));
+            let mut seq = (&mut serializer).serialize_seq(Some(2)).unwrap();
+            seq.serialize_element(first).unwrap();
+            seq.serialize_raw_element(fragment).unwrap();
+            seq.end().unwrap();
+            serializer.writer.into_inner().0
+        }
+
+        // Link text spanning lines which look like items must be left alone
+        let cached = Cached {
+            text: "spans\n1. two [lines]".to_owned(),
+            lines: vec!["a".to_owned(), "b\n    * c".to_owned()],
+        };
+
+        let doc = splice(&cached, &to_string(&cached).unwrap());
+        assert_eq!(
+            crate::de::from_str::<Vec<Cached>>(&doc).unwrap(),
+            [cached.clone(), cached.clone()]
+        );
+        assert_eq!(doc, to_string(&vec![&cached, &cached]).unwrap());
+
+        let doc = splice(&cached.text, &to_string(&cached.text).unwrap());
+        assert_eq!(
+            crate::de::from_str::<Vec<String>>(&doc).unwrap(),
+            [cached.text.clone(), cached.text.clone()]
+        );
+
+        let nested = vec![cached.clone()];
+        let doc = splice(&nested, &to_string(&nested).unwrap());
+        assert_eq!(
+            crate::de::from_str::<Vec<Vec<Cached>>>(&doc).unwrap(),
+            [nested.clone(), nested]
+        );
+
+        let mut serializer = Serializer::new(FmtOutput(String::new()));
+        serializer
+            .serialize_raw(&to_string(&cached).unwrap())
+            .unwrap();
+        assert_eq!(
+            serializer.writer.into_inner().0,
+            to_string(&cached).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Borrowed<'a> {
+            #[serde(with = "serde_bytes")]
+            plain: &'a [u8],
+            #[serde(with = "serde_bytes")]
+            binary: Vec<u8>,
+            #[serde(with = "serde_bytes")]
+            escaped: Vec<u8>,
+        }
+
+        let value = Borrowed {
+            plain: b"hello world",
+            binary: vec![0xff, 0x00],
+            escaped: b"a [b]".to_vec(),
+        };
+
+        let mut serializer = Serializer::new(FmtOutput(String::new())).with_raw_bytes(true);
+        value.serialize(&mut serializer).unwrap();
+        let doc = serializer.writer.into_inner().0;
+        assert!(doc.contains("[hello world](serde://raw_bytes)"), "{}", doc);
+        assert!(doc.contains("[_wA=](serde://bytes)"), "{}", doc);
+        assert!(doc.contains("[a \\[b\\]](serde://raw_bytes)"), "{}", doc);
+
+        let de = crate::de::from_str::<Borrowed>(&doc).unwrap();
+        assert_eq!(de, value);
+
+        // Text with escapes has to be unescaped into a buffer of its own
+        let err =
+            crate::de::from_str::<Borrowed>(&doc.replace("hello world", "hello\\!")).unwrap_err();
+        assert!(err.to_string().contains("borrowed"), "{}", err);
+    }
+
+    #[test]
+    fn test_float_formatting() {
+        /// A number which shows itself the way some locales would
+        struct Localized(f64);
+
+        impl Serialize for Localized {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(&self.0.to_string().replace('.', ","))
+            }
+        }
+
+        let floats = [
+            1.5,
+            -0.25,
+            1e300,
+            1e-300,
+            f64::MIN_POSITIVE,
+            f64::MIN_POSITIVE / 2.0,
+            f64::MAX,
+            1234567.125,
+        ];
+        for &x in &floats {
+            let doc = to_string(&x).unwrap();
+            assert!(!doc.contains(','), "{}", doc);
+            assert_eq!(
+                crate::de::from_str::<f64>(&doc).unwrap().to_bits(),
+                x.to_bits()
+            );
+
+            let doc = to_string(&(x as f32)).unwrap();
+            assert!(!doc.contains(','), "{}", doc);
+            assert_eq!(crate::de::from_str::<f32>(&doc).unwrap(), x as f32);
+        }
+        assert_eq!(first_link_text(&1234567.125), "1234567.125");
+
+        // Whatever other `Display` impls do ends up in strings, never in float links
+        let doc = to_string(&Localized(1.5)).unwrap();
+        assert_eq!(doc, "[1\\,5](serde://string)\n");
+        assert!(crate::de::from_str::<f64>(&doc).is_err());
+    }
+
+    #[test]
+    fn test_scalar_text() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Report {
+            revenue: u64,
+            loss: i32,
+            ratio: f64,
+            audited: bool,
+            label: String,
+        }
+
+        fn thousands(ty: &Type<'_>, text: &str) -> Option<String> {
+            if !matches!(ty, Type::U64 | Type::I32) || text.trim_start_matches('-').len() <= 3 {
+                return None;
+            }
+
+            let (sign, digits) = text.split_at(text.starts_with('-') as usize);
+            let mut grouped = String::from(sign);
+            for (i, digit) in digits.chars().enumerate() {
+                if i > 0 && (digits.len() - i) % 3 == 0 {
+                    grouped.push(',');
+                }
+                grouped.push(digit);
+            }
+            Some(grouped)
+        }
+
+        let report = Report {
+            revenue: 1_000_000,
+            loss: -25_000,
+            ratio: 0.5,
+            audited: true,
+            label: "1000".to_owned(),
+        };
+        let mut serializer = Serializer::new(FmtOutput(String::new())).with_scalar_text(thousands);
+        report.serialize(&mut serializer).unwrap();
+        let doc = serializer.writer.into_inner().0;
+
+        assert!(
+            doc.contains("[1\\,000\\,000](serde://u64#1000000)\n"),
+            "{}",
+            doc
+        );
+        assert!(
+            doc.contains("[\\-25\\,000](serde://i32#-25000)\n"),
+            "{}",
+            doc
+        );
+        assert!(doc.contains("[0\\.5](serde://f64)\n"), "{}", doc);
+        assert!(doc.contains("[1000](serde://string)\n"), "{}", doc);
+        assert_eq!(crate::de::from_str::<Report>(&doc).unwrap(), report);
+        assert_eq!(crate::md::validate(&doc), Ok(()));
+
+        // Strings holding numbers are still written as they are
+        #[cfg(feature = "bignum")]
+        {
+            let mut serializer = Serializer::new(FmtOutput(String::new()))
+                .with_scalar_text(thousands)
+                .with_bignum_strings(true);
+            report.serialize(&mut serializer).unwrap();
+            let doc = serializer.writer.into_inner().0;
+            assert!(doc.contains("[1000](serde://bignum)\n"), "{}", doc);
+            assert_eq!(crate::de::from_str::<Report>(&doc).unwrap(), report);
+        }
+
+        // Only booleans and numbers have their value after the URI
+        let doc = "[x](serde://string#y)\n";
+        assert_eq!(
+            crate::de::from_str::<String>(doc).unwrap_err().unlocated(),
+            &Error::TypeParseError(ty::ParseError::UnknownType)
+        );
+    }
+
+    #[test]
+    fn test_inline_threshold() {
+        use serde::Deserialize;
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Pixel {
+            color: [u8; 3],
+            tags: Vec<String>,
+        }
+
+        let color = [255u8, 128, 0];
+        let doc = render(&color, |serializer| serializer.with_inline_threshold(3));
+        assert_eq!(
+            doc,
+            "[Tuple of length 3](serde://tuple/3) [255](serde://u8) [128](serde://u8) [0](serde://u8)\n"
+        );
+        assert_eq!(crate::de::from_str::<[u8; 3]>(&doc).unwrap(), color);
+
+        // Too many elements for the threshold, so the usual sublist
+        let doc = render(&color, |serializer| serializer.with_inline_threshold(2));
+        assert_eq!(doc, to_string(&color).unwrap());
+        assert_eq!(crate::de::from_str::<[u8; 3]>(&doc).unwrap(), color);
+
+        // Tricky texts are fine, but anything other than a link isn't
+        let pixel = Pixel {
+            color,
+            tags: vec!["a] [b".to_owned(), "two\nlines".to_owned()],
+        };
+        let doc = render(&pixel, |serializer| serializer.with_inline_threshold(3));
+        assert!(doc.contains("    1. [Tuple of length 3](serde://tuple/3) [255](serde://u8)"));
+        assert!(doc.contains("[Seq of length 2](serde://seq/2) [a\\] \\[b](serde://string)"));
+        assert_eq!(crate::de::from_str::<Pixel>(&doc).unwrap(), pixel);
+
+        let pixels = vec![
+            pixel,
+            Pixel {
+                color,
+                tags: vec![],
+            },
+        ];
+        let doc = render(&pixels, |serializer| serializer.with_inline_threshold(3));
+        assert!(doc.starts_with("0. [Seq of length 2](serde://seq/2)\n1. \n    * "));
+        assert!(doc.contains("[Seq of length 0](serde://seq/0)\n"));
+        assert_eq!(
+            doc.matches("[Tuple of length 3](serde://tuple/3) ").count(),
+            2
+        );
+        assert_eq!(crate::de::from_str::<Vec<Pixel>>(&doc).unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_float_points() {
+        let pointed: Configure = |serializer| serializer.with_float_points(true);
+
+        assert_eq!(first_link_text(&1.0f64), "1");
+        assert_eq!(render(&1.0f64, pointed), "[1\\.0](serde://f64)\n");
+        assert_eq!(render(&-0.0f32, pointed), "[\\-0\\.0](serde://f32)\n");
+        assert_eq!(render(&1.5f64, pointed), to_string(&1.5f64).unwrap());
+        assert_eq!(
+            render(&f64::INFINITY, pointed),
+            to_string(&f64::INFINITY).unwrap()
+        );
+
+        for &x in &[1.0, -3.0, 1e20, f64::MAX, 0.0] {
+            let doc = render(&x, pointed);
+            assert!(doc.contains("\\.0]"), "{}", doc);
+            assert_eq!(
+                crate::de::from_str::<f64>(&doc).unwrap().to_bits(),
+                x.to_bits()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unit_texts() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Marker;
+
+        assert_eq!(first_link_text(&()), "()");
+        assert_eq!(first_link_text(&None::<u8>), "None");
+        assert_eq!(first_link_text(&Marker), "Marker");
+
+        let custom: Configure = |serializer| {
+            serializer
+                .with_unit_text("∅")
+                .with_none_text("")
+                .with_unit_struct_text("-")
+        };
+
+        assert_eq!(render(&(), custom), "[∅](serde://unit)\n");
+        assert_eq!(render(&None::<u8>, custom), "[](serde://none)\n");
+        assert_eq!(
+            render(&Marker, custom),
+            "[\\-](serde://unit_struct/Marker)\n"
+        );
+
+        // Only the URIs are looked at when deserializing
+        let value = (vec![Some(()), None], Marker);
+        let doc = render(&value, custom);
+        let parsed: (Vec<Option<()>>, Marker) = crate::de::from_str(&doc).unwrap();
+        assert_eq!(parsed, value);
+        let any: serde_value::Value = crate::de::from_str(&doc).unwrap();
+        assert_eq!(any, serde_value::to_value(&value).unwrap());
+    }
+
+    #[test]
+    fn test_deep_nesting_renders_once() {
+        use core::cell::Cell;
+
+        /// Nested maps and seqs, `depth` deep, counting how often they're serialized
+        ///
+        /// Rendering each one to the side and then again in place would take 2^depth calls.
+        struct Nested<'a> {
+            depth: usize,
+            calls: &'a Cell<usize>,
+        }
+
+        impl Serialize for Nested<'_> {
+            fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use ser::{SerializeMap, SerializeSeq};
+
+                self.calls.set(self.calls.get() + 1);
+                let inner = Nested {
+                    depth: self.depth.wrapping_sub(1),
+                    calls: self.calls,
+                };
+                match self.depth {
+                    0 => serializer.serialize_u8(0),
+                    depth if depth % 2 == 1 => {
+                        let mut map = serializer.serialize_map(Some(1))?;
+                        map.serialize_entry("inner", &inner)?;
+                        map.end()
+                    }
+                    _ => {
+                        let mut seq = serializer.serialize_seq(Some(1))?;
+                        seq.serialize_element(&inner)?;
+                        seq.end()
+                    }
+                }
+            }
+        }
+
+        let configs: [Configure; 3] = [
+            |serializer| serializer.with_definition_lists(true),
+            |serializer| serializer.with_inline_threshold(4),
+            |serializer| {
+                serializer
+                    .with_definition_lists(true)
+                    .with_inline_threshold(4)
+                    .with_sorted_maps(true)
+            },
+        ];
+        for configure in configs.iter() {
+            let calls = Cell::new(0);
+            let doc = render(
+                &Nested {
+                    depth: 24,
+                    calls: &calls,
+                },
+                configure,
+            );
+            // Once for real, and at most once more by a `LinkProbe`
+            assert!(calls.get() <= 2 * 25, "{} calls: {}", calls.get(), doc);
+            assert!(doc.contains("[0](serde://u8)"), "{}", doc);
+        }
+    }
+
+    #[test]
+    fn test_definition_lists() {
+        use std::collections::{BTreeMap, HashMap};
+
+        fn defined<T: Serialize>(value: &T, configure: Configure) -> String {
+            let mut serializer = configure(Serializer::new(Vec::new())).with_definition_lists(true);
+            value.serialize(&mut serializer).unwrap();
+            serializer.finish().unwrap();
+            let stats = serializer.stats();
+            let doc = String::from_utf8(serializer.writer.into_inner()).unwrap();
+
+            let items = Reader::tokenize(&doc).unwrap();
+            let links = items
+                .iter()
+                .filter(|item| matches!(item, Item::Link { .. }))
+                .count();
+            assert_eq!(stats.links, links, "{}", doc);
+            doc
+        }
+
+        let map = vec![("a".to_owned(), 1u32), ("b**: [c]".to_owned(), 2)]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            defined(&map, |serializer| serializer),
+            concat!(
+                "* [Map of length 2](serde://map/2)\n",
+                "* **a**: [1](serde://u32)\n",
+                "* **b\\*\\*\\: \\[c\\]**: [2](serde://u32)\n",
+            )
+        );
+
+        // The reader couldn't tell where a key with a newline starts
+        let map = vec![("a\nb".to_owned(), 1u8)]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let doc = defined(&map, |serializer| serializer);
+        assert!(doc.contains("    0. [a\nb](serde://string)\n"), "{}", doc);
+        assert_eq!(
+            crate::de::from_str::<BTreeMap<String, u8>>(&doc).unwrap(),
+            map
+        );
+
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Entry {
+            name: String,
+            tags: Vec<String>,
+            flag: bool,
+            counts: HashMap<String, u32>,
+            ids: BTreeMap<u8, String>,
+        }
+
+        let entry = Entry {
+            name: "multi\nline".to_owned(),
+            tags: vec!["x".to_owned()],
+            flag: true,
+            counts: (0..20).map(|i| (format!("key {}", i), i)).collect(),
+            ids: vec![(1, "one".to_owned())].into_iter().collect(),
+        };
+        let configs: [Configure; 6] = [
+            |serializer| serializer,
+            |serializer| serializer.with_sorted_maps(true),
+            |serializer| serializer.with_reference_uris(true),
+            |serializer| serializer.with_task_lists(true).with_spaced_lists(true),
+            |serializer| serializer.summarize_over(64),
+            |serializer| serializer.with_inline_threshold(4),
+        ];
+        for configure in configs.iter() {
+            let doc = defined(&entry.counts, *configure);
+            assert_eq!(doc.matches("* **key ").count(), 20, "{}", doc);
+            let counts: HashMap<String, u32> = crate::de::from_str(&doc).unwrap();
+            assert_eq!(counts, entry.counts);
+
+            // Containers and keys which aren't strings are left nested
+            let doc = defined(&entry, *configure);
+            assert!(doc.contains("* **name**: [multi\n"), "{}", doc);
+            assert!(doc.contains("* **flag**: "), "{}", doc);
+            if !doc.contains("serde://summary") {
+                assert!(!doc.contains("**tags**"), "{}", doc);
+                assert!(!doc.contains("**counts**"), "{}", doc);
+                assert!(!doc.contains("**1**") && doc.contains("[one]"), "{}", doc);
+                assert_eq!(crate::de::from_str::<Entry>(&doc).unwrap(), entry);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spaced_lists() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Inner {
+            a: u8,
+            b: Vec<u8>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Outer {
+            inner: Inner,
+            list: Vec<Vec<u8>>,
+            flag: bool,
+        }
+
+        let value = Outer {
+            inner: Inner { a: 1, b: vec![2] },
+            list: vec![vec![3]],
+            flag: true,
+        };
+
+        assert_eq!(
+            render(&value, |serializer| serializer.with_spaced_lists(true)),
+            concat!(
+                "* [Struct Outer of length 3](serde://struct/Outer/3)\n",
+                "\n",
+                "* \n",
+                "    0. [inner](serde://string)\n",
+                "    1. \n",
+                "        * [Struct Inner of length 2](serde://struct/Inner/2)\n",
+                "        * \n",
+                "            0. [a](serde://string)\n",
+                "            1. [1](serde://u8)\n",
+                "        * \n",
+                "            0. [b](serde://string)\n",
+                "            1. \n",
+                "                0. [Seq of length 1](serde://seq/1)\n",
+                "                1. [2](serde://u8)\n",
+                "\n",
+                "* \n",
+                "    0. [list](serde://string)\n",
+                "    1. \n",
+                "        0. [Seq of length 1](serde://seq/1)\n",
+                "        1. \n",
+                "            0. [Seq of length 1](serde://seq/1)\n",
+                "            1. [3](serde://u8)\n",
+                "\n",
+                "* \n",
+                "    0. [flag](serde://string)\n",
+                "    1. [true](serde://bool)\n",
+            )
+        );
+
+        // Including when parts of it are rendered to the side first
+        let configs: [Configure; 4] = [
+            |serializer| serializer,
+            |serializer| serializer.with_sorted_maps(true),
+            |serializer| serializer.summarize_over(1024),
+            |serializer| serializer.with_inline_threshold(2),
+        ];
+        for &configure in configs.iter() {
+            let doc = render(&value, |serializer| {
+                configure(serializer).with_spaced_lists(true)
+            });
+            assert_eq!(doc.matches("\n\n* ").count(), 3, "{}", doc);
+            assert_eq!(crate::de::from_str::<Outer>(&doc).unwrap(), value);
+
+            let dense = render(&value, configure);
+            assert!(crate::md::semantically_eq(&doc, &dense).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reuse() {
+        let first = vec![vec![1u8], vec![2, 3]];
+        let second = vec![(4u8, "a".to_owned())]
+            .into_iter()
+            .collect::<std::collections::BTreeMap<_, _>>();
+
+        // Writing one document after the other into the same output
+        let mut serializer = Serializer::new(Vec::new());
+        first.serialize(&mut serializer).unwrap();
+        second.serialize(&mut serializer).unwrap();
+        let doc = String::from_utf8(serializer.writer.into_inner()).unwrap();
+        assert_eq!(
+            doc,
+            to_string(&first).unwrap() + &to_string(&second).unwrap()
+        );
+
+        let mut values = crate::de::StreamDeserializer::<serde_value::Value>::new(&doc);
+        assert_eq!(
+            values.next().unwrap().unwrap(),
+            serde_value::to_value(&first).unwrap()
+        );
+        assert_eq!(
+            values.next().unwrap().unwrap(),
+            serde_value::to_value(&second).unwrap()
+        );
+        assert!(values.next().is_none());
+
+        // Or into a new one each time, even after a failure halfway through a value
+        let mut serializer = Serializer::new(Vec::new())
+            .with_max_bytes(256)
+            .with_reference_uris(true);
+        assert_eq!(
+            (0..100u8).collect::<Vec<_>>().serialize(&mut serializer),
+            Err(Error::OutputLimitExceeded)
+        );
+        for value in [&first, &vec![vec![5u8; 3]]] {
+            serializer.reset(Vec::new());
+            value.serialize(&mut serializer).unwrap();
+            serializer.finish().unwrap();
+            let stats = serializer.stats();
+            let doc = String::from_utf8(serializer.reset(Vec::new())).unwrap();
+            assert_eq!(stats.bytes, doc.len());
+            assert!(doc.starts_with("0. [Seq of length "), "{}", doc);
+            assert!(doc.contains("]: serde://seq/"), "{}", doc);
+            assert_eq!(crate::de::from_str::<Vec<Vec<u8>>>(&doc).unwrap(), *value);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        #[derive(Serialize)]
+        struct Point {
+            x: u8,
+            y: u8,
+        }
+
+        let points = vec![Point { x: 1, y: 2 }, Point { x: 3, y: 4 }];
+        let mut serializer = Serializer::new(Vec::new());
+        points.serialize(&mut serializer).unwrap();
+        let stats = serializer.stats();
+        let doc = serializer.writer.into_inner();
+        assert_eq!(
+            stats,
+            SerializeStats {
+                bytes: doc.len(),
+                links: 11,
+                lists: 7,
+            }
+        );
+
+        #[derive(Serialize)]
+        struct Row {
+            bytes: Vec<u8>,
+            name: Option<&'static str>,
+            flags: std::collections::HashMap<u8, bool>,
+        }
+
+        // Whatever goes through scratch buffers counts once it's written, and only then
+        let value = vec![
+            Row {
+                bytes: vec![1, 2],
+                name: Some("a"),
+                flags: vec![(1, true)].into_iter().collect(),
+            },
+            Row {
+                bytes: vec![3; 40],
+                name: None,
+                flags: Default::default(),
+            },
+        ];
+        let configs: [Configure; 4] = [
+            |serializer| serializer,
+            |serializer| serializer.with_sorted_maps(true),
+            |serializer| serializer.summarize_over(80),
+            |serializer| serializer.with_inline_threshold(2).with_task_lists(true),
+        ];
+        for config in configs.iter() {
+            let mut serializer = config(Serializer::new(Vec::new()));
+            value.serialize(&mut serializer).unwrap();
+            let stats = serializer.stats();
+            let doc = String::from_utf8(serializer.writer.into_inner()).unwrap();
+
+            let items = Reader::tokenize(&doc).unwrap();
+            let links = items
+                .iter()
+                .filter(|item| matches!(item, Item::Link { .. }))
+                .count();
+            let lists = items
+                .iter()
+                .filter(|item| matches!(item, Item::PushOrderedList | Item::PushUnorderedList))
+                .count();
+            assert_eq!(
+                stats,
+                SerializeStats {
+                    bytes: doc.len(),
+                    links,
+                    lists
+                },
+                "{}",
+                doc
+            );
+        }
+    }
+}
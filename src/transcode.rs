@@ -0,0 +1,126 @@
+//! Conversion between MML and any other serde format, without an intermediate value
+//!
+//! Numbers keep their exact type and value both ways, as MML writes them out in full along with
+//! their type: e.g. a JSON integer too big for an `f64` to hold exactly comes back out the same.
+
+use serde::de::IgnoredAny;
+use serde::ser::Error as _;
+
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::md::Output;
+use crate::ser::Serializer;
+
+/// Write whatever `de` deserializes to `output` as MML
+///
+/// Errors from `de` come out as `Error::CustomSerializeError`s, with their message.
+pub fn transcode_to_mml<'de, D, W>(de: D, output: W) -> Result<()>
+where
+    D: serde::Deserializer<'de>,
+    W: Output,
+{
+    let mut serializer = Serializer::new(output);
+    serde_transcode::transcode(de, &mut serializer)?;
+    serializer.finish()
+}
+
+/// Serialize the MML document `input` with `ser`
+///
+/// Errors from reading `input` are returned as they are, located as with `from_str`, so that
+/// e.g. an `Error::UnterminatedLink` can be matched on. Errors from `ser` come out as
+/// `Error::CustomSerializeError`s, with their message.
+pub fn transcode_from_mml<S>(input: &str, ser: S) -> Result<()>
+where
+    S: serde::Serializer,
+{
+    let mut deserializer = Deserializer::new(input);
+    match serde_transcode::transcode(&mut deserializer, ser) {
+        Ok(_) => Ok(()),
+
+        // Errors from the input only get out as `ser`'s, with just their message, so read it
+        // again by itself to tell whether the error was its own
+        Err(error) => match crate::de::from_str::<IgnoredAny>(input) {
+            Ok(IgnoredAny) => Err(Error::custom(error)),
+            Err(error) => Err(error),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    #[test]
+    fn test_json_roundtrip() {
+        let value = json!({
+            "name": "serde-mml",
+            "tags": ["markdown", "serde", ""],
+            "stars": 3,
+            "ratio": -0.25,
+            "archived": false,
+            "parent": null,
+            "nested": {"empty": {}, "list": [[], [1, [2]]]},
+        });
+
+        let mut mml = Vec::new();
+        transcode_to_mml(&value, &mut mml).unwrap();
+        let mml = String::from_utf8(mml).unwrap();
+        assert!(mml.contains("[serde\\-mml](serde://string)"));
+
+        let mut json = Vec::new();
+        transcode_from_mml(&mml, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_big_integers() {
+        let json = r#"{"big":9007199254740993,"max":18446744073709551615,"min":-9223372036854775808,"float":9007199254740992.0}"#;
+
+        let mut mml = Vec::new();
+        transcode_to_mml(&mut serde_json::Deserializer::from_str(json), &mut mml).unwrap();
+        let mml = String::from_utf8(mml).unwrap();
+        assert!(mml.contains("[9007199254740993](serde://u64)"));
+        assert!(mml.contains("[\\-9223372036854775808](serde://i64)"));
+
+        let mut back = Vec::new();
+        transcode_from_mml(&mml, &mut serde_json::Serializer::new(&mut back)).unwrap();
+        assert_eq!(String::from_utf8(back).unwrap(), json);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut de = serde_json::Deserializer::from_str("[1, 2");
+        let err = transcode_to_mml(&mut de, Vec::new()).unwrap_err();
+        assert!(matches!(err, Error::CustomSerializeError { .. }));
+        assert!(err.to_string().contains("EOF"), "{}", err);
+
+        let err = transcode_from_mml("* [x\n", &mut serde_json::Serializer::new(Vec::new()));
+        assert_eq!(err.unwrap_err().unlocated(), &Error::UnterminatedLink);
+
+        // Even from deep inside the document
+        let mml = crate::ser::to_string(&vec![vec![1u8], vec![2]])
+            .unwrap()
+            .replace("[2](serde://u8)", "[2](serde://u9)");
+        let err =
+            transcode_from_mml(&mml, &mut serde_json::Serializer::new(Vec::new())).unwrap_err();
+        assert_eq!(
+            err.unlocated(),
+            &Error::TypeParseError(crate::ty::ParseError::UnknownType)
+        );
+
+        // JSON can't have non-string keys
+        let mml = crate::ser::to_string(
+            &[((1, 2), 3)]
+                .iter()
+                .cloned()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+        )
+        .unwrap();
+        let err =
+            transcode_from_mml(&mml, &mut serde_json::Serializer::new(Vec::new())).unwrap_err();
+        assert!(matches!(err, Error::CustomSerializeError { .. }));
+        assert!(err.to_string().contains("key must be"), "{}", err);
+    }
+}
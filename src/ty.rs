@@ -1,12 +1,15 @@
-use std::fmt;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::string::String;
+use core::fmt;
 
-#[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
 pub enum ParseError {
     #[error("Unknown type URI")]
     UnknownType,
 
-    #[error("Unknown schema, expected \"serde://\"")]
-    UnknownSchema,
+    #[error("Unknown schema, expected \"{0}://\"")]
+    UnknownSchema(&'static str),
 
     #[error("Missing the domain")]
     MissingDomain,
@@ -14,11 +17,20 @@ pub enum ParseError {
     #[error("Missing a path fragment")]
     MissingPathFragment,
 
+    #[error("Declared length `{0}` is too large")]
+    LengthTooLarge(String),
+
     #[error("Int parse error: {0}")]
-    IntParseError(#[from] std::num::ParseIntError),
+    IntParseError(#[from] core::num::ParseIntError),
 }
 
+/// The type of a value, as written in the URIs of links
+///
+/// Names of types and variants are percent-encoded in URIs wherever they contain a `/`, a `%`, an
+/// angle bracket or a control character, and parsed names are left encoded; `decode_name` gives
+/// back the name itself.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Type<'a> {
     Bool,
     I8,
@@ -35,7 +47,14 @@ pub enum Type<'a> {
     F64,
     Char,
     String,
+
+    /// Only written with the `bignum` feature, like `Decimal`, but both are always parsed so
+    /// that documents written with it can still be read as plain strings without it
+    BigNum,
+    Decimal,
     Bytes,
+    HexBytes,
+    RawBytes,
     None,
     Some,
     Unit,
@@ -50,74 +69,241 @@ pub enum Type<'a> {
     Map(Option<usize>),
     Struct(&'a str, usize),
     StructVariant(&'a str, &'a str, usize),
+    Summary,
+    ByteChunks(usize),
+}
+
+/// The scheme used by type URIs unless another one is configured
+pub const DEFAULT_SCHEME: &str = "serde";
+
+/// Whether a name has to be percent-encoded to fit in a single path fragment of a type URI
+fn needs_encoding(ch: char) -> bool {
+    matches!(ch, '/' | '%' | '<' | '>') || ch.is_ascii_control()
+}
+
+/// A type or variant name displayed percent-encoded
+struct Name<'a>(&'a str);
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Only ASCII needs encoding, so we can safely slice around single bytes
+        let mut start = 0;
+        for (i, ch) in self.0.char_indices().filter(|&(_, ch)| needs_encoding(ch)) {
+            f.write_str(&self.0[start..i])?;
+            write!(f, "%{:02X}", u32::from(ch))?;
+            start = i + 1;
+        }
+        f.write_str(&self.0[start..])
+    }
+}
+
+/// Undo the percent-encoding of a name parsed from a type URI
+///
+/// Only what `Type` encodes is decoded, and anything else is left as it is, so names which don't
+/// contain a `%` are borrowed.
+pub fn decode_name(name: &str) -> Cow<'_, str> {
+    if !name.contains('%') {
+        return Cow::Borrowed(name);
+    }
+
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(i) = rest.find('%') {
+        decoded.push_str(&rest[..i]);
+        let ch = rest
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(char::from)
+            .filter(|&ch| needs_encoding(ch));
+        match ch {
+            Some(ch) => {
+                decoded.push(ch);
+                rest = &rest[i + 3..];
+            }
+            None => {
+                decoded.push('%');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+/// A `Type` displayed as a URI with a custom scheme
+pub struct Uri<'t, 'a> {
+    scheme: &'static str,
+    ty: &'t Type<'a>,
+    unknown_length_slash: bool,
+}
+
+impl Uri<'_, '_> {
+    /// Whether to end the URIs of seqs and maps of unknown length with a slash, e.g. `seq/`
+    /// rather than `seq`, which is the default
+    ///
+    /// Both forms are parsed the same.
+    pub fn unknown_length_slash(mut self, slash: bool) -> Self {
+        self.unknown_length_slash = slash;
+        self
+    }
+}
+
+impl fmt::Display for Uri<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://", self.scheme)?;
+        match self.ty {
+            Type::Seq(None) if !self.unknown_length_slash => f.pad("seq"),
+            Type::Map(None) if !self.unknown_length_slash => f.pad("map"),
+            ty => ty.fmt_path(f),
+        }
+    }
+}
+
+/// A `Type` displayed without its scheme, e.g. `seq/3`
+pub struct Path<'t, 'a>(&'t Type<'a>);
+
+impl fmt::Display for Path<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt_path(f)
+    }
 }
 
 impl fmt::Display for Type<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.with_scheme(DEFAULT_SCHEME).fmt(f)
+    }
+}
+
+impl Type<'_> {
+    /// Display this type as a URI with the given scheme instead of `serde`
+    pub fn with_scheme(&self, scheme: &'static str) -> Uri<'_, '_> {
+        Uri {
+            scheme,
+            ty: self,
+            unknown_length_slash: true,
+        }
+    }
+
+    /// Whether this is a boolean or a number, whose links may have a different text than their
+    /// value, which then follows the URI, e.g. `[1,000](serde://u16#1000)`
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Type::Bool
+                | Type::I8
+                | Type::I16
+                | Type::I32
+                | Type::I64
+                | Type::I128
+                | Type::U8
+                | Type::U16
+                | Type::U32
+                | Type::U64
+                | Type::U128
+                | Type::F32
+                | Type::F64
+        )
+    }
+
+    pub fn path(&self) -> Path<'_, '_> {
+        Path(self)
+    }
+
+    /// Write out everything after the `scheme://` part of the URI
+    fn fmt_path(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Type::Bool => f.pad("serde://bool"),
-            Type::I8 => f.pad("serde://i8"),
-            Type::I16 => f.pad("serde://i16"),
-            Type::I32 => f.pad("serde://i32"),
-            Type::I64 => f.pad("serde://i64"),
-            Type::I128 => f.pad("serde://i128"),
-            Type::U8 => f.pad("serde://u8"),
-            Type::U16 => f.pad("serde://u16"),
-            Type::U32 => f.pad("serde://u32"),
-            Type::U64 => f.pad("serde://u64"),
-            Type::U128 => f.pad("serde://u128"),
-            Type::F32 => f.pad("serde://f32"),
-            Type::F64 => f.pad("serde://f64"),
-            Type::Char => f.pad("serde://char"),
-            Type::String => f.pad("serde://string"),
-            Type::Bytes => f.pad("serde://bytes"),
-            Type::None => f.pad("serde://none"),
-            Type::Some => f.pad("serde://some"),
-            Type::Unit => f.pad("serde://unit"),
-            Type::UnitStruct(name) => write!(f, "serde://unit_struct/{}", name),
+            Type::Bool => f.pad("bool"),
+            Type::I8 => f.pad("i8"),
+            Type::I16 => f.pad("i16"),
+            Type::I32 => f.pad("i32"),
+            Type::I64 => f.pad("i64"),
+            Type::I128 => f.pad("i128"),
+            Type::U8 => f.pad("u8"),
+            Type::U16 => f.pad("u16"),
+            Type::U32 => f.pad("u32"),
+            Type::U64 => f.pad("u64"),
+            Type::U128 => f.pad("u128"),
+            Type::F32 => f.pad("f32"),
+            Type::F64 => f.pad("f64"),
+            Type::Char => f.pad("char"),
+            Type::String => f.pad("string"),
+            Type::BigNum => f.pad("bignum"),
+            Type::Decimal => f.pad("decimal"),
+            Type::Bytes => f.pad("bytes"),
+            Type::HexBytes => f.pad("bytes/hex"),
+            Type::RawBytes => f.pad("raw_bytes"),
+            Type::None => f.pad("none"),
+            Type::Some => f.pad("some"),
+            Type::Unit => f.pad("unit"),
+            Type::UnitStruct(name) => write!(f, "unit_struct/{}", Name(name)),
             Type::UnitVariant(name, variant) => {
-                write!(f, "serde://unit_variant/{}/{}", name, variant)
+                write!(f, "unit_variant/{}/{}", Name(name), Name(variant))
             }
-            Type::NewtypeStruct(name) => write!(f, "serde://newtype_struct/{}", name),
+            Type::NewtypeStruct(name) => write!(f, "newtype_struct/{}", Name(name)),
             Type::NewtypeVariant(name, variant) => {
-                write!(f, "serde://newtype_variant/{}/{}", name, variant)
+                write!(f, "newtype_variant/{}/{}", Name(name), Name(variant))
             }
-            Type::Seq(Some(len)) => write!(f, "serde://seq/{}", len),
-            Type::Seq(None) => f.pad("serde://seq/"),
-            Type::Tuple(len) => write!(f, "serde://tuple/{}", len),
-            Type::TupleStruct(name, len) => write!(f, "serde://tuple_struct/{}/{}", name, len),
+            Type::Seq(Some(len)) => write!(f, "seq/{}", len),
+            Type::Seq(None) => f.pad("seq/"),
+            Type::Tuple(len) => write!(f, "tuple/{}", len),
+            Type::TupleStruct(name, len) => write!(f, "tuple_struct/{}/{}", Name(name), len),
             Type::TupleVariant(name, variant, len) => {
-                write!(f, "serde://tuple_variant/{}/{}/{}", name, variant, len)
+                write!(f, "tuple_variant/{}/{}/{}", Name(name), Name(variant), len)
             }
-            Type::Map(Some(len)) => write!(f, "serde://map/{}", len),
-            Type::Map(None) => f.pad("serde://map/"),
-            Type::Struct(name, fields) => write!(f, "serde://struct/{}/{}", name, fields),
+            Type::Map(Some(len)) => write!(f, "map/{}", len),
+            Type::Map(None) => f.pad("map/"),
+            Type::Struct(name, fields) => write!(f, "struct/{}/{}", Name(name), fields),
             Type::StructVariant(name, variant, fields) => {
-                write!(f, "serde://struct_variant/{}/{}/{}", name, variant, fields)
+                write!(
+                    f,
+                    "struct_variant/{}/{}/{}",
+                    Name(name),
+                    Name(variant),
+                    fields
+                )
             }
+            Type::Summary => f.pad("summary"),
+            Type::ByteChunks(len) => write!(f, "byte_chunks/{}", len),
         }
     }
 }
 
 impl<'a> Type<'a> {
-    pub fn from_str(s: &'a str) -> Result<Self, ParseError> {
-        if !s.starts_with("serde://") {
-            return Err(ParseError::UnknownSchema);
-        }
-        let s = &s["serde://".len()..];
+    /// Parse a type URI which uses the given scheme, usually `DEFAULT_SCHEME`
+    pub fn from_str(s: &'a str, scheme: &'static str) -> Result<Self, ParseError> {
+        let s = s
+            .strip_prefix(scheme)
+            .and_then(|s| s.strip_prefix("://"))
+            .ok_or(ParseError::UnknownSchema(scheme))?;
+        Self::from_path(s)
+    }
 
+    /// Parse the part of a type URI after the scheme, e.g. `seq/3` for `serde://seq/3`
+    pub fn from_path(s: &'a str) -> Result<Self, ParseError> {
         let mut parts = s.split('/');
 
         let domain = parts.next().ok_or(ParseError::MissingDomain)?;
 
-        fn fragment<'a>(parts: &mut std::str::Split<'a, char>) -> Result<&'a str, ParseError> {
+        fn fragment<'a>(parts: &mut core::str::Split<'a, char>) -> Result<&'a str, ParseError> {
             parts.next().ok_or(ParseError::MissingPathFragment)
         }
 
-        fn opt_len(parts: &mut std::str::Split<'_, char>) -> Result<Option<usize>, ParseError> {
-            match parts.next() {
+        fn len(parts: &mut core::str::Split<'_, char>) -> Result<usize, ParseError> {
+            let s = fragment(parts)?;
+            s.parse()
+                .map_err(|error: core::num::ParseIntError| match error.kind() {
+                    // This also catches lengths which were fine on the 64-bit machine that wrote them
+                    core::num::IntErrorKind::PosOverflow => {
+                        ParseError::LengthTooLarge(s.to_owned())
+                    }
+                    _ => error.into(),
+                })
+        }
+
+        fn opt_len(parts: &mut core::str::Split<'_, char>) -> Result<Option<usize>, ParseError> {
+            match parts.clone().next() {
                 Some("") | None => Ok(None),
-                Some(s) => Ok(Some(s.parse()?)),
+                Some(_) => Ok(Some(len(parts)?)),
             }
         }
 
@@ -137,7 +323,14 @@ impl<'a> Type<'a> {
             "f64" => Type::F64,
             "char" => Type::Char,
             "string" => Type::String,
-            "bytes" => Type::Bytes,
+            "bignum" => Type::BigNum,
+            "decimal" => Type::Decimal,
+            "bytes" => match parts.next() {
+                Some("hex") => Type::HexBytes,
+                Some(_) => return Err(ParseError::UnknownType),
+                None => Type::Bytes,
+            },
+            "raw_bytes" => Type::RawBytes,
             "none" => Type::None,
             "some" => Type::Some,
             "unit" => Type::Unit,
@@ -146,22 +339,22 @@ impl<'a> Type<'a> {
             "newtype_struct" => Type::NewtypeStruct(fragment(&mut parts)?),
             "newtype_variant" => Type::NewtypeVariant(fragment(&mut parts)?, fragment(&mut parts)?),
             "seq" => Type::Seq(opt_len(&mut parts)?),
-            "tuple" => Type::Tuple(fragment(&mut parts)?.parse()?),
-            "tuple_struct" => {
-                Type::TupleStruct(fragment(&mut parts)?, fragment(&mut parts)?.parse()?)
-            }
+            "tuple" => Type::Tuple(len(&mut parts)?),
+            "tuple_struct" => Type::TupleStruct(fragment(&mut parts)?, len(&mut parts)?),
             "tuple_variant" => Type::TupleVariant(
                 fragment(&mut parts)?,
                 fragment(&mut parts)?,
-                fragment(&mut parts)?.parse()?,
+                len(&mut parts)?,
             ),
             "map" => Type::Map(opt_len(&mut parts)?),
-            "struct" => Type::Struct(fragment(&mut parts)?, fragment(&mut parts)?.parse()?),
+            "struct" => Type::Struct(fragment(&mut parts)?, len(&mut parts)?),
             "struct_variant" => Type::StructVariant(
                 fragment(&mut parts)?,
                 fragment(&mut parts)?,
-                fragment(&mut parts)?.parse()?,
+                len(&mut parts)?,
             ),
+            "summary" => Type::Summary,
+            "byte_chunks" => Type::ByteChunks(len(&mut parts)?),
             _ => return Err(ParseError::UnknownType),
         })
     }
@@ -180,7 +373,7 @@ mod tests {
                 fn $name($($param in $strategy),+) {
                     let ty = $expr;
                     let repr = format!("{}", ty);
-                    prop_assert_eq!(Type::from_str(&repr).unwrap(), ty)
+                    prop_assert_eq!(Type::from_str(&repr, DEFAULT_SCHEME).unwrap(), ty)
                 }
             }
         };
@@ -189,12 +382,13 @@ mod tests {
             #[test]
             fn $name() {
                 let ty = $expr;
-                assert_eq!(Type::from_str(&format!("{}", ty)).unwrap(), ty)
+                assert_eq!(Type::from_str(&format!("{}", ty), DEFAULT_SCHEME).unwrap(), ty)
             }
         }
     }
 
-    const RE: &str = "[^/]+";
+    // Names which don't need percent-encoding, and as such are parsed back as they are
+    const RE: &str = "[^/%<>\\x00-\\x1F\\x7F]+";
     roundtrip! { test_bool: [] => Type::Bool }
     roundtrip! { test_i8: [] => Type::I8 }
     roundtrip! { test_i16: [] => Type::I16 }
@@ -210,7 +404,11 @@ mod tests {
     roundtrip! { test_f64: [] => Type::F64 }
     roundtrip! { test_char: [] => Type::Char }
     roundtrip! { test_string: [] => Type::String }
+    roundtrip! { test_bignum: [] => Type::BigNum }
+    roundtrip! { test_decimal: [] => Type::Decimal }
     roundtrip! { test_bytes: [] => Type::Bytes }
+    roundtrip! { test_hex_bytes: [] => Type::HexBytes }
+    roundtrip! { test_raw_bytes: [] => Type::RawBytes }
     roundtrip! { test_none: [] => Type::None }
     roundtrip! { test_some: [] => Type::Some }
     roundtrip! { test_unit: [] => Type::Unit }
@@ -224,5 +422,76 @@ mod tests {
     roundtrip! { test_tuple_variant: [name in RE, variant in RE, len in any::<usize>()] => Type::TupleVariant(&name, &variant, len) }
     roundtrip! { test_map: [len in prop::option::of(any::<usize>())] => Type::Map(len) }
     roundtrip! { test_struct: [name in RE, fields in any::<usize>()] => Type::Struct(&name, fields) }
+    roundtrip! { test_summary: [] => Type::Summary }
+    roundtrip! { test_byte_chunks: [len in any::<usize>()] => Type::ByteChunks(len) }
     roundtrip! { test_struct_variant: [name in RE, variant in RE, fields in any::<usize>()] => Type::StructVariant(&name, &variant, fields) }
+
+    proptest! {
+        #[test]
+        fn test_name_encoding(name in any::<String>()) {
+            let encoded = Name(&name).to_string();
+            prop_assert!(!encoded.contains('/'));
+            prop_assert_eq!(decode_name(&encoded), name.as_str());
+        }
+    }
+
+    #[test]
+    fn test_encoded_names() {
+        let ty = Type::UnitVariant("io/Kind", "Mod::Variant");
+        let uri = ty.to_string();
+        assert_eq!(uri, "serde://unit_variant/io%2FKind/Mod::Variant");
+
+        match Type::from_str(&uri, DEFAULT_SCHEME).unwrap() {
+            Type::UnitVariant(name, variant) => {
+                assert_eq!(decode_name(name), "io/Kind");
+                assert_eq!(decode_name(variant), "Mod::Variant");
+            }
+            ty => panic!("unexpected type {:?}", ty),
+        }
+
+        // Percent signs which don't start anything we'd have encoded are left alone
+        assert_eq!(decode_name("100%"), "100%");
+        assert_eq!(decode_name("%41%2f%zz"), "%41/%zz");
+    }
+
+    #[test]
+    fn test_length_too_large() {
+        let huge = "99999999999999999999";
+        for uri in &[
+            format!("serde://seq/{}", huge),
+            format!("serde://map/{}", huge),
+            format!("serde://tuple/{}", huge),
+            format!("serde://struct/Name/{}", huge),
+            format!("serde://byte_chunks/{}", huge),
+        ] {
+            match Type::from_str(uri, DEFAULT_SCHEME) {
+                Err(ParseError::LengthTooLarge(len)) => assert_eq!(len, huge),
+                result => panic!("unexpected result {:?} for {}", result, uri),
+            }
+        }
+
+        assert!(matches!(
+            Type::from_str("serde://seq/-1", DEFAULT_SCHEME),
+            Err(ParseError::IntParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_length_slash() {
+        for (ty, path) in [(Type::Seq(None), "seq"), (Type::Map(None), "map")] {
+            for uri in [format!("serde://{}", path), format!("serde://{}/", path)] {
+                assert_eq!(Type::from_str(&uri, DEFAULT_SCHEME).unwrap(), ty);
+            }
+
+            let slashless = ty.with_scheme(DEFAULT_SCHEME).unknown_length_slash(false);
+            assert_eq!(slashless.to_string(), format!("serde://{}", path));
+            assert_eq!(ty.to_string(), format!("serde://{}/", path));
+        }
+
+        let known = Type::Seq(Some(3)).with_scheme(DEFAULT_SCHEME);
+        assert_eq!(
+            known.unknown_length_slash(false).to_string(),
+            "serde://seq/3"
+        );
+    }
 }
@@ -0,0 +1,113 @@
+//! Round-trips through `md::Writer` and `md::Reader` alone, without serde
+use std::borrow::Cow;
+
+use serde_mml::md::{FmtOutput, Item, List, Reader, Writer};
+
+/// What to write, in a shape which maps directly onto `Writer`'s calls
+#[derive(Debug, Clone)]
+enum Node {
+    Link(&'static str, &'static str),
+    Ordered(Vec<Node>),
+    Unordered(Vec<Node>),
+}
+
+fn write(writer: &mut Writer<FmtOutput<String>>, list: Option<&mut List>, node: &Node) {
+    match node {
+        Node::Link(text, uri) => writer.link(list, text, uri).unwrap(),
+        Node::Ordered(children) => {
+            let mut sublist = writer.ordered_list(list).unwrap();
+            for child in children {
+                write(writer, Some(&mut sublist), child);
+            }
+        }
+        Node::Unordered(children) => {
+            let mut sublist = writer.unordered_list(list).unwrap();
+            for child in children {
+                write(writer, Some(&mut sublist), child);
+            }
+        }
+    }
+}
+
+/// The items `Reader` should produce for a node
+fn expected(node: &Node, items: &mut Vec<Item<'static>>) {
+    match node {
+        Node::Link(text, uri) => items.push(Item::Link {
+            text: Cow::Borrowed(*text),
+            uri,
+        }),
+        Node::Ordered(children) | Node::Unordered(children) => {
+            items.push(match node {
+                Node::Ordered(_) => Item::PushOrderedList,
+                _ => Item::PushUnorderedList,
+            });
+            for child in children {
+                expected(child, items);
+            }
+            items.push(Item::PopList);
+        }
+    }
+}
+
+fn roundtrip(node: &Node) {
+    let mut writer = Writer::new(FmtOutput(String::new()));
+    write(&mut writer, None, node);
+    let doc = writer.into_inner().0;
+
+    let items = Reader::new(&doc).collect::<Result<Vec<_>, _>>().unwrap();
+    let mut want = Vec::new();
+    expected(node, &mut want);
+    assert_eq!(items, want, "{}", doc);
+}
+
+#[test]
+fn test_single_link() {
+    roundtrip(&Node::Link("hello", "https://example.com"));
+    roundtrip(&Node::Link("", "x:"));
+}
+
+#[test]
+fn test_nested_lists() {
+    use Node::*;
+
+    roundtrip(&Unordered(vec![
+        Link("a", "x://a"),
+        Ordered(vec![
+            Link("b", "x://b"),
+            Unordered(vec![Link("c", "x://c"), Ordered(vec![Link("d", "x://d")])]),
+            Link("e", "x://e"),
+        ]),
+        Link("f", "x://f"),
+        Ordered(vec![Link("g", "x://g")]),
+    ]));
+
+    let mut deep = Link("bottom", "x://bottom");
+    for i in 0..50 {
+        deep = if i % 2 == 0 {
+            Ordered(vec![deep])
+        } else {
+            Unordered(vec![Link("top", "x://top"), deep])
+        };
+    }
+    roundtrip(&deep);
+}
+
+#[test]
+fn test_awkward_text() {
+    use Node::*;
+
+    roundtrip(&Ordered(vec![
+        Link("[brackets] and (parens)", "x://1"),
+        Link("back\\slash\\", "x://2"),
+        Link("* 1. - bullets", "x://3"),
+        Link("multiple\nlines\n    * indented", "x://4"),
+        Link("ünïcödé ✓", "x://5"),
+        Link("`code` <angle> ![img](x)", "x://6"),
+    ]));
+}
+
+#[test]
+fn test_many_items() {
+    let items = (0..1000).map(|_| Node::Link("item", "x://item")).collect();
+    roundtrip(&Node::Ordered(items));
+}
@@ -0,0 +1,151 @@
+//! Fields using `#[serde(with = ...)]` and friends, which drive the serializer and deserializer
+//! through call sequences that plain derives don't
+
+use std::fmt;
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_mml::{de, ser};
+
+fn roundtrip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+    let doc = ser::to_string(value).unwrap();
+    de::from_str(&doc).unwrap()
+}
+
+/// Write a value with its `Display` impl, as many `serialize_with` helpers do
+fn display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Read back a value written with `display`
+fn from_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(serde::de::Error::custom)
+}
+
+#[test]
+fn serde_bytes() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Blob {
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        maybe: Option<Vec<u8>>,
+        #[serde(with = "serde_bytes")]
+        empty: Vec<u8>,
+    }
+
+    let blob = Blob {
+        data: (0..=255).collect(),
+        maybe: Some(b"hi".to_vec()),
+        empty: Vec::new(),
+    };
+    let doc = ser::to_string(&blob).unwrap();
+    assert!(doc.contains("](serde://bytes)"));
+    assert_eq!(de::from_str::<Blob>(&doc).unwrap(), blob);
+
+    let blob = Blob {
+        maybe: None,
+        ..blob
+    };
+    assert_eq!(roundtrip(&blob), blob);
+}
+
+#[test]
+fn collect_str() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Host {
+        #[serde(serialize_with = "display", deserialize_with = "from_str")]
+        addr: Ipv4Addr,
+        #[serde(serialize_with = "display", deserialize_with = "from_str")]
+        port: u16,
+        #[serde(serialize_with = "display")]
+        weight: f64,
+        #[serde(serialize_with = "display")]
+        id: u64,
+    }
+
+    let host = Host {
+        addr: Ipv4Addr::new(127, 0, 0, 1),
+        port: 8080,
+        weight: 0.5,
+        id: u64::MAX,
+    };
+    let doc = ser::to_string(&host).unwrap();
+    assert!(doc.contains("[127\\.0\\.0\\.1](serde://string)"));
+    assert!(doc.contains("[8080](serde://string)"));
+
+    // Numbers written as strings can be read back as numbers without a `deserialize_with`
+    assert_eq!(de::from_str::<Host>(&doc).unwrap(), host);
+}
+
+#[test]
+fn with_module() {
+    mod hex {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&format!("{:#x}", value))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+            let text = <&str>::deserialize(deserializer)?;
+            let digits = text.trim_start_matches("0x");
+            u32::from_str_radix(digits, 16).map_err(serde::de::Error::custom)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pixel {
+        #[serde(with = "hex")]
+        color: u32,
+        #[serde(with = "hex")]
+        mask: u32,
+    }
+
+    let pixel = Pixel {
+        color: 0xff00ff,
+        mask: 0,
+    };
+    assert_eq!(roundtrip(&pixel), pixel);
+
+    let err = de::from_str::<Pixel>(
+        &ser::to_string(&("not hex", 1))
+            .unwrap()
+            .replace("tuple/2", "struct/Pixel/2"),
+    );
+    assert!(err.is_err());
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_timestamps() {
+    use chrono::{DateTime, TimeZone, Utc};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        #[serde(with = "chrono::serde::ts_seconds")]
+        at: DateTime<Utc>,
+        #[serde(with = "chrono::serde::ts_milliseconds_option")]
+        until: Option<DateTime<Utc>>,
+    }
+
+    let event = Event {
+        at: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+        until: Some(Utc.timestamp_opt(1_600_000_001, 500_000_000).unwrap()),
+    };
+    let doc = ser::to_string(&event).unwrap();
+    assert!(doc.contains("[1600000000](serde://i64)"));
+    assert_eq!(roundtrip(&event), event);
+
+    let event = Event {
+        until: None,
+        ..event
+    };
+    assert_eq!(roundtrip(&event), event);
+}